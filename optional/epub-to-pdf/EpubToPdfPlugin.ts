@@ -4,15 +4,38 @@
  */
 
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { save } from '@tauri-apps/plugin-dialog';
 import type { Plugin } from '../types';
 import type {
+  CacheStats,
+  ChapterList,
+  ChapterSelection,
   ConversionResult,
   EpubToPdfSettings,
   BatchConversionResult,
   ConverterInfo,
+  ConversionJob,
+  ConversionEvent,
+  ConverterPaths,
+  EpubValidation,
+  LayoutProfile,
+  PdfAnnotations,
+  OutputNaming,
+  ResolvedOutput,
+  SandboxStatus,
+  WatchFolder,
+  WatchFolderStatus,
 } from './types';
 
+const CONVERSION_EVENTS: ConversionEvent[] = [
+  'epub-conversion-queued',
+  'epub-conversion-started',
+  'epub-conversion-progress',
+  'epub-conversion-completed',
+  'epub-conversion-failed',
+];
+
 export const epubToPdfPlugin: Plugin = {
   id: 'epub-to-pdf',
   name: 'EPUB to PDF',
@@ -39,11 +62,32 @@ export const epubToPdfPlugin: Plugin = {
     },
     includeTableOfContents: true,
     preserveImages: true,
+    timeoutSeconds: 1800,
+    linearize: false,
+    pdfA: false,
+    parallelJobs: 0,
+    maxLatexJobs: 2,
+    cacheEnabled: true,
+    cacheMaxMb: 1024,
+    layoutProfile: '',
+    customCss: '',
+    customFonts: [],
+    pandocTemplate: '',
+    pandocPdfEngine: '',
+    sandboxConverters: true,
+    requireNetworkIsolation: false,
+    maxCpuSeconds: 3600,
+    maxOutputMb: 4096,
+    converterPaths: {},
+    watchFolders: [],
+    outputTemplate: '{title}.pdf',
+    collisionPolicy: 'suffix',
   } as EpubToPdfSettings,
 
   // Lifecycle hooks
   onInstall: async () => {
     console.log('[EpubToPdfPlugin] Plugin installed');
+    await applyConverterPaths();
     // Check for available converters
     const info = await checkConverterAvailability();
     console.log('[EpubToPdfPlugin] Converter info:', info);
@@ -55,6 +99,8 @@ export const epubToPdfPlugin: Plugin = {
 
   onEnable: async () => {
     console.log('[EpubToPdfPlugin] Plugin enabled');
+    await applyConverterPaths();
+    await startWatchFolders();
     // Verify converter is still available
     const info = await checkConverterAvailability();
     if (!info.available) {
@@ -66,6 +112,8 @@ export const epubToPdfPlugin: Plugin = {
 
   onDisable: async () => {
     console.log('[EpubToPdfPlugin] Plugin disabled');
+    const watched = await listWatchFolders();
+    await Promise.all(watched.map((w) => stopWatchFolder(w.folder.sourceDir)));
   },
 
   // Actions exposed in the UI
@@ -141,11 +189,11 @@ export const epubToPdfPlugin: Plugin = {
               return { success: false, error: 'Save dialog cancelled' };
             }
 
-            // Convert single book
-            const result = await convertEpubToPdf(
+            // Convert single book with the plugin settings
+            const result = await convertWithSettings(
               book.path,
               outputFolder,
-              converterInfo.converter
+              epubToPdfPlugin.settings as EpubToPdfSettings
             );
 
             return result;
@@ -161,14 +209,15 @@ export const epubToPdfPlugin: Plugin = {
               return { success: false, error: 'Folder selection cancelled' };
             }
 
-            // Convert multiple books
-            const result = await convertMultipleEpubsToPdf(
+            // Queue the books; progress is reported through conversion events
+            const jobs = await enqueueConversions(
               selectedBooks,
               outputFolder,
-              converterInfo.converter
+              converterInfo.converter,
+              epubToPdfPlugin.settings as EpubToPdfSettings
             );
 
-            return result;
+            return { success: jobs.length > 0, queued: jobs.length, jobs };
           }
         } catch (error) {
           console.error('[EpubToPdfPlugin] Conversion failed:', error);
@@ -210,19 +259,103 @@ export async function checkConverterAvailability(): Promise<ConverterInfo> {
   }
 }
 
+/**
+ * List every converter with its version and path, or why it is unavailable;
+ * `refresh` probes again instead of using the cached detection
+ */
+export async function detectConverters(refresh = false): Promise<ConverterInfo[]> {
+  try {
+    return await invoke<ConverterInfo[]>('detect_converters', { refresh });
+  } catch (error) {
+    console.error('[EpubToPdfPlugin] Failed to detect converters:', error);
+    return [];
+  }
+}
+
+/**
+ * Use user-specified converter binaries and detect again
+ */
+export async function setConverterPaths(paths: ConverterPaths): Promise<ConverterInfo[]> {
+  return await invoke<ConverterInfo[]>('set_converter_paths', { paths });
+}
+
+/**
+ * Pass the configured converter paths to the backend
+ */
+async function applyConverterPaths(): Promise<void> {
+  const paths = (epubToPdfPlugin.settings as EpubToPdfSettings).converterPaths;
+  if (paths && (paths.calibre || paths.pandoc)) {
+    try {
+      await setConverterPaths(paths);
+    } catch (error) {
+      console.error('[EpubToPdfPlugin] Failed to set converter paths:', error);
+    }
+  }
+}
+
+/**
+ * Watch the folders from the settings; each keeps its own state file, so books
+ * converted before the restart are not converted again
+ */
+async function startWatchFolders(): Promise<void> {
+  const settings = epubToPdfPlugin.settings as EpubToPdfSettings;
+  for (const folder of settings.watchFolders ?? []) {
+    try {
+      await startWatchFolder(folder, settings);
+    } catch (error) {
+      console.error(`[EpubToPdfPlugin] Failed to watch ${folder.sourceDir}:`, error);
+    }
+  }
+}
+
+/**
+ * Watch a folder: its books are converted now and whenever they are added or modified
+ */
+export async function startWatchFolder(
+  folder: WatchFolder,
+  settings?: EpubToPdfSettings
+): Promise<WatchFolderStatus> {
+  return await invoke<WatchFolderStatus>('start_watch_folder', { folder, settings });
+}
+
+/**
+ * Stop watching a folder; conversions already queued still run
+ */
+export async function stopWatchFolder(sourceDir: string): Promise<void> {
+  await invoke('stop_watch_folder', { sourceDir });
+}
+
+/**
+ * Watched folders with the state of their books
+ */
+export async function listWatchFolders(): Promise<WatchFolderStatus[]> {
+  return await invoke<WatchFolderStatus[]>('list_watch_folders');
+}
+
+/**
+ * Check an EPUB (structure, missing files, DRM) without converting it
+ */
+export async function validateEpub(epubPath: string): Promise<EpubValidation> {
+  return await invoke<EpubValidation>('validate_epub', { epubPath });
+}
+
 /**
  * Convert a single EPUB file to PDF
  */
 export async function convertEpubToPdf(
   epubPath: string,
   pdfPath: string,
-  converter: 'calibre' | 'pandoc' = 'calibre'
+  converter: 'calibre' | 'pandoc' | 'native' = 'calibre',
+  chapters?: ChapterSelection,
+  annotations?: PdfAnnotations
 ): Promise<ConversionResult> {
   try {
     return await invoke<ConversionResult>('convert_epub_to_pdf', {
       epubPath,
       pdfPath,
       converter,
+      chapters,
+      annotations,
     });
   } catch (error) {
     console.error('[EpubToPdfPlugin] Conversion failed:', error);
@@ -234,29 +367,79 @@ export async function convertEpubToPdf(
 }
 
 /**
- * Convert multiple EPUB files to PDF in batch
+ * Convert between any two supported formats (taken from the file extensions);
+ * without a converter the preferred installed one for the pair is used
+ */
+export async function convertEbook(
+  sourcePath: string,
+  targetPath: string,
+  converter?: 'calibre' | 'pandoc' | 'native',
+  settings?: Partial<EpubToPdfSettings>
+): Promise<ConversionResult> {
+  try {
+    return await invoke<ConversionResult>('convert_ebook', {
+      sourcePath,
+      targetPath,
+      converter,
+      settings,
+    });
+  } catch (error) {
+    console.error('[EpubToPdfPlugin] Conversion failed:', error);
+    return {
+      success: false,
+      error: error instanceof Error ? error.message : String(error),
+    };
+  }
+}
+
+/**
+ * Output naming for a folder, using the template and collision policy from the settings
+ */
+function outputNaming(
+  outputFolder: string,
+  settings?: Partial<EpubToPdfSettings>
+): OutputNaming {
+  return {
+    outputDir: outputFolder,
+    template: settings?.outputTemplate || '{title}.pdf',
+    collision: settings?.collisionPolicy || 'suffix',
+  };
+}
+
+/**
+ * Preview where a batch would write its files, without converting anything
+ */
+export async function resolveOutputPaths(
+  epubPaths: string[],
+  naming: OutputNaming
+): Promise<ResolvedOutput[]> {
+  return await invoke<ResolvedOutput[]>('resolve_output_paths', {
+    epubPaths,
+    naming,
+  });
+}
+
+/**
+ * Convert multiple EPUB files to PDF in batch; file names come from the
+ * output template filled with each book's metadata
  */
 export async function convertMultipleEpubsToPdf(
-  books: Array<{ path: string; title?: string }>,
+  books: Array<{ path: string; title?: string; layoutProfile?: string }>,
   outputFolder: string,
-  converter: 'calibre' | 'pandoc' = 'calibre'
+  converter: 'calibre' | 'pandoc' | 'native' = 'calibre',
+  settings?: Partial<EpubToPdfSettings>
 ): Promise<BatchConversionResult> {
   try {
-    // Prepare conversion jobs
-    const jobs = books.map((book) => {
-      const fileName = book.title
-        ? `${book.title}.pdf`
-        : `${book.path.split('/').pop()?.replace('.epub', '.pdf')}`;
-
-      return {
-        epubPath: book.path,
-        pdfPath: `${outputFolder}/${fileName}`,
-      };
-    });
+    const jobs = books.map((book) => ({
+      epubPath: book.path,
+      layoutProfile: book.layoutProfile,
+    }));
 
     return await invoke<BatchConversionResult>('convert_multiple_epub_to_pdf', {
       jobs,
       converter,
+      naming: outputNaming(outputFolder, settings),
+      settings,
     });
   } catch (error) {
     console.error('[EpubToPdfPlugin] Batch conversion failed:', error);
@@ -275,13 +458,149 @@ export async function convertMultipleEpubsToPdf(
   }
 }
 
+/**
+ * Queue EPUB files for background conversion and return the queued jobs;
+ * books skipped by the collision policy are not queued
+ */
+export async function enqueueConversions(
+  books: Array<{ id?: string; path: string; title?: string; layoutProfile?: string }>,
+  outputFolder: string,
+  converter: 'calibre' | 'pandoc' | 'native' = 'calibre',
+  settings?: Partial<EpubToPdfSettings>
+): Promise<ConversionJob[]> {
+  try {
+    const jobs = books.map((book) => ({
+      epubPath: book.path,
+      bookId: book.id,
+      layoutProfile: book.layoutProfile,
+    }));
+
+    return await invoke<ConversionJob[]>('enqueue_epub_conversions', {
+      jobs,
+      converter,
+      settings,
+      naming: outputNaming(outputFolder, settings),
+    });
+  } catch (error) {
+    console.error('[EpubToPdfPlugin] Failed to queue conversions:', error);
+    return [];
+  }
+}
+
+/**
+ * List queued, running and finished conversion jobs
+ */
+export async function listConversionJobs(): Promise<ConversionJob[]> {
+  try {
+    return await invoke<ConversionJob[]>('list_conversion_jobs');
+  } catch (error) {
+    console.error('[EpubToPdfPlugin] Failed to list conversion jobs:', error);
+    return [];
+  }
+}
+
+/**
+ * Cancel a pending or running conversion job; a running converter is killed
+ * together with its child processes and the job ends up 'cancelled'
+ */
+export async function cancelConversionJob(jobId: string): Promise<ConversionJob> {
+  return await invoke<ConversionJob>('cancel_conversion_job', { jobId });
+}
+
+/**
+ * Queue a failed or cancelled conversion job again
+ */
+export async function retryConversionJob(jobId: string): Promise<ConversionJob> {
+  return await invoke<ConversionJob>('retry_conversion_job', { jobId });
+}
+
+/**
+ * Remove completed, failed and cancelled jobs from the queue; returns how many were removed
+ */
+export async function clearFinishedConversionJobs(): Promise<number> {
+  return await invoke<number>('clear_finished_conversion_jobs');
+}
+
+/**
+ * List the spine documents and table of contents entries of an EPUB,
+ * to pick the chapters passed to convertEpubToPdf / convertWithSettings
+ */
+export async function listEpubChapters(epubPath: string): Promise<ChapterList> {
+  return await invoke<ChapterList>('list_epub_chapters', { epubPath });
+}
+
+/**
+ * List the builtin and user layout profiles
+ */
+export async function listLayoutProfiles(): Promise<LayoutProfile[]> {
+  return await invoke<LayoutProfile[]>('list_layout_profiles');
+}
+
+/**
+ * Add or replace a user layout profile; returns every profile
+ */
+export async function saveLayoutProfile(profile: LayoutProfile): Promise<LayoutProfile[]> {
+  return await invoke<LayoutProfile[]>('save_layout_profile', { profile });
+}
+
+/**
+ * Remove a user layout profile; returns every profile
+ */
+export async function deleteLayoutProfile(id: string): Promise<LayoutProfile[]> {
+  return await invoke<LayoutProfile[]>('delete_layout_profile', { id });
+}
+
+/**
+ * Number and total size of the cached conversions
+ */
+export async function getConversionCacheStats(): Promise<CacheStats> {
+  return await invoke<CacheStats>('get_conversion_cache_stats');
+}
+
+/**
+ * Delete every cached conversion and return what was removed
+ */
+export async function clearConversionCache(): Promise<CacheStats> {
+  return await invoke<CacheStats>('clear_conversion_cache');
+}
+
+/**
+ * Full output of a failed converter run, from a diagnostic's logPath
+ */
+export async function getConversionLog(logPath: string): Promise<string> {
+  return await invoke<string>('get_conversion_log', { logPath });
+}
+
+/**
+ * Protections Calibre and Pandoc run with on this system
+ */
+export async function checkConverterSandbox(): Promise<SandboxStatus> {
+  return await invoke<SandboxStatus>('check_converter_sandbox');
+}
+
+/**
+ * Subscribe to conversion job events; call the returned function to unsubscribe
+ */
+export async function onConversionEvent(
+  callback: (event: ConversionEvent, job: ConversionJob) => void
+): Promise<UnlistenFn> {
+  const unlisteners = await Promise.all(
+    CONVERSION_EVENTS.map((name) =>
+      listen<ConversionJob>(name, (event) => callback(name, event.payload))
+    )
+  );
+  return () => unlisteners.forEach((unlisten) => unlisten());
+}
+
 /**
  * Helper function to convert EPUB to PDF with custom settings
  */
 export async function convertWithSettings(
   epubPath: string,
   pdfPath: string,
-  settings: Partial<EpubToPdfSettings>
+  settings: Partial<EpubToPdfSettings>,
+  chapters?: ChapterSelection,
+  annotations?: PdfAnnotations
 ): Promise<ConversionResult> {
   try {
     const converterInfo = await checkConverterAvailability();
@@ -298,6 +617,8 @@ export async function convertWithSettings(
       pdfPath,
       converter: converterInfo.converter,
       settings,
+      chapters,
+      annotations,
     });
   } catch (error) {
     return {
@@ -0,0 +1,346 @@
+/// Conversion Result Cache
+/// Keeps converted files in a content-addressed cache. The key hashes the source
+/// file, the converter and its version, the target format and the settings that
+/// change the output, so converting an unchanged book again is a file copy.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/conversion_cache.rs (needs converter_registry.rs)
+/// 2. Add `mod conversion_cache;` to src-tauri/src/main.rs
+/// 3. Add `sha2 = "0.10"` to src-tauri/Cargo.toml
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tauri::command;
+
+use super::converter_registry;
+use super::ebook_formats::EbookFormat;
+use super::epub_converter::EpubToPdfSettings;
+use super::layout_profiles;
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    used: SystemTime, // Modification time, refreshed on every hit
+}
+
+// ============================================================================
+// Location
+// ============================================================================
+
+/// Per-user cache folder of the platform
+pub fn cache_dir() -> PathBuf {
+    let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        var("LOCALAPPDATA").map(|p| p.join("Stomy"))
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|p| p.join("Library/Caches/Stomy"))
+    } else {
+        var("XDG_CACHE_HOME")
+            .or_else(|| var("HOME").map(|p| p.join(".cache")))
+            .map(|p| p.join("stomy"))
+    };
+    base.unwrap_or_else(std::env::temp_dir).join("conversion-cache")
+}
+
+fn entry_path(dir: &Path, key: &str, extension: &str) -> PathBuf {
+    dir.join(&key[..2]).join(format!("{}.{}", key, extension))
+}
+
+/// Cached files, two levels deep; half-written files are skipped
+fn list_entries(dir: &Path) -> Vec<CacheEntry> {
+    let read = |dir: &Path| std::fs::read_dir(dir).into_iter().flatten().flatten();
+    let mut entries = Vec::new();
+    for shard in read(dir).filter(|e| e.path().is_dir()) {
+        for file in read(&shard.path()) {
+            let path = file.path();
+            if path.extension().is_some_and(|e| e == "tmp") {
+                continue;
+            }
+            let Ok(metadata) = file.metadata() else {
+                continue;
+            };
+            entries.push(CacheEntry {
+                path,
+                size: metadata.len(),
+                used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+    entries
+}
+
+// ============================================================================
+// Keys
+// ============================================================================
+
+/// Version part of the key: external converters by their detected version,
+/// the native renderer by the app version it ships with
+fn converter_version(converter: &str) -> String {
+    if converter == "native" {
+        return env!("CARGO_PKG_VERSION").to_string();
+    }
+    converter_registry::converters(false)
+        .into_iter()
+        .find(|c| c.converter == converter)
+        .and_then(|c| c.version)
+        .unwrap_or_default()
+}
+
+/// The settings that change the converted file; timeouts, parallelism and the
+/// cache options do not. Only PDF output depends on them.
+fn normalized_settings(settings: &EpubToPdfSettings, target: EbookFormat) -> String {
+    if target != EbookFormat::Pdf {
+        return String::new();
+    }
+    // User profiles can be edited, so the key covers the profile's values, not its id
+    let profile = layout_profiles::selected_profile(settings)
+        .ok()
+        .flatten()
+        .and_then(|p| serde_json::to_string(&p).ok())
+        .unwrap_or_default();
+    // Same for the stylesheet, fonts and template: their contents, not their paths
+    let style: Vec<String> = [&settings.custom_css, &settings.pandoc_template]
+        .into_iter()
+        .chain(&settings.custom_fonts)
+        .filter(|path| !path.is_empty())
+        .map(|path| file_digest(Path::new(path)).map(|h| hex(&h.finalize())).unwrap_or_default())
+        .collect();
+    let m = &settings.margins;
+    format!(
+        "page={};margins={:.1},{:.1},{:.1},{:.1};compression={};toc={};images={};linearize={};pdfa={};profile={};style={}",
+        settings.page_size.to_lowercase(),
+        m.top,
+        m.bottom,
+        m.left,
+        m.right,
+        settings.compression_level.to_lowercase(),
+        settings.include_table_of_contents,
+        settings.preserve_images,
+        settings.linearize,
+        settings.pdf_a,
+        profile,
+        style.join(",")
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of a file's contents, streamed
+fn file_digest(path: &Path) -> Result<Sha256, String> {
+    let mut hasher = Sha256::new();
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Failed to hash {}: {}", path.display(), e))?;
+    Ok(hasher)
+}
+
+/// SHA-256 over the source file, converter, version, target format, settings and PDF engine
+pub fn cache_key(
+    source: &Path,
+    converter: &str,
+    target: EbookFormat,
+    settings: &EpubToPdfSettings,
+) -> Result<String, String> {
+    let mut hasher = file_digest(source)?;
+
+    // Pandoc PDFs depend on the engine, which can change when another one is installed
+    let engine = match (converter, target) {
+        ("pandoc", EbookFormat::Pdf) => converter_registry::pdf_engine(&settings.pandoc_pdf_engine)
+            .map(|e| e.name)
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    let context = format!(
+        "\0{}\0{}\0{}\0{}\0{}",
+        converter,
+        converter_version(converter),
+        target.extension(),
+        normalized_settings(settings, target),
+        engine
+    );
+    hasher.update(context.as_bytes());
+    Ok(hex(&hasher.finalize()))
+}
+
+// ============================================================================
+// Lookup and Storage
+// ============================================================================
+
+/// Cached file for a key, marked as recently used
+pub fn lookup(dir: &Path, key: &str, extension: &str) -> Option<PathBuf> {
+    let path = entry_path(dir, key, extension);
+    let file = File::options().write(true).open(&path).ok()?;
+    // Eviction drops the least recently used entries first
+    let _ = file.set_modified(SystemTime::now());
+    Some(path)
+}
+
+/// Copy a converted file into the cache, then evict old entries beyond `max_bytes`
+pub fn store(dir: &Path, key: &str, extension: &str, converted: &Path, max_bytes: u64) -> Result<(), String> {
+    let path = entry_path(dir, key, extension);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create cache folder: {}", e))?;
+    }
+
+    // Parallel jobs may store the same key; each writes its own temporary file
+    let temp = path.with_extension(format!("{}.{:?}.tmp", extension, std::thread::current().id()));
+    std::fs::copy(converted, &temp)
+        .and_then(|_| std::fs::rename(&temp, &path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp);
+            format!("Failed to store converted file in cache: {}", e)
+        })?;
+
+    evict(dir, max_bytes);
+    Ok(())
+}
+
+/// Remove the least recently used entries until the cache fits in `max_bytes`
+fn evict(dir: &Path, max_bytes: u64) -> CacheStats {
+    let mut entries = list_entries(dir);
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    entries.sort_by_key(|e| e.used);
+
+    let mut removed = CacheStats::default();
+    for entry in entries {
+        if total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(&entry.path).is_ok() {
+            total -= entry.size;
+            removed.entries += 1;
+            removed.bytes += entry.size;
+        }
+    }
+    removed
+}
+
+fn stats(dir: &Path) -> CacheStats {
+    let entries = list_entries(dir);
+    CacheStats {
+        entries: entries.len(),
+        bytes: entries.iter().map(|e| e.size).sum(),
+    }
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Number and total size of the cached conversions
+#[command]
+pub fn get_conversion_cache_stats() -> CacheStats {
+    stats(&cache_dir())
+}
+
+/// Delete every cached conversion; returns what was removed
+#[command]
+pub fn clear_conversion_cache() -> Result<CacheStats, String> {
+    let dir = cache_dir();
+    let removed = stats(&dir);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear conversion cache: {}", e))?;
+    }
+    Ok(removed)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let dir = std::env::temp_dir().join(format!("stomy-cache-key-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let book = dir.join("dune.epub");
+        std::fs::write(&book, "book").unwrap();
+
+        let settings = EpubToPdfSettings::default();
+        let key = cache_key(&book, "native", EbookFormat::Pdf, &settings).unwrap();
+        assert_eq!(key.len(), 64);
+
+        // Options that do not change the PDF share the entry
+        let slower = EpubToPdfSettings {
+            timeout_seconds: 10,
+            parallel_jobs: 8,
+            ..settings.clone()
+        };
+        assert_eq!(cache_key(&book, "native", EbookFormat::Pdf, &slower).unwrap(), key);
+
+        let letter = EpubToPdfSettings {
+            page_size: "Letter".to_string(),
+            ..settings.clone()
+        };
+        assert_ne!(cache_key(&book, "native", EbookFormat::Pdf, &letter).unwrap(), key);
+        assert_ne!(cache_key(&book, "native", EbookFormat::Txt, &settings).unwrap(), key);
+        assert_eq!(
+            cache_key(&book, "native", EbookFormat::Txt, &settings).unwrap(),
+            cache_key(&book, "native", EbookFormat::Txt, &letter).unwrap()
+        );
+
+        // A stylesheet counts by its contents
+        let css = dir.join("brand.css");
+        std::fs::write(&css, "p { color: navy }").unwrap();
+        let styled = EpubToPdfSettings {
+            custom_css: css.to_string_lossy().to_string(),
+            ..settings.clone()
+        };
+        let styled_key = cache_key(&book, "native", EbookFormat::Pdf, &styled).unwrap();
+        assert_ne!(styled_key, key);
+        std::fs::write(&css, "p { color: teal }").unwrap();
+        assert_ne!(cache_key(&book, "native", EbookFormat::Pdf, &styled).unwrap(), styled_key);
+
+        std::fs::write(&book, "book, second edition").unwrap();
+        assert_ne!(cache_key(&book, "native", EbookFormat::Pdf, &settings).unwrap(), key);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_and_evict() {
+        let root = std::env::temp_dir().join(format!("stomy-cache-{}", std::process::id()));
+        let dir = root.join("cache");
+        std::fs::create_dir_all(&root).unwrap();
+        let converted = root.join("dune.pdf");
+        std::fs::write(&converted, vec![b'x'; 100]).unwrap();
+
+        let keys: Vec<String> = ["aa", "bb", "cc"].iter().map(|k| k.repeat(32)).collect();
+        assert_eq!(lookup(&dir, &keys[0], "pdf"), None);
+        for key in &keys[..2] {
+            store(&dir, key, "pdf", &converted, 1000).unwrap();
+        }
+        assert_eq!(stats(&dir), CacheStats { entries: 2, bytes: 200 });
+
+        // The first entry is used again, so the second one is evicted
+        let old = SystemTime::now() - std::time::Duration::from_secs(60);
+        for key in &keys[..2] {
+            File::options().write(true).open(entry_path(&dir, key, "pdf")).unwrap().set_modified(old).unwrap();
+        }
+        assert!(lookup(&dir, &keys[0], "pdf").is_some());
+        store(&dir, &keys[2], "pdf", &converted, 250).unwrap();
+        assert!(entry_path(&dir, &keys[0], "pdf").exists());
+        assert!(!entry_path(&dir, &keys[1], "pdf").exists());
+        assert_eq!(stats(&dir).entries, 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
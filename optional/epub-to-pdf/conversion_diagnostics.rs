@@ -0,0 +1,320 @@
+/// Converter Diagnostics
+/// Turns the output of a failed Calibre, Pandoc or LaTeX run into an error code,
+/// a short message and a suggested fix. The full log is saved next to the
+/// conversion cache so it can be attached to a bug report.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/conversion_diagnostics.rs (needs conversion_cache.rs)
+/// 2. Add `mod conversion_diagnostics;` to src-tauri/src/main.rs
+/// 3. Register the command in the invoke_handler
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use tauri::command;
+
+use super::conversion_cache;
+use super::epub_validator::ValidationIssue;
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionDiagnostic {
+    pub code: String, // "latex-engine-missing", "font-missing", "drm-protected", ...
+    pub message: String,
+    pub suggestion: String,
+    pub detail: Option<String>,   // Log line the diagnosis is based on
+    pub log_path: Option<String>, // Full converter output, see get_conversion_log
+}
+
+/// A known failure: its code, message and fix, and the log lines that reveal it
+struct Rule {
+    code: &'static str,
+    message: &'static str,
+    suggestion: &'static str,
+    matches: fn(&str) -> bool, // Called with a lowercased log line
+}
+
+/// Checked in order; the first rule with a matching line wins
+const RULES: &[Rule] = &[
+    Rule {
+        code: "disk-full",
+        message: "The disk ran out of space during the conversion",
+        suggestion: "Free some disk space or choose an output folder on another drive, then convert again",
+        matches: |line| {
+            ["no space left on device", "errno 28", "os error 28", "not enough space on the disk", "disk full"]
+                .iter()
+                .any(|p| line.contains(p))
+        },
+    },
+    Rule {
+        code: "drm-protected",
+        message: "The book is protected by DRM and cannot be converted",
+        suggestion: "Convert a DRM-free copy of the book, for example the one from the store's download page",
+        matches: |line| {
+            ["drmerror", "drm-protected", "locked with drm", "is drm protected", "encrypted with drm"]
+                .iter()
+                .any(|p| line.contains(p))
+        },
+    },
+    Rule {
+        code: "latex-engine-missing",
+        message: "Pandoc needs a PDF engine such as LaTeX and none was found",
+        suggestion: "Install TeX Live, MiKTeX, Tectonic, WeasyPrint or wkhtmltopdf, \
+                     or convert with Calibre or the native converter",
+        // Only a missing executable: LaTeX's own "not found" errors are about packages and fonts
+        matches: |line| {
+            line.contains("select a different --pdf-engine")
+                || line.contains("no pdf engine found")
+                || line.contains("pdf engine not found")
+                || (line.contains("latex")
+                    && ["could not find executable", "command not found", "is not recognized"]
+                        .iter()
+                        .any(|p| line.contains(p)))
+        },
+    },
+    Rule {
+        code: "latex-package-missing",
+        message: "A LaTeX package needed by the PDF engine is not installed",
+        suggestion: "Install the package with your TeX distribution (tlmgr install, MiKTeX Console), \
+                     or convert with Calibre or the native converter",
+        matches: |line| line.contains(".sty' not found") || line.contains(".cls' not found"),
+    },
+    Rule {
+        code: "font-missing",
+        message: "A font used by the book or the settings is not installed",
+        suggestion: "Install the font or add it to the custom fonts in the settings, \
+                     or convert with the native converter",
+        matches: |line| {
+            line.contains("not loadable")
+                || line.contains("tfm) file not found")
+                || (line.contains("font")
+                    && ["cannot be found", "not found", "could not find", "failed to load"]
+                        .iter()
+                        .any(|p| line.contains(p)))
+        },
+    },
+    Rule {
+        code: "malformed-xhtml",
+        message: "The book contains XHTML the converter cannot parse",
+        suggestion: "Repair the book, for example with Calibre's Check Book tool, or convert with the native converter",
+        matches: |line| {
+            [
+                "xmlsyntaxerror",
+                "not well-formed",
+                "mismatched tag",
+                "tag mismatch",
+                "error parsing xml",
+                "couldn't parse",
+                "could not parse",
+            ]
+            .iter()
+            .any(|p| line.contains(p))
+        },
+    },
+];
+
+const UNKNOWN_CODE: &str = "converter-failed";
+const TAIL_LINES: usize = 5; // Log lines kept in the error of an unrecognised failure
+const MAX_LOGS: usize = 50;
+
+// ============================================================================
+// Classification
+// ============================================================================
+
+/// Known failure found in a log or error message, with the line it is based on
+pub fn classify(log: &str) -> Option<ConversionDiagnostic> {
+    let lines: Vec<(&str, String)> = log
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| (l, l.to_lowercase()))
+        .collect();
+    RULES.iter().find_map(|rule| {
+        let (line, _) = lines.iter().find(|(_, lower)| (rule.matches)(lower))?;
+        Some(ConversionDiagnostic {
+            code: rule.code.to_string(),
+            message: rule.message.to_string(),
+            suggestion: rule.suggestion.to_string(),
+            detail: Some(line.to_string()),
+            log_path: None,
+        })
+    })
+}
+
+/// Diagnosis of a failed converter run; unrecognised failures keep the end of the log
+pub fn diagnose(converter: &str, log: &str) -> ConversionDiagnostic {
+    classify(log).unwrap_or_else(|| {
+        let lines: Vec<&str> = log.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        let tail = lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n");
+        ConversionDiagnostic {
+            code: UNKNOWN_CODE.to_string(),
+            message: format!("{} stopped with an error", converter),
+            suggestion: "Check the conversion log, or try another converter".to_string(),
+            detail: (!tail.is_empty()).then_some(tail),
+            log_path: None,
+        }
+    })
+}
+
+/// Diagnosis of the validator error that stopped a conversion before it started
+pub fn from_validation(issue: &ValidationIssue) -> ConversionDiagnostic {
+    let suggestion = match issue.code.as_str() {
+        "drm-protected" => RULES.iter().find(|r| r.code == "drm-protected").map(|r| r.suggestion),
+        _ => None,
+    };
+    ConversionDiagnostic {
+        code: issue.code.clone(),
+        message: issue.message.clone(),
+        suggestion: suggestion
+            .unwrap_or("Repair the book, for example with Calibre's Check Book tool")
+            .to_string(),
+        detail: issue.path.clone(),
+        log_path: None,
+    }
+}
+
+// ============================================================================
+// Logs
+// ============================================================================
+
+/// Folder of the saved converter logs, next to the conversion cache
+pub fn logs_dir() -> PathBuf {
+    conversion_cache::cache_dir().with_file_name("conversion-logs")
+}
+
+/// Save a converter log under `dir`, keeping the newest MAX_LOGS; None when it cannot be written
+pub fn save_log(dir: &Path, converter: &str, target: &Path, log: &str) -> Option<PathBuf> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    std::fs::create_dir_all(dir).ok()?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let stem = target.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = format!(
+        "{}-{}-{}-{}.log",
+        stamp,
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        converter.to_lowercase(),
+        // Converters write to ".<stem>.partial.<ext>", see partial_output_path
+        stem.trim_start_matches('.').trim_end_matches(".partial")
+    );
+    let path = dir.join(name);
+    std::fs::write(&path, log).ok()?;
+    prune_logs(dir, MAX_LOGS);
+    Some(path)
+}
+
+/// Remove the oldest logs beyond `keep`
+fn prune_logs(dir: &Path, keep: usize) {
+    let mut logs: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "log"))
+        .map(|p| (p.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH), p))
+        .collect();
+    logs.sort();
+    let excess = logs.len().saturating_sub(keep);
+    for (_, path) in logs.into_iter().take(excess) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Contents of a saved converter log
+#[command]
+pub fn get_conversion_log(log_path: String) -> Result<String, String> {
+    let dir = logs_dir().canonicalize().map_err(|e| format!("Failed to read conversion log: {}", e))?;
+    let path = Path::new(&log_path)
+        .canonicalize()
+        .map_err(|e| format!("Failed to read conversion log: {}", e))?;
+    // Only files written by save_log can be read through this command
+    if path.parent() != Some(dir.as_path()) {
+        return Err(format!("Not a conversion log: {}", log_path));
+    }
+    std::fs::read_to_string(&path).map_err(|e| format!("Failed to read conversion log: {}", e))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let code = |log: &str| classify(log).map(|d| d.code);
+        assert_eq!(
+            code("xelatex not found. Please select a different --pdf-engine or install xelatex").as_deref(),
+            Some("latex-engine-missing")
+        );
+        assert_eq!(
+            code("Error producing PDF.\n! Package fontspec Error: The font \"Garamond\" cannot be found.")
+                .as_deref(),
+            Some("font-missing")
+        );
+        assert_eq!(
+            code("Traceback (most recent call last):\ncalibre.ebooks.DRMError: This file is locked with DRM").as_deref(),
+            Some("drm-protected")
+        );
+        assert_eq!(
+            code("lxml.etree.XMLSyntaxError: Opening and ending tag mismatch: p line 12 and div").as_deref(),
+            Some("malformed-xhtml")
+        );
+        // Disk space wins over whatever failed because of it
+        assert_eq!(
+            code("! I can't write on file `book.pdf'.\nOSError: [Errno 28] No space left on device").as_deref(),
+            Some("disk-full")
+        );
+        assert_eq!(
+            code("No PDF engine found for Pandoc: install TeX Live, MiKTeX, Tectonic or WeasyPrint").as_deref(),
+            Some("latex-engine-missing")
+        );
+        assert_eq!(
+            code("! LaTeX Error: File `fontspec.sty' not found.").as_deref(),
+            Some("latex-package-missing")
+        );
+        assert_eq!(
+            code("! LaTeX Error: Font \\OT1/cmr/m/n/10=cmr10 not loadable: Metric (TFM) file not found.").as_deref(),
+            Some("font-missing")
+        );
+        assert_eq!(code("Failed to move PDF into place: Permission denied (os error 13)"), None);
+
+        let log = "Converting...\n  ! Package fontspec Error: The font \"Garamond\" cannot be found.  ";
+        let diagnostic = classify(log).unwrap();
+        assert_eq!(
+            diagnostic.detail.as_deref(),
+            Some("! Package fontspec Error: The font \"Garamond\" cannot be found.")
+        );
+
+        let unknown = diagnose("Pandoc", "one\ntwo\nthree\nfour\nfive\nsix\n\n");
+        assert_eq!(unknown.code, "converter-failed");
+        assert_eq!(unknown.message, "Pandoc stopped with an error");
+        assert_eq!(unknown.detail.as_deref(), Some("two\nthree\nfour\nfive\nsix"));
+    }
+
+    #[test]
+    fn test_save_log() {
+        let dir = std::env::temp_dir().join(format!("stomy-logs-{}", std::process::id()));
+        let path = save_log(&dir, "Calibre", Path::new("/books/.dune.partial.pdf"), "full log").unwrap();
+        assert!(path.file_name().unwrap().to_string_lossy().ends_with("-calibre-dune.log"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "full log");
+
+        for _ in 0..3 {
+            save_log(&dir, "Pandoc", Path::new("dune.pdf"), "log").unwrap();
+        }
+        prune_logs(&dir, 2);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
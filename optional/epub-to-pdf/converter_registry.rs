@@ -0,0 +1,564 @@
+/// Converter Registry
+/// Finds Calibre and Pandoc without relying on the shell PATH: user-configured
+/// binaries first, then PATH, then well-known install locations and Flatpak.
+/// Versions are checked against a minimum and results are cached until refreshed.
+/// The PDF engines Pandoc can drive are detected alongside it.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/converter_registry.rs
+/// 2. Add `mod converter_registry;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use tauri::command;
+
+use super::epub_converter::ConverterInfo;
+
+/// An external converter the registry knows how to find
+struct ConverterSpec {
+    name: &'static str,
+    label: &'static str,
+    binary: &'static str,
+    min_version: [u32; 2],
+    flatpak_app: Option<&'static str>,
+    // Well-known install locations, `~` and `%VAR%` are expanded
+    locations: &'static [&'static str],
+}
+
+const SPECS: [ConverterSpec; 2] = [
+    ConverterSpec {
+        name: "calibre",
+        label: "Calibre",
+        binary: "ebook-convert",
+        // Oldest release with the Python 3 engine and the PDF options we pass
+        min_version: [5, 0],
+        flatpak_app: Some("com.calibre_ebook.calibre"),
+        locations: &[
+            "/opt/calibre/ebook-convert",
+            "~/calibre-bin/calibre/ebook-convert",
+            "/usr/local/bin/ebook-convert",
+            "/Applications/calibre.app/Contents/MacOS/ebook-convert",
+            "~/Applications/calibre.app/Contents/MacOS/ebook-convert",
+            "%ProgramFiles%\\Calibre2\\ebook-convert.exe",
+            "%ProgramFiles(x86)%\\Calibre2\\ebook-convert.exe",
+        ],
+    },
+    ConverterSpec {
+        name: "pandoc",
+        label: "Pandoc",
+        binary: "pandoc",
+        // --pdf-engine appeared in 2.0
+        min_version: [2, 0],
+        flatpak_app: None,
+        locations: &[
+            "/usr/local/bin/pandoc",
+            "/opt/homebrew/bin/pandoc",
+            "~/.local/bin/pandoc",
+            "~/.cabal/bin/pandoc",
+            "%ProgramFiles%\\Pandoc\\pandoc.exe",
+            "%LOCALAPPDATA%\\Pandoc\\pandoc.exe",
+        ],
+    },
+];
+
+/// PDF engines Pandoc can drive, best first: the Unicode TeX engines, pdflatex,
+/// then the HTML-based ones, which need no TeX installation
+pub const PDF_ENGINES: [&str; 6] = ["xelatex", "lualatex", "tectonic", "pdflatex", "weasyprint", "wkhtmltopdf"];
+
+/// Engines that render Pandoc's HTML output instead of LaTeX
+const HTML_PDF_ENGINES: [&str; 2] = ["weasyprint", "wkhtmltopdf"];
+
+/// Where TeX distributions and the HTML engines install their binaries outside PATH
+const PDF_ENGINE_LOCATIONS: &[&str] = &[
+    "/Library/TeX/texbin",
+    "/usr/texbin",
+    "/opt/homebrew/bin",
+    "/usr/local/bin",
+    "~/.TinyTeX/bin/x86_64-linux",
+    "~/Library/TinyTeX/bin/universal-darwin",
+    "~/.cargo/bin",
+    "%ProgramFiles%\\MiKTeX\\miktex\\bin\\x64",
+    "%LOCALAPPDATA%\\Programs\\MiKTeX\\miktex\\bin\\x64",
+    "%ProgramFiles%\\wkhtmltopdf\\bin",
+];
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+/// Binaries chosen by the user, tried before anything else
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConverterPaths {
+    pub calibre: Option<String>,
+    pub pandoc: Option<String>,
+}
+
+impl ConverterPaths {
+    fn get(&self, converter: &str) -> Option<&str> {
+        match converter {
+            "calibre" => self.calibre.as_deref(),
+            "pandoc" => self.pandoc.as_deref(),
+            _ => None,
+        }
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    }
+}
+
+/// An installed PDF engine, passed to Pandoc by its full path
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfEngine {
+    pub name: String, // One of PDF_ENGINES
+    pub path: String,
+}
+
+impl PdfEngine {
+    pub fn is_latex(&self) -> bool {
+        !HTML_PDF_ENGINES.contains(&self.name.as_str())
+    }
+
+    /// Engines with fontspec, which loads the user's font files
+    pub fn supports_fonts(&self) -> bool {
+        matches!(self.name.as_str(), "xelatex" | "lualatex" | "tectonic")
+    }
+}
+
+/// How to start a converter: a binary, or `flatpak run --command=...`
+#[derive(Debug, Clone, PartialEq)]
+struct Binary {
+    program: PathBuf,
+    args: Vec<String>,
+}
+
+impl Binary {
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+
+    fn display(&self) -> String {
+        let mut parts = vec![self.program.to_string_lossy().to_string()];
+        parts.extend(self.args.iter().cloned());
+        parts.join(" ")
+    }
+}
+
+struct Detected {
+    info: ConverterInfo,
+    binary: Option<Binary>,
+}
+
+#[derive(Default)]
+struct Registry {
+    paths: ConverterPaths,
+    detected: Option<Vec<Detected>>, // None until the first detection or after a change
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY
+        .get_or_init(|| Mutex::new(Registry::default()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Expand `~` and `%VAR%` in a well-known location; None if a variable is unset
+fn expand_location(location: &str) -> Option<PathBuf> {
+    if let Some(rest) = location.strip_prefix("~/") {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+        return Some(Path::new(&home).join(rest));
+    }
+    if let Some(rest) = location.strip_prefix('%') {
+        let (var, rest) = rest.split_once('%')?;
+        let value = std::env::var_os(var)?;
+        return Some(PathBuf::from(format!("{}{}", value.to_string_lossy(), rest)));
+    }
+    Some(PathBuf::from(location))
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+fn executable_name(binary: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("{}.exe", binary)
+    } else {
+        binary.to_string()
+    }
+}
+
+/// Look a binary up in PATH without spawning `which`/`where`
+pub fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    let file_name = executable_name(binary);
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Flatpak installs do not export the command line tools, so run them through `flatpak run`
+fn flatpak_binary(app: &str, binary: &str) -> Option<Binary> {
+    if cfg!(target_os = "windows") || cfg!(target_os = "macos") {
+        return None;
+    }
+    let installed = expand_location(&format!("~/.local/share/flatpak/app/{}", app))
+        .into_iter()
+        .chain([PathBuf::from("/var/lib/flatpak/app").join(app)])
+        .any(|dir| dir.is_dir());
+    let flatpak = find_in_path("flatpak").or_else(|| {
+        let path = PathBuf::from("/usr/bin/flatpak");
+        is_executable(&path).then_some(path)
+    })?;
+
+    installed.then(|| Binary {
+        program: flatpak,
+        args: vec!["run".to_string(), format!("--command={}", binary), app.to_string()],
+    })
+}
+
+/// Candidates in the order they are tried
+fn candidates(spec: &ConverterSpec, user_path: Option<&str>) -> Vec<Binary> {
+    let plain = |program: PathBuf| Binary { program, args: Vec::new() };
+    let mut candidates = Vec::new();
+
+    if let Some(path) = user_path {
+        candidates.push(plain(PathBuf::from(path)));
+    }
+    if let Some(path) = find_in_path(spec.binary) {
+        candidates.push(plain(path));
+    }
+    for location in spec.locations {
+        if let Some(path) = expand_location(location).filter(|p| is_executable(p)) {
+            candidates.push(plain(path));
+        }
+    }
+    if let Some(binary) = spec.flatpak_app.and_then(|app| flatpak_binary(app, spec.binary)) {
+        candidates.push(binary);
+    }
+
+    // PATH often contains one of the well-known locations too
+    let mut unique: Vec<Binary> = Vec::new();
+    for candidate in candidates {
+        if !unique.contains(&candidate) {
+            unique.push(candidate);
+        }
+    }
+    unique
+}
+
+/// First version number in `--version` output ("ebook-convert (calibre 7.6.0)", "pandoc 3.1.3")
+fn parse_version(output: &str) -> Option<Vec<u32>> {
+    output
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|token| token.starts_with(|c: char| c.is_ascii_digit()) && token.contains('.'))
+        .find_map(|token| {
+            token
+                .trim_end_matches('.')
+                .split('.')
+                .map(|part| part.parse::<u32>().ok())
+                .collect::<Option<Vec<u32>>>()
+        })
+}
+
+fn format_version(version: &[u32]) -> String {
+    version.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// Run `--version` and check the minimum version
+fn check_binary(spec: &ConverterSpec, binary: &Binary) -> Result<String, String> {
+    let output = binary
+        .command()
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", binary.display(), e))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let version = parse_version(&text)
+        .ok_or_else(|| format!("Unrecognised {} version output from {}", spec.label, binary.display()))?;
+
+    if version.as_slice() < spec.min_version.as_slice() {
+        return Err(format!(
+            "{} {} is too old, version {} or newer is required",
+            spec.label,
+            format_version(&version),
+            format_version(&spec.min_version)
+        ));
+    }
+    Ok(format_version(&version))
+}
+
+/// Try every candidate and keep the first one that works
+fn probe(spec: &ConverterSpec, user_path: Option<&str>) -> Detected {
+    // The first error is the most relevant one: the configured path comes first
+    let mut errors = Vec::new();
+    if let Some(path) = user_path.filter(|p| !Path::new(p).exists()) {
+        errors.push(format!("Configured {} path does not exist: {}", spec.label, path));
+    }
+
+    for binary in candidates(spec, user_path) {
+        match check_binary(spec, &binary) {
+            Ok(version) => {
+                return Detected {
+                    info: ConverterInfo {
+                        available: true,
+                        converter: spec.name.to_string(),
+                        version: Some(version),
+                        path: Some(binary.display()),
+                        conversions: Vec::new(),
+                        pdf_engines: Vec::new(),
+                        error: None,
+                    },
+                    binary: Some(binary),
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    let error = errors.into_iter().next().unwrap_or_else(|| format!("{} not found", spec.label));
+
+    Detected {
+        info: ConverterInfo {
+            available: false,
+            converter: spec.name.to_string(),
+            version: None,
+            path: None,
+            conversions: Vec::new(),
+            pdf_engines: Vec::new(),
+            error: Some(error),
+        },
+        binary: None,
+    }
+}
+
+/// Installed PDF engines found in `dirs`, best first; the first directory wins for each engine
+fn find_pdf_engines(dirs: &[PathBuf]) -> Vec<PdfEngine> {
+    PDF_ENGINES
+        .iter()
+        .filter_map(|name| {
+            let file_name = executable_name(name);
+            let path = dirs.iter().map(|dir| dir.join(&file_name)).find(|p| is_executable(p))?;
+            Some(PdfEngine {
+                name: name.to_string(),
+                path: path.to_string_lossy().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// PDF engines in PATH, then in the well-known install locations
+fn detect_pdf_engines() -> Vec<PdfEngine> {
+    let mut dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default();
+    dirs.extend(PDF_ENGINE_LOCATIONS.iter().filter_map(|l| expand_location(l)));
+    find_pdf_engines(&dirs)
+}
+
+fn detect_all(paths: &ConverterPaths) -> Vec<Detected> {
+    let mut detected: Vec<Detected> = SPECS.iter().map(|spec| probe(spec, paths.get(spec.name))).collect();
+    for pandoc in detected.iter_mut().filter(|d| d.info.converter == "pandoc" && d.info.available) {
+        pandoc.info.pdf_engines = detect_pdf_engines();
+    }
+    // The built-in renderer needs no external tool
+    detected.push(Detected {
+        info: ConverterInfo {
+            available: true,
+            converter: "native".to_string(),
+            version: None,
+            path: None,
+            conversions: Vec::new(),
+            pdf_engines: Vec::new(),
+            error: None,
+        },
+        binary: None,
+    });
+    detected
+}
+
+// ============================================================================
+// Registry Access
+// ============================================================================
+
+fn with_detected<T>(refresh: bool, f: impl FnOnce(&[Detected]) -> T) -> T {
+    let mut registry = registry();
+    if refresh || registry.detected.is_none() {
+        let detected = detect_all(&registry.paths);
+        registry.detected = Some(detected);
+    }
+    f(registry.detected.as_deref().unwrap_or_default())
+}
+
+/// Every known converter, available or not, in order of preference
+pub fn converters(refresh: bool) -> Vec<ConverterInfo> {
+    with_detected(refresh, |detected| detected.iter().map(|d| d.info.clone()).collect())
+}
+
+/// Names of the converters that can be used, in order of preference
+pub fn available_converters() -> Vec<&'static str> {
+    with_detected(false, |detected| {
+        ["calibre", "pandoc", "native"]
+            .into_iter()
+            .filter(|name| detected.iter().any(|d| d.info.converter == *name && d.info.available))
+            .collect()
+    })
+}
+
+/// A Command starting the detected binary of an external converter
+pub fn converter_command(converter: &str) -> Result<Command, String> {
+    with_detected(false, |detected| {
+        let found = detected.iter().find(|d| d.info.converter == converter);
+        match found.and_then(|d| d.binary.as_ref()) {
+            Some(binary) => Ok(binary.command()),
+            None => Err(found
+                .and_then(|d| d.info.error.clone())
+                .unwrap_or_else(|| format!("Unknown converter: {}", converter))),
+        }
+    })
+}
+
+/// The PDF engine Pandoc should run: `requested`, or the best installed one when empty
+pub fn pdf_engine(requested: &str) -> Result<PdfEngine, String> {
+    let engines = with_detected(false, |detected| {
+        detected
+            .iter()
+            .find(|d| d.info.converter == "pandoc")
+            .map(|d| d.info.pdf_engines.clone())
+            .unwrap_or_default()
+    });
+    match requested {
+        "" => engines.into_iter().next().ok_or_else(|| {
+            "No PDF engine found for Pandoc: install TeX Live, MiKTeX, Tectonic, WeasyPrint or wkhtmltopdf".to_string()
+        }),
+        name => engines
+            .into_iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| format!("PDF engine not found: {}", name)),
+    }
+}
+
+/// All converters; `refresh` probes again instead of using the cached results
+#[command]
+pub fn detect_converters(refresh: Option<bool>) -> Vec<ConverterInfo> {
+    converters(refresh.unwrap_or(false))
+}
+
+/// Use the given binaries and detect again
+#[command]
+pub fn set_converter_paths(paths: ConverterPaths) -> Vec<ConverterInfo> {
+    let mut registry = registry();
+    registry.paths = paths;
+    let detected = detect_all(&registry.paths);
+    let infos = detected.iter().map(|d| d.info.clone()).collect();
+    registry.detected = Some(detected);
+    infos
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("ebook-convert (calibre 7.6.0)\nCreated by: Kovid Goyal"), Some(vec![7, 6, 0]));
+        assert_eq!(parse_version("pandoc 3.1.3\nFeatures: +server"), Some(vec![3, 1, 3]));
+        assert_eq!(parse_version("pandoc.exe 2.19."), Some(vec![2, 19]));
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_expand_location() {
+        assert_eq!(expand_location("/opt/calibre/ebook-convert"), Some(PathBuf::from("/opt/calibre/ebook-convert")));
+        assert_eq!(expand_location("%STOMY_UNSET_VARIABLE%\\x.exe"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_pdf_engines() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("stomy-engines-{}", std::process::id()));
+        let (tex, local) = (root.join("texbin"), root.join("bin"));
+        for (dir, name) in [(&local, "weasyprint"), (&tex, "lualatex"), (&local, "lualatex"), (&tex, "pdflatex")] {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(dir.join(name), "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(dir.join(name), std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        // Not executable, so not an engine
+        std::fs::write(local.join("xelatex"), "").unwrap();
+
+        let engines = find_pdf_engines(&[tex.clone(), local.clone()]);
+        let names: Vec<&str> = engines.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["lualatex", "pdflatex", "weasyprint"]);
+        assert_eq!(engines[0].path, tex.join("lualatex").to_string_lossy());
+        assert!(engines[0].is_latex() && engines[0].supports_fonts());
+        assert!(engines[1].is_latex() && !engines[1].supports_fonts());
+        assert!(!engines[2].is_latex());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_user_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("stomy-registry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write_script = |name: &str, version: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\necho 'ebook-convert (calibre {})'\n", version)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path.to_string_lossy().to_string()
+        };
+
+        let spec = &SPECS[0];
+        let current = write_script("ebook-convert", "7.6.0");
+        let detected = probe(spec, Some(&current));
+        assert!(detected.info.available);
+        assert_eq!(detected.info.version.as_deref(), Some("7.6.0"));
+        assert_eq!(detected.info.path.as_deref(), Some(current.as_str()));
+
+        let old = write_script("ebook-convert-old", "3.48.0");
+        let detected = probe(spec, Some(&old));
+        if !detected.info.available {
+            assert_eq!(
+                detected.info.error.as_deref(),
+                Some("Calibre 3.48.0 is too old, version 5.0 or newer is required")
+            );
+        }
+
+        let missing = probe(spec, Some("/nonexistent/ebook-convert"));
+        if !missing.info.available {
+            assert_eq!(
+                missing.info.error.as_deref(),
+                Some("Configured Calibre path does not exist: /nonexistent/ebook-convert")
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,471 @@
+/// Converter Sandbox
+/// Runs Calibre and Pandoc on untrusted books with as little reach as the platform
+/// allows: a private working folder, a scrubbed environment, no network (user and
+/// network namespaces or seccomp on Linux, sandbox-exec on macOS) and limits on CPU
+/// time and file size. What is missing on this system is reported, not hidden.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/converter_sandbox.rs (needs conversion_cache.rs)
+/// 2. Add `mod converter_sandbox;` to src-tauri/src/main.rs
+/// 3. Add `libc = "0.2"` to src-tauri/Cargo.toml
+/// 4. Register the command in the invoke_handler
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use tauri::command;
+
+use super::conversion_cache;
+use super::epub_converter::EpubToPdfSettings;
+
+/// Variables converters need; everything else (tokens, proxies, agent sockets) is dropped
+const KEPT_VARIABLES: &[&str] = &[
+    "PATH",
+    "LANG",
+    "LANGUAGE",
+    "TZ",
+    "SystemRoot",
+    "windir",
+    "ComSpec",
+    "PATHEXT",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramData",
+    "APPDATA",
+    "LOCALAPPDATA",
+];
+/// Locale and TeX/Calibre configuration set on purpose by the user
+const KEPT_PREFIXES: &[&str] = &["LC_", "TEXMF", "TEXINPUTS", "MIKTEX", "CALIBRE_"];
+
+/// macOS profile: everything but IP networking
+const MACOS_PROFILE: &str =
+    "(version 1)(allow default)(deny network-outbound (remote ip))(deny network-inbound (local ip))";
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+/// Limits an external converter runs under
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxPolicy {
+    pub cpu_seconds: Option<u64>,
+    pub max_output_bytes: Option<u64>, // Per written file
+    pub require_network_isolation: bool,
+}
+
+impl SandboxPolicy {
+    /// None when sandboxing is turned off in the settings
+    pub fn from_settings(settings: &EpubToPdfSettings) -> Option<Self> {
+        settings.sandbox_converters.then(|| SandboxPolicy {
+            cpu_seconds: (settings.max_cpu_seconds > 0).then_some(settings.max_cpu_seconds),
+            max_output_bytes: (settings.max_output_mb > 0).then(|| settings.max_output_mb.saturating_mul(1024 * 1024)),
+            require_network_isolation: settings.require_network_isolation,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SandboxStatus {
+    pub network_isolation: String, // "namespaces", "seccomp", "sandbox-exec" or "none"
+    pub resource_limits: bool,     // CPU time and file size limits
+    pub warning: Option<String>,   // What is not protected on this system, and why
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Isolation {
+    Namespaces,
+    Seccomp,
+    SandboxExec,
+    None,
+}
+
+/// A converter's private working folder, removed once it has exited
+pub struct SandboxDir {
+    path: PathBuf,
+}
+
+impl SandboxDir {
+    fn create() -> Result<Self, String> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "stomy-sandbox-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).map_err(|e| format!("Failed to create sandbox folder: {}", e))?;
+        Ok(SandboxDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SandboxDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Best network isolation this system allows, probed once with a trivial command
+fn isolation() -> Isolation {
+    static ISOLATION: OnceLock<Isolation> = OnceLock::new();
+    *ISOLATION.get_or_init(|| {
+        #[cfg(target_os = "linux")]
+        {
+            let works = |isolation: Isolation| {
+                let mut command = Command::new("/bin/sh");
+                command.args(["-c", "exit 0"]);
+                apply_limits(&mut command, None, isolation);
+                command.status().is_ok_and(|s| s.success())
+            };
+            // Distributions may turn unprivileged user namespaces off
+            if works(Isolation::Namespaces) {
+                return Isolation::Namespaces;
+            }
+            if linux::AUDIT_ARCH.is_some() && works(Isolation::Seccomp) {
+                return Isolation::Seccomp;
+            }
+        }
+        if cfg!(target_os = "macos") && Path::new("/usr/bin/sandbox-exec").exists() {
+            return Isolation::SandboxExec;
+        }
+        Isolation::None
+    })
+}
+
+/// What the sandbox can enforce here
+pub fn status() -> SandboxStatus {
+    let isolation = isolation();
+    let warning = match isolation {
+        Isolation::None if cfg!(target_os = "windows") => Some(
+            "Network isolation and CPU limits are not available on Windows: converters only get a private \
+             folder, a scrubbed environment and an output size check"
+                .to_string(),
+        ),
+        Isolation::None if cfg!(target_os = "macos") => {
+            Some("sandbox-exec was not found: converters can reach the network".to_string())
+        }
+        Isolation::None => Some(
+            "Neither user namespaces nor seccomp are available: converters can reach the network".to_string(),
+        ),
+        _ => None,
+    };
+    SandboxStatus {
+        network_isolation: match isolation {
+            Isolation::Namespaces => "namespaces",
+            Isolation::Seccomp => "seccomp",
+            Isolation::SandboxExec => "sandbox-exec",
+            Isolation::None => "none",
+        }
+        .to_string(),
+        resource_limits: cfg!(unix),
+        warning,
+    }
+}
+
+// ============================================================================
+// Confinement
+// ============================================================================
+
+fn kept_variable(name: &str) -> bool {
+    KEPT_VARIABLES.iter().any(|v| v.eq_ignore_ascii_case(name)) || KEPT_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+/// Home of the sandboxed converters: their caches (Calibre settings, LuaTeX font
+/// names, Tectonic bundles) survive between runs, the user's own files are not in it
+fn converter_home() -> PathBuf {
+    conversion_cache::cache_dir().with_file_name("converter-home")
+}
+
+/// Prepare a converter command to run in the sandbox; the folder must outlive the process
+pub fn confine(command: Command, policy: &SandboxPolicy) -> Result<(Command, SandboxDir), String> {
+    let isolation = isolation();
+    // Otherwise the missing isolation is reported in the warnings of the conversion result
+    if isolation == Isolation::None && policy.require_network_isolation {
+        return Err(format!("Converter sandbox unavailable: {}", status().warning.unwrap_or_default()));
+    }
+
+    let mut confined = if isolation == Isolation::SandboxExec {
+        let mut wrapped = Command::new("/usr/bin/sandbox-exec");
+        wrapped.args(["-p", MACOS_PROFILE]).arg(command.get_program());
+        wrapped
+    } else {
+        Command::new(command.get_program())
+    };
+    confined.args(command.get_args());
+
+    let dir = SandboxDir::create()?;
+    let home = converter_home();
+    std::fs::create_dir_all(&home).map_err(|e| format!("Failed to create sandbox folder: {}", e))?;
+    confined.env_clear().current_dir(dir.path());
+    confined.envs(std::env::vars_os().filter(|(name, _)| kept_variable(&name.to_string_lossy())));
+    for name in ["HOME", "USERPROFILE"] {
+        confined.env(name, &home);
+    }
+    for name in ["TMPDIR", "TMP", "TEMP"] {
+        confined.env(name, dir.path());
+    }
+
+    #[cfg(unix)]
+    apply_limits(&mut confined, Some(policy), isolation);
+    Ok((confined, dir))
+}
+
+/// Network isolation and resource limits, applied in the child between fork and exec
+#[cfg(unix)]
+fn apply_limits(command: &mut Command, policy: Option<&SandboxPolicy>, isolation: Isolation) {
+    use std::os::unix::process::CommandExt;
+
+    let limit = |soft: u64, hard: u64| libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // A grace of a few seconds: SIGXCPU first, then SIGKILL at the hard limit
+    let cpu = policy.and_then(|p| p.cpu_seconds).map(|s| limit(s, s + 5));
+    let size = policy.and_then(|p| p.max_output_bytes).map(|b| limit(b, b));
+    #[cfg(target_os = "linux")]
+    let id_maps = linux::IdMaps::current();
+
+    let setup = move || -> std::io::Result<()> {
+        #[cfg(target_os = "linux")]
+        match isolation {
+            Isolation::Namespaces => linux::enter_namespaces(&id_maps)?,
+            Isolation::Seccomp => linux::deny_ip_sockets()?,
+            _ => {}
+        }
+        if let Some(cpu) = &cpu {
+            if unsafe { libc::setrlimit(libc::RLIMIT_CPU, cpu) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(size) = &size {
+            if unsafe { libc::setrlimit(libc::RLIMIT_FSIZE, size) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    };
+    // Safety: the closure only makes system calls on data prepared before the fork
+    unsafe {
+        command.pre_exec(setup);
+    }
+}
+
+/// Check a converted file against the size limit, for platforms without file size limits
+pub fn check_output(path: &Path, policy: &SandboxPolicy) -> Result<(), String> {
+    let Some(max) = policy.max_output_bytes else {
+        return Ok(());
+    };
+    let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+    if size > max {
+        return Err(format!(
+            "Converter output exceeds the {} MB limit",
+            max / (1024 * 1024)
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CStr;
+    use std::io;
+
+    /// Seccomp architecture of this build; the filter refuses every other one
+    pub const AUDIT_ARCH: Option<u32> = if cfg!(target_arch = "x86_64") {
+        Some(0xc000_003e)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(0xc000_00b7)
+    } else {
+        None
+    };
+    /// x32 system calls on x86_64 carry this bit
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// The caller's ids mapped to themselves, formatted before the fork
+    pub struct IdMaps {
+        uid: Vec<u8>,
+        gid: Vec<u8>,
+    }
+
+    impl IdMaps {
+        pub fn current() -> Self {
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            IdMaps {
+                uid: format!("{} {} 1", uid, uid).into_bytes(),
+                gid: format!("{} {} 1", gid, gid).into_bytes(),
+            }
+        }
+    }
+
+    fn write_proc(path: &CStr, contents: &[u8]) -> io::Result<()> {
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+        let error = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        if written != contents.len() as isize {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// New user and network namespaces: only a loopback interface, which is down.
+    /// The same ids are mapped, so files are still read and written as the user.
+    pub fn enter_namespaces(maps: &IdMaps) -> io::Result<()> {
+        if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        write_proc(c"/proc/self/setgroups", b"deny")?;
+        write_proc(c"/proc/self/uid_map", &maps.uid)?;
+        write_proc(c"/proc/self/gid_map", &maps.gid)
+    }
+
+    const fn statement(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    const fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt, jf, k }
+    }
+
+    /// Seccomp filter refusing IPv4 and IPv6 sockets and io_uring, when namespaces are not allowed
+    pub fn deny_ip_sockets() -> io::Result<()> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+        let Some(arch) = AUDIT_ARCH else {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        };
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EACCES as u32;
+        // seccomp_data: nr at 0, arch at 4, args from 16 (the low half of args[0] on little-endian)
+        let filter = [
+            statement(BPF_LD | BPF_W | BPF_ABS, 4),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 0, 8),
+            statement(BPF_LD | BPF_W | BPF_ABS, 0),
+            jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 6, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_io_uring_setup as u32, 5, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_socket as u32, 0, 3),
+            statement(BPF_LD | BPF_W | BPF_ABS, 16),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::AF_INET as u32, 2, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::AF_INET6 as u32, 1, 0),
+            statement(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW),
+            statement(BPF_RET | BPF_K, deny),
+        ];
+        let program = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr() as *mut libc::sock_filter,
+        };
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                || libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Which protections external converters run with on this system
+#[command]
+pub fn check_converter_sandbox() -> SandboxStatus {
+    status()
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> SandboxPolicy {
+        SandboxPolicy {
+            cpu_seconds: Some(60),
+            max_output_bytes: Some(1000),
+            require_network_isolation: false,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_confine() {
+        let mut command = Command::new("/bin/sh");
+        command
+            .args(["-c", "echo \"$STOMY_SECRET|$LANG|$(pwd)\"; head -c 5000 /dev/zero > big; wc -c < big"])
+            .env("STOMY_SECRET", "hunter2")
+            .env("LANG", "C");
+        let (mut confined, dir) = confine(command, &policy()).unwrap();
+        let output = confined.output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+
+        // LANG comes from this process, not from the command
+        let lang = std::env::var("LANG").unwrap_or_default();
+        let pwd = dir.path().canonicalize().unwrap();
+        assert_eq!(lines[0], format!("|{}|{}", lang, pwd.display()));
+        // The write stops at the file size limit
+        assert_eq!(lines[1].trim(), "1000");
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_network_isolation() {
+        if isolation() != Isolation::Namespaces {
+            assert!(status().network_isolation != "namespaces");
+            return;
+        }
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", "cat /proc/self/net/dev"]);
+        let (mut confined, _dir) = confine(command, &policy()).unwrap();
+        let output = String::from_utf8_lossy(&confined.output().unwrap().stdout).to_string();
+        let interfaces: Vec<&str> = output.lines().skip(2).filter_map(|l| l.split(':').next()).map(str::trim).collect();
+        assert_eq!(interfaces, vec!["lo"]);
+    }
+
+    #[test]
+    fn test_policy_and_output_check() {
+        let settings = EpubToPdfSettings {
+            max_output_mb: 1,
+            ..EpubToPdfSettings::default()
+        };
+        let policy = SandboxPolicy::from_settings(&settings).unwrap();
+        assert_eq!(policy.max_output_bytes, Some(1024 * 1024));
+        let off = EpubToPdfSettings {
+            sandbox_converters: false,
+            ..settings
+        };
+        assert_eq!(SandboxPolicy::from_settings(&off), None);
+
+        assert!(kept_variable("LC_ALL") && kept_variable("Path") && !kept_variable("AWS_SECRET_ACCESS_KEY"));
+
+        let dir = SandboxDir::create().unwrap();
+        let file = dir.path().join("dune.pdf");
+        std::fs::write(&file, vec![0u8; 2000]).unwrap();
+        let small = SandboxPolicy {
+            max_output_bytes: Some(1000),
+            ..policy
+        };
+        assert!(check_output(&file, &small).is_err());
+        assert!(check_output(&file, &SandboxPolicy { max_output_bytes: None, ..small }).is_ok());
+    }
+}
@@ -0,0 +1,451 @@
+/// Custom Stylesheet and Fonts
+/// Applies a user stylesheet and font files to every conversion. Calibre gets
+/// an EPUB copy with the fonts and stylesheet injected plus `--extra-css` and
+/// `--embed-font-family`, Pandoc gets the fonts through fontspec variables, the
+/// stylesheet through `--css` and an optional template, and the native
+/// renderer loads the fonts and the body font size itself.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/custom_style.rs (needs epub_package.rs and epub_native.rs)
+/// 2. Add `mod custom_style;` to src-tauri/src/main.rs
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::epub_converter::EpubToPdfSettings;
+use super::epub_native::{first_font_family, parse_css_rules};
+use super::epub_package::{prepare_xml, EpubPackage};
+
+/// Folder next to the OPF that receives the injected files
+const INJECTED_DIR: &str = "stomy-style";
+
+static WORKSPACE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// ============================================================================
+// Style
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomFont {
+    pub family: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub path: PathBuf,
+}
+
+impl CustomFont {
+    /// Read the family and style from the font's name and OS/2 tables
+    fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read font {}: {}", path.display(), e))?;
+        let face = ttf_parser::Face::parse(&data, 0)
+            .map_err(|e| format!("Failed to read font {}: {} (use TrueType or OpenType)", path.display(), e))?;
+        let name = |id: u16| {
+            face.names()
+                .into_iter()
+                .find(|n| n.name_id == id && n.is_unicode())
+                .and_then(|n| n.to_string())
+        };
+        let family = name(ttf_parser::name_id::TYPOGRAPHIC_FAMILY)
+            .or_else(|| name(ttf_parser::name_id::FAMILY))
+            .ok_or_else(|| format!("Font {} has no family name", path.display()))?;
+        Ok(CustomFont {
+            family,
+            bold: face.is_bold(),
+            italic: face.is_italic(),
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// User stylesheet, fonts and Pandoc template from the settings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CustomStyle {
+    pub css: String,
+    pub fonts: Vec<CustomFont>,
+    pub template: Option<PathBuf>,
+}
+
+impl CustomStyle {
+    /// None when the settings name no stylesheet, font or template
+    pub fn from_settings(settings: &EpubToPdfSettings) -> Result<Option<Self>, String> {
+        if settings.custom_css.is_empty() && settings.custom_fonts.is_empty() && settings.pandoc_template.is_empty() {
+            return Ok(None);
+        }
+
+        let css = match settings.custom_css.as_str() {
+            "" => String::new(),
+            path => std::fs::read_to_string(path).map_err(|e| format!("Failed to read stylesheet {}: {}", path, e))?,
+        };
+        let fonts = settings
+            .custom_fonts
+            .iter()
+            .map(|path| CustomFont::load(Path::new(path)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let template = match settings.pandoc_template.as_str() {
+            "" => None,
+            path if Path::new(path).is_file() => Some(PathBuf::from(path)),
+            path => return Err(format!("Pandoc template not found: {}", path)),
+        };
+        Ok(Some(CustomStyle { css, fonts, template }))
+    }
+
+    /// Declarations of the stylesheet's body rules (`html`, `body` or `p`), later rules first
+    fn body_declaration(&self, name: &str) -> Option<String> {
+        parse_css_rules(&self.css)
+            .into_iter()
+            .rev()
+            .filter(|(selector, _)| selector.split(',').any(|s| matches!(s.trim(), "body" | "p" | "html")))
+            .find_map(|(_, declarations)| declarations.into_iter().find(|(k, _)| k == name).map(|(_, v)| v))
+    }
+
+    /// Body family: the one the stylesheet names if it is among the fonts, else the first font's
+    pub fn body_family(&self) -> Option<&str> {
+        let wanted = self.body_declaration("font-family").map(|f| first_font_family(&f));
+        self.fonts
+            .iter()
+            .find(|f| wanted.as_deref() == Some(f.family.to_lowercase().as_str()))
+            .or_else(|| self.fonts.first())
+            .map(|f| f.family.as_str())
+    }
+
+    /// Body font size in points, from a `pt` or `px` value
+    pub fn body_font_size(&self) -> Option<f32> {
+        let value = self.body_declaration("font-size")?;
+        let value = value.trim().trim_end_matches("!important").trim();
+        let (number, factor) = if let Some(pt) = value.strip_suffix("pt") {
+            (pt, 1.0)
+        } else {
+            (value.strip_suffix("px")?, 0.75)
+        };
+        number.trim().parse::<f32>().ok().map(|n| n * factor).filter(|n| (4.0..=72.0).contains(n))
+    }
+
+    /// Font of the body family in the given style
+    pub fn face(&self, bold: bool, italic: bool) -> Option<&CustomFont> {
+        let family = self.body_family()?;
+        self.fonts
+            .iter()
+            .find(|f| f.family == family && f.bold == bold && f.italic == italic)
+    }
+
+    /// @font-face rules for every font, with urls under `font_dir`, then the body family
+    fn font_css(&self, font_dir: &str) -> String {
+        let mut css = String::new();
+        for font in &self.fonts {
+            css.push_str(&format!(
+                "@font-face {{ font-family: \"{}\"; font-weight: {}; font-style: {}; src: url(\"{}{}\"); }}\n",
+                font.family,
+                if font.bold { "bold" } else { "normal" },
+                if font.italic { "italic" } else { "normal" },
+                font_dir,
+                font.file_name()
+            ));
+        }
+        if let Some(family) = self.body_family() {
+            css.push_str(&format!("body {{ font-family: \"{}\", serif; }}\n", family));
+        }
+        css
+    }
+}
+
+// ============================================================================
+// Workspace
+// ============================================================================
+
+/// Temporary folder with the fonts, the stylesheet and the styled EPUB; removed on drop
+pub struct StyleWorkspace {
+    pub dir: PathBuf,
+}
+
+impl StyleWorkspace {
+    pub fn create(style: &CustomStyle) -> Result<Self, String> {
+        let dir = std::env::temp_dir().join(format!(
+            "stomy-style-{}-{}",
+            std::process::id(),
+            WORKSPACE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let workspace = StyleWorkspace { dir };
+        std::fs::create_dir_all(workspace.font_dir())
+            .map_err(|e| format!("Failed to create style folder: {}", e))?;
+        for font in &style.fonts {
+            std::fs::copy(&font.path, workspace.font_dir().join(font.file_name()))
+                .map_err(|e| format!("Failed to copy font {}: {}", font.path.display(), e))?;
+        }
+        std::fs::write(workspace.css_path(), &style.css)
+            .map_err(|e| format!("Failed to write stylesheet: {}", e))?;
+        Ok(workspace)
+    }
+
+    pub fn font_dir(&self) -> PathBuf {
+        self.dir.join("fonts")
+    }
+
+    pub fn css_path(&self) -> PathBuf {
+        self.dir.join("custom.css")
+    }
+
+    pub fn epub_path(&self) -> PathBuf {
+        self.dir.join("styled.epub")
+    }
+}
+
+impl Drop for StyleWorkspace {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// ============================================================================
+// EPUB Injection
+// ============================================================================
+
+/// Path of `target` relative to the folder of `from`, both archive paths
+fn relative_href(from: &str, target: &str) -> String {
+    let from_dirs: Vec<&str> = from.split('/').collect();
+    let from_dirs = &from_dirs[..from_dirs.len() - 1];
+    let target_parts: Vec<&str> = target.split('/').collect();
+    let common = from_dirs
+        .iter()
+        .zip(&target_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts = vec![".."; from_dirs.len() - common];
+    parts.extend(&target_parts[common..]);
+    parts.join("/")
+}
+
+fn font_media_type(path: &Path) -> &'static str {
+    match path.extension().map(|e| e.to_string_lossy().to_lowercase()).as_deref() {
+        Some("otf") => "font/otf",
+        _ => "font/ttf",
+    }
+}
+
+/// Copy the EPUB with the fonts and a stylesheet added to the manifest and linked,
+/// last, from every XHTML document so its rules win over the book's
+pub fn inject_into_epub(source: &Path, target: &Path, style: &CustomStyle) -> Result<(), String> {
+    let package = EpubPackage::open(source)?;
+    let documents: HashSet<String> = package
+        .manifest
+        .iter()
+        .filter(|item| item.media_type == "application/xhtml+xml")
+        .map(|item| item.href.clone())
+        .collect();
+    let opf_dir = package.opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let base = if opf_dir.is_empty() {
+        INJECTED_DIR.to_string()
+    } else {
+        format!("{}/{}", opf_dir, INJECTED_DIR)
+    };
+    let css_path = format!("{}/custom.css", base);
+
+    // Injected files, as (archive path, manifest href, media type, content)
+    let mut added = vec![(
+        css_path.clone(),
+        format!("{}/custom.css", INJECTED_DIR),
+        "text/css",
+        format!("{}{}", style.font_css("fonts/"), style.css).into_bytes(),
+    )];
+    for font in &style.fonts {
+        let data = std::fs::read(&font.path).map_err(|e| format!("Failed to read font {}: {}", font.path.display(), e))?;
+        added.push((
+            format!("{}/fonts/{}", base, font.file_name()),
+            format!("{}/fonts/{}", INJECTED_DIR, font.file_name()),
+            font_media_type(&font.path),
+            data,
+        ));
+    }
+
+    let input = File::open(source).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+    let mut archive = ZipArchive::new(input).map_err(|e| format!("Failed to read EPUB archive: {}", e))?;
+    let output = File::create(target).map_err(|e| format!("Failed to create output file: {}", e))?;
+    let mut writer = ZipWriter::new(output);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for index in 0..archive.len() {
+        let name = archive
+            .by_index_raw(index)
+            .map_err(|e| format!("Failed to read EPUB entry: {}", e))?
+            .name()
+            .to_string();
+
+        if name != package.opf_path && !documents.contains(&name) {
+            let entry = archive
+                .by_index_raw(index)
+                .map_err(|e| format!("Failed to read EPUB entry: {}", e))?;
+            writer
+                .raw_copy_file(entry)
+                .map_err(|e| format!("Failed to copy {}: {}", name, e))?;
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        archive
+            .by_index(index)
+            .and_then(|mut entry| entry.read_to_end(&mut bytes).map_err(Into::into))
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        let text = prepare_xml(&bytes);
+        let text = if name == package.opf_path {
+            let items: String = added
+                .iter()
+                .enumerate()
+                .map(|(i, (_, href, media_type, _))| {
+                    format!("<item id=\"stomy-style-{}\" href=\"{}\" media-type=\"{}\"/>", i, href, media_type)
+                })
+                .collect();
+            text.replacen("</manifest>", &format!("{}</manifest>", items), 1)
+        } else {
+            let link = format!(
+                "<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>",
+                relative_href(&name, &css_path)
+            );
+            match text.find("</head>") {
+                Some(end) => format!("{}{}{}", &text[..end], link, &text[end..]),
+                None => text,
+            }
+        };
+        writer
+            .start_file(name.as_str(), options)
+            .and_then(|_| writer.write_all(text.as_bytes()).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+
+    for (path, _, _, data) in added {
+        writer
+            .start_file(path.as_str(), options)
+            .and_then(|_| writer.write_all(&data).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    }
+    writer.finish().map_err(|e| format!("Failed to write output file: {}", e))?;
+    Ok(())
+}
+
+// ============================================================================
+// Converter Arguments
+// ============================================================================
+
+/// ebook-convert options; the fonts themselves travel inside the injected EPUB
+pub fn calibre_style_args(style: &CustomStyle, workspace: &StyleWorkspace) -> Vec<String> {
+    let mut args = Vec::new();
+    if !style.css.is_empty() {
+        args.push("--extra-css".to_string());
+        args.push(workspace.css_path().to_string_lossy().to_string());
+    }
+    // Also embeds the family when it is installed system-wide
+    if let Some(family) = style.body_family() {
+        args.push("--embed-font-family".to_string());
+        args.push(family.to_string());
+    }
+    args
+}
+
+/// pandoc options: fontspec loads the body fonts from the workspace copies
+pub fn pandoc_style_args(style: &CustomStyle, workspace: &StyleWorkspace) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(regular) = style.face(false, false) {
+        let mut options = vec![format!("Path={}/", workspace.font_dir().to_string_lossy())];
+        for (key, bold, italic) in [("BoldFont", true, false), ("ItalicFont", false, true), ("BoldItalicFont", true, true)] {
+            if let Some(font) = style.face(bold, italic) {
+                options.push(format!("{}={}", key, font.file_name()));
+            }
+        }
+        args.extend([
+            "-V".to_string(),
+            format!("mainfont={}", regular.file_name()),
+            "-V".to_string(),
+            format!("mainfontoptions={}", options.join(",")),
+        ]);
+    }
+    // Used by the HTML-based PDF engines; LaTeX ignores it
+    if !style.css.is_empty() {
+        args.push("--css".to_string());
+        args.push(workspace.css_path().to_string_lossy().to_string());
+    }
+    if let Some(template) = &style.template {
+        args.push("--template".to_string());
+        args.push(template.to_string_lossy().to_string());
+    }
+    args
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::epub_package::write_test_epub;
+
+    #[test]
+    fn test_relative_href() {
+        assert_eq!(relative_href("OEBPS/Text/c1.xhtml", "OEBPS/stomy-style/custom.css"), "../stomy-style/custom.css");
+        assert_eq!(relative_href("OEBPS/c1.xhtml", "OEBPS/stomy-style/custom.css"), "stomy-style/custom.css");
+        assert_eq!(relative_href("c1.xhtml", "stomy-style/custom.css"), "stomy-style/custom.css");
+    }
+
+    #[test]
+    fn test_body_style() {
+        let font = |family: &str, bold: bool| CustomFont {
+            family: family.to_string(),
+            bold,
+            italic: false,
+            path: PathBuf::from(format!("/fonts/{}{}.ttf", family, if bold { "-Bold" } else { "" })),
+        };
+        let style = CustomStyle {
+            css: "h1 { font-family: Brand Sans } body, p { font-family: 'Brand Serif', serif; font-size: 14px }".to_string(),
+            fonts: vec![font("Brand Sans", false), font("Brand Serif", false), font("Brand Serif", true)],
+            template: None,
+        };
+        assert_eq!(style.body_family(), Some("Brand Serif"));
+        assert_eq!(style.body_font_size(), Some(10.5));
+        assert_eq!(style.face(true, false).map(|f| f.file_name()), Some("Brand Serif-Bold.ttf".to_string()));
+
+        let workspace = StyleWorkspace { dir: PathBuf::from("/tmp/ws") };
+        let args = pandoc_style_args(&style, &workspace);
+        assert!(args.contains(&"mainfont=Brand Serif.ttf".to_string()));
+        assert!(args.contains(&"mainfontoptions=Path=/tmp/ws/fonts/,BoldFont=Brand Serif-Bold.ttf".to_string()));
+        let args = calibre_style_args(&style, &workspace);
+        assert!(args.windows(2).any(|w| w == ["--embed-font-family", "Brand Serif"]));
+    }
+
+    #[test]
+    fn test_inject_into_epub() {
+        let dir = std::env::temp_dir().join(format!("stomy-style-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("dune.epub");
+        let target = dir.join("styled.epub");
+
+        write_test_epub(
+            &source,
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata/>
+<manifest><item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/></manifest>
+<spine><itemref idref="c1"/></spine></package>"#,
+            &[("OEBPS/Text/c1.xhtml", r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>1</title></head><body><p>Dune</p></body></html>"#)],
+        );
+
+        let style = CustomStyle { css: "p { color: navy }".to_string(), ..CustomStyle::default() };
+        inject_into_epub(&source, &target, &style).unwrap();
+
+        let mut package = EpubPackage::open(&target).unwrap();
+        assert!(package.manifest.iter().any(|i| i.href == "OEBPS/stomy-style/custom.css" && i.media_type == "text/css"));
+        let css = String::from_utf8(package.read_resource("OEBPS/stomy-style/custom.css").unwrap()).unwrap();
+        assert!(css.ends_with("p { color: navy }"));
+        let xhtml = String::from_utf8(package.read_resource("OEBPS/Text/c1.xhtml").unwrap()).unwrap();
+        assert!(xhtml.contains(r#"<link rel="stylesheet" type="text/css" href="../stomy-style/custom.css"/></head>"#));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
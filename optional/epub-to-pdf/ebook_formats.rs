@@ -0,0 +1,399 @@
+/// Ebook Format Matrix
+/// Formats known to the conversion engine, which converter handles each
+/// source/target pair, and the native KEPUB transforms.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/ebook_formats.rs (needs epub_package.rs)
+/// 2. Add `mod ebook_formats;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::epub_package::{parse_xml, prepare_xml, EpubPackage};
+
+/// Converters in order of preference
+pub const CONVERTERS: [&str; 3] = ["calibre", "pandoc", "native"];
+
+const KOBO_SPAN_CLASS: &str = "koboSpan";
+
+// ============================================================================
+// Formats
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EbookFormat {
+    Epub,
+    Pdf,
+    Mobi,
+    Azw3,
+    Kepub,
+    Txt,
+    Html,
+    Docx,
+    Cbz,
+}
+
+impl EbookFormat {
+    pub const ALL: [EbookFormat; 9] = [
+        EbookFormat::Epub,
+        EbookFormat::Pdf,
+        EbookFormat::Mobi,
+        EbookFormat::Azw3,
+        EbookFormat::Kepub,
+        EbookFormat::Txt,
+        EbookFormat::Html,
+        EbookFormat::Docx,
+        EbookFormat::Cbz,
+    ];
+
+    /// Format from the file name, ignoring case ("Dune.kepub.epub" is KEPUB)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".kepub.epub") {
+            return Some(EbookFormat::Kepub);
+        }
+        let (_, extension) = name.rsplit_once('.')?;
+        match extension {
+            "epub" => Some(EbookFormat::Epub),
+            "pdf" => Some(EbookFormat::Pdf),
+            "mobi" | "prc" => Some(EbookFormat::Mobi),
+            "azw3" | "azw" => Some(EbookFormat::Azw3),
+            "kepub" => Some(EbookFormat::Kepub),
+            "txt" => Some(EbookFormat::Txt),
+            "html" | "htm" | "xhtml" => Some(EbookFormat::Html),
+            "docx" => Some(EbookFormat::Docx),
+            "cbz" => Some(EbookFormat::Cbz),
+            _ => None,
+        }
+    }
+
+    /// Extension used for output files
+    pub fn extension(self) -> &'static str {
+        match self {
+            EbookFormat::Epub => "epub",
+            EbookFormat::Pdf => "pdf",
+            EbookFormat::Mobi => "mobi",
+            EbookFormat::Azw3 => "azw3",
+            EbookFormat::Kepub => "kepub.epub",
+            EbookFormat::Txt => "txt",
+            EbookFormat::Html => "html",
+            EbookFormat::Docx => "docx",
+            EbookFormat::Cbz => "cbz",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EbookFormat::Epub => "EPUB",
+            EbookFormat::Pdf => "PDF",
+            EbookFormat::Mobi => "MOBI",
+            EbookFormat::Azw3 => "AZW3",
+            EbookFormat::Kepub => "KEPUB",
+            EbookFormat::Txt => "TXT",
+            EbookFormat::Html => "HTML",
+            EbookFormat::Docx => "DOCX",
+            EbookFormat::Cbz => "CBZ",
+        }
+    }
+}
+
+// ============================================================================
+// Conversion Matrix
+// ============================================================================
+
+/// A source/target pair and the available converters able to handle it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConversionPath {
+    pub from: EbookFormat,
+    pub to: EbookFormat,
+    pub converters: Vec<String>,
+}
+
+/// Whether `converter` can turn `from` into `to`
+pub fn supports(converter: &str, from: EbookFormat, to: EbookFormat) -> bool {
+    use EbookFormat::*;
+
+    if from == to {
+        return false;
+    }
+    match converter {
+        // ebook-convert has no HTML, KEPUB or CBZ output plugin and cannot read KEPUB
+        "calibre" => {
+            matches!(from, Epub | Pdf | Mobi | Azw3 | Txt | Html | Docx | Cbz)
+                && matches!(to, Epub | Pdf | Mobi | Azw3 | Txt | Docx)
+        }
+        "pandoc" => {
+            matches!(from, Epub | Html | Docx | Txt) && matches!(to, Epub | Pdf | Html | Docx | Txt)
+        }
+        // A KEPUB is an EPUB with Kobo spans, so the renderer reads both
+        "native" => matches!(
+            (from, to),
+            (Epub | Kepub, Pdf) | (Epub | Kepub, Txt) | (Epub, Kepub) | (Kepub, Epub)
+        ),
+        _ => false,
+    }
+}
+
+/// First available converter able to handle the pair
+pub fn preferred_converter(available: &[&str], from: EbookFormat, to: EbookFormat) -> Option<&'static str> {
+    CONVERTERS
+        .into_iter()
+        .find(|c| available.contains(c) && supports(c, from, to))
+}
+
+/// Every pair at least one of the available converters can handle
+pub fn conversion_paths(available: &[&str]) -> Vec<ConversionPath> {
+    let mut paths = Vec::new();
+    for from in EbookFormat::ALL {
+        for to in EbookFormat::ALL {
+            let converters: Vec<String> = CONVERTERS
+                .into_iter()
+                .filter(|c| available.contains(c) && supports(c, from, to))
+                .map(|c| c.to_string())
+                .collect();
+            if !converters.is_empty() {
+                paths.push(ConversionPath { from, to, converters });
+            }
+        }
+    }
+    paths
+}
+
+// ============================================================================
+// KEPUB
+// ============================================================================
+
+fn text_edits_applied(text: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    // Apply from the end so earlier offsets stay valid
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.0));
+    let mut result = text.to_string();
+    for (start, end, replacement) in edits {
+        result.replace_range(start..end, &replacement);
+    }
+    result
+}
+
+/// Byte ranges of an element's start tag and end tag (the end tag is empty when self-closing)
+fn tag_ranges(node: roxmltree::Node, text: &str) -> ((usize, usize), (usize, usize)) {
+    let range = node.range();
+    match node.first_child() {
+        Some(first) => {
+            let close = text[range.clone()].rfind("</").map_or(range.end, |i| range.start + i);
+            ((range.start, first.range().start), (close, range.end))
+        }
+        None => ((range.start, range.end), (range.end, range.end)),
+    }
+}
+
+fn has_class(node: roxmltree::Node, class: &str) -> bool {
+    node.attribute("class")
+        .is_some_and(|value| value.split_whitespace().any(|c| c == class))
+}
+
+/// Wrap the text of every paragraph and heading in a Kobo span
+fn add_kobo_spans(xhtml: &str) -> Result<String, String> {
+    let doc = parse_xml(xhtml)?;
+    if doc.descendants().any(|n| has_class(n, KOBO_SPAN_CLASS)) {
+        return Ok(xhtml.to_string());
+    }
+
+    let mut edits = Vec::new();
+    let mut paragraph = 0;
+    for node in doc.descendants().filter(|n| n.is_element()) {
+        let name = node.tag_name().name().to_lowercase();
+        if !matches!(name.as_str(), "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+            continue;
+        }
+        let has_text = node
+            .descendants()
+            .any(|n| n.is_text() && n.text().is_some_and(|t| !t.trim().is_empty()));
+        if !has_text {
+            continue;
+        }
+
+        paragraph += 1;
+        let ((_, open_end), (close_start, _)) = tag_ranges(node, xhtml);
+        edits.push((
+            open_end,
+            open_end,
+            format!(r#"<span class="{}" id="kobo.{}.1">"#, KOBO_SPAN_CLASS, paragraph),
+        ));
+        edits.push((close_start, close_start, "</span>".to_string()));
+    }
+    Ok(text_edits_applied(xhtml, edits))
+}
+
+/// Unwrap Kobo spans and the book-columns/book-inner wrappers added by kepubify
+fn remove_kobo_spans(xhtml: &str) -> Result<String, String> {
+    let doc = parse_xml(xhtml)?;
+    let mut edits = Vec::new();
+    for node in doc.descendants().filter(|n| n.is_element()) {
+        let is_wrapper = node.tag_name().name() == "div"
+            && matches!(node.attribute("id"), Some("book-columns") | Some("book-inner"));
+        if !is_wrapper && !has_class(node, KOBO_SPAN_CLASS) {
+            continue;
+        }
+        let ((open_start, open_end), (close_start, close_end)) = tag_ranges(node, xhtml);
+        edits.push((open_start, open_end, String::new()));
+        edits.push((close_start, close_end, String::new()));
+    }
+    Ok(text_edits_applied(xhtml, edits))
+}
+
+/// Copy the archive, rewriting its XHTML documents with `transform`
+fn rewrite_epub(source: &Path, target: &Path, transform: fn(&str) -> Result<String, String>) -> Result<(), String> {
+    let package = EpubPackage::open(source)?;
+    let documents: HashSet<String> = package
+        .manifest
+        .iter()
+        .filter(|item| item.media_type == "application/xhtml+xml")
+        .map(|item| item.href.clone())
+        .collect();
+
+    let input = File::open(source).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+    let mut archive = ZipArchive::new(input).map_err(|e| format!("Failed to read EPUB archive: {}", e))?;
+    let output = File::create(target).map_err(|e| format!("Failed to create output file: {}", e))?;
+    let mut writer = ZipWriter::new(output);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for index in 0..archive.len() {
+        let name = archive
+            .by_index_raw(index)
+            .map_err(|e| format!("Failed to read EPUB entry: {}", e))?
+            .name()
+            .to_string();
+
+        if !documents.contains(&name) {
+            // Stored entries such as mimetype keep their compression and position
+            let entry = archive
+                .by_index_raw(index)
+                .map_err(|e| format!("Failed to read EPUB entry: {}", e))?;
+            writer
+                .raw_copy_file(entry)
+                .map_err(|e| format!("Failed to copy {}: {}", name, e))?;
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        archive
+            .by_index(index)
+            .and_then(|mut entry| entry.read_to_end(&mut bytes).map_err(Into::into))
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        let xhtml = transform(&prepare_xml(&bytes)).map_err(|e| format!("Failed to convert {}: {}", name, e))?;
+        writer
+            .start_file(name.as_str(), options)
+            .and_then(|_| writer.write_all(xhtml.as_bytes()).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+
+    writer.finish().map_err(|e| format!("Failed to write output file: {}", e))?;
+    Ok(())
+}
+
+/// Convert an EPUB to a Kobo KEPUB
+pub fn epub_to_kepub(epub_path: &Path, kepub_path: &Path) -> Result<(), String> {
+    rewrite_epub(epub_path, kepub_path, add_kobo_spans)
+}
+
+/// Convert a Kobo KEPUB back to a plain EPUB
+pub fn kepub_to_epub(kepub_path: &Path, epub_path: &Path) -> Result<(), String> {
+    rewrite_epub(kepub_path, epub_path, remove_kobo_spans)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::epub_package::write_test_epub;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(EbookFormat::from_path(Path::new("/b/Dune.EPUB")), Some(EbookFormat::Epub));
+        assert_eq!(EbookFormat::from_path(Path::new("Dune.kepub.epub")), Some(EbookFormat::Kepub));
+        assert_eq!(EbookFormat::from_path(Path::new("Dune.htm")), Some(EbookFormat::Html));
+        assert_eq!(EbookFormat::from_path(Path::new("Dune.azw3")), Some(EbookFormat::Azw3));
+        assert_eq!(EbookFormat::from_path(Path::new("Dune")), None);
+        assert_eq!(EbookFormat::from_path(Path::new("Dune.fb2")), None);
+    }
+
+    #[test]
+    fn test_conversion_matrix() {
+        use EbookFormat::*;
+
+        assert!(supports("calibre", Epub, Mobi));
+        assert!(supports("calibre", Mobi, Epub));
+        assert!(!supports("calibre", Epub, Kepub));
+        assert!(!supports("pandoc", Epub, Mobi));
+        assert!(supports("native", Kepub, Pdf));
+        assert!(!supports("native", Epub, Epub));
+
+        assert_eq!(preferred_converter(&["pandoc", "native"], Epub, Pdf), Some("pandoc"));
+        assert_eq!(preferred_converter(&["calibre", "native"], Epub, Kepub), Some("native"));
+        assert_eq!(preferred_converter(&["native"], Epub, Mobi), None);
+
+        let paths = conversion_paths(&["native"]);
+        assert_eq!(paths.len(), 6);
+        assert!(paths.iter().all(|p| p.converters == vec!["native"]));
+    }
+
+    #[test]
+    fn test_kobo_spans_round_trip() {
+        let xhtml = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><h1>Dune</h1><p>Fear is <i>the</i> mind-killer.</p><p> </p></body></html>"#;
+        let kepub = add_kobo_spans(xhtml).unwrap();
+        assert_eq!(
+            kepub,
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><h1><span class="koboSpan" id="kobo.1.1">Dune</span></h1><p><span class="koboSpan" id="kobo.2.1">Fear is <i>the</i> mind-killer.</span></p><p> </p></body></html>"#
+        );
+        assert_eq!(add_kobo_spans(&kepub).unwrap(), kepub);
+        assert_eq!(remove_kobo_spans(&kepub).unwrap(), xhtml);
+
+    }
+
+    #[test]
+    fn test_epub_to_kepub() {
+        let dir = std::env::temp_dir().join(format!("stomy-kepub-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let epub = dir.join("dune.epub");
+        write_test_epub(
+            &epub,
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata/>
+<manifest><item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/></manifest>
+<spine><itemref idref="c1"/></spine></package>"#,
+            &[("OEBPS/c1.xhtml", r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><p>Fear</p></body></html>"#)],
+        );
+
+        let kepub = dir.join("dune.kepub.epub");
+        epub_to_kepub(&epub, &kepub).unwrap();
+        let mut archive = ZipArchive::new(File::open(&kepub).unwrap()).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!((mimetype.name(), mimetype.compression()), ("mimetype", CompressionMethod::Stored));
+        drop(mimetype);
+        let mut chapter = String::new();
+        archive.by_name("OEBPS/c1.xhtml").unwrap().read_to_string(&mut chapter).unwrap();
+        assert!(chapter.contains(r#"<p><span class="koboSpan" id="kobo.1.1">Fear</span></p>"#));
+
+        let back = dir.join("back.epub");
+        kepub_to_epub(&kepub, &back).unwrap();
+        let mut archive = ZipArchive::new(File::open(&back).unwrap()).unwrap();
+        let mut chapter = String::new();
+        archive.by_name("OEBPS/c1.xhtml").unwrap().read_to_string(&mut chapter).unwrap();
+        assert!(chapter.contains("<p>Fear</p>"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_kepubify_wrappers() {
+        let wrapped = r#"<html><body><div id="book-columns"><div id="book-inner"><p>x</p></div></div></body></html>"#;
+        assert_eq!(remove_kobo_spans(wrapped).unwrap(), "<html><body><p>x</p></body></html>");
+    }
+}
@@ -0,0 +1,490 @@
+/// EPUB Chapter Selection
+/// Lists the chapters of an EPUB (spine documents and table of contents entries)
+/// and writes a copy holding only the selected ones, so a single chapter or a
+/// range of them can be exported. Links to removed chapters are unlinked;
+/// images, stylesheets and links between the kept chapters are left untouched.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/epub_chapters.rs (needs epub_package.rs)
+/// 2. Add `mod epub_chapters;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tauri::command;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::epub_package::{parse_xml, prepare_xml, resolve_href, strip_fragment, EpubPackage};
+
+static SUBSET_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SpineChapter {
+    pub index: usize,
+    pub idref: String,
+    pub href: String,
+    pub title: Option<String>, // First table of contents entry pointing into the document
+    pub linear: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TocChapter {
+    pub index: usize,
+    pub title: String,
+    pub href: String,
+    pub level: usize,
+    pub spine_index: Option<usize>, // None when the entry points outside the reading order
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterList {
+    pub spine: Vec<SpineChapter>,
+    pub toc: Vec<TocChapter>,
+}
+
+/// Chapters to export, by spine idref and/or table of contents index; both add up
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterSelection {
+    #[serde(default)]
+    pub spine: Vec<String>,
+    #[serde(default)]
+    pub toc: Vec<usize>,
+}
+
+// ============================================================================
+// Listing
+// ============================================================================
+
+fn spine_index(package: &EpubPackage, href: &str) -> Option<usize> {
+    let href = strip_fragment(href);
+    package.spine.iter().position(|s| s.href.as_deref() == Some(href))
+}
+
+fn chapter_list(package: &EpubPackage) -> ChapterList {
+    let spine = package
+        .spine
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let href = item.href.clone()?;
+            Some(SpineChapter {
+                index,
+                idref: item.idref.clone(),
+                title: package
+                    .toc
+                    .iter()
+                    .find(|e| strip_fragment(&e.href) == href)
+                    .map(|e| e.title.clone()),
+                href,
+                linear: item.linear,
+            })
+        })
+        .collect();
+    let toc = package
+        .toc
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| TocChapter {
+            index,
+            title: entry.title.clone(),
+            href: entry.href.clone(),
+            level: entry.level,
+            spine_index: spine_index(package, &entry.href),
+        })
+        .collect();
+    ChapterList { spine, toc }
+}
+
+pub fn list_chapters(path: &Path) -> Result<ChapterList, String> {
+    Ok(chapter_list(&EpubPackage::open(path)?))
+}
+
+// ============================================================================
+// Selection
+// ============================================================================
+
+/// Archive paths of the selected spine documents. A table of contents entry covers
+/// its document and the following ones, up to the next entry of the same or a
+/// higher level that starts in a later document.
+fn selected_documents(package: &EpubPackage, selection: &ChapterSelection) -> Result<HashSet<String>, String> {
+    if selection.spine.is_empty() && selection.toc.is_empty() {
+        return Err("No chapters selected".to_string());
+    }
+
+    let mut selected = HashSet::new();
+    for idref in &selection.spine {
+        let href = package
+            .spine
+            .iter()
+            .find(|s| &s.idref == idref)
+            .and_then(|s| s.href.clone())
+            .ok_or_else(|| format!("Unknown spine item: {}", idref))?;
+        selected.insert(href);
+    }
+
+    for &index in &selection.toc {
+        let entry = package
+            .toc
+            .get(index)
+            .ok_or_else(|| format!("Table of contents entry {} does not exist", index))?;
+        let start = spine_index(package, &entry.href)
+            .ok_or_else(|| format!("'{}' is not part of the reading order", entry.title))?;
+        let end = package.toc[index + 1..]
+            .iter()
+            .filter(|next| next.level <= entry.level)
+            .filter_map(|next| spine_index(package, &next.href))
+            .find(|&i| i > start)
+            .unwrap_or(package.spine.len());
+        selected.extend(package.spine[start..end].iter().filter_map(|s| s.href.clone()));
+    }
+    Ok(selected)
+}
+
+// ============================================================================
+// Subset Writing
+// ============================================================================
+
+/// Remove byte ranges from a document; ranges nested in an earlier one are skipped
+fn remove_ranges(text: &str, mut ranges: Vec<Range<usize>>) -> String {
+    ranges.sort_by_key(|r| r.start);
+    let mut out = String::with_capacity(text.len());
+    let mut position = 0;
+    for range in ranges {
+        if range.start < position {
+            continue;
+        }
+        out.push_str(&text[position..range.start]);
+        position = range.end;
+    }
+    out.push_str(&text[position..]);
+    out
+}
+
+/// Attribute span with its leading whitespace, so removing it leaves a tidy tag
+fn attribute_span(text: &str, range: Range<usize>) -> Range<usize> {
+    text[..range.start].trim_end().len()..range.end
+}
+
+/// Edits to one kept file of the subset; None when it stays as it is
+fn edited_document(
+    name: &str,
+    text: &str,
+    is_opf: bool,
+    removed: &HashSet<String>,
+    removed_spine: &HashSet<String>,
+) -> Result<Option<String>, String> {
+    let doc = parse_xml(text).map_err(|e| format!("Failed to parse {}: {}", name, e))?;
+    let points_to_removed = |href: &str| removed.contains(strip_fragment(&resolve_href(name, href)));
+    let mut ranges = Vec::new();
+
+    for node in doc.descendants().filter(|n| n.is_element()) {
+        match node.tag_name().name() {
+            // Package document: drop the removed documents from the manifest, spine and guide
+            "item" if is_opf && node.attribute("href").is_some_and(points_to_removed) => ranges.push(node.range()),
+            "reference" if is_opf && node.attribute("href").is_some_and(points_to_removed) => {
+                ranges.push(node.range())
+            }
+            "itemref" if is_opf && node.attribute("idref").is_some_and(|id| removed_spine.contains(id)) => {
+                ranges.push(node.range())
+            }
+            // NCX: drop the entries whose whole subtree points to removed documents
+            "navPoint" => {
+                let mut sources = node
+                    .descendants()
+                    .filter(|n| n.tag_name().name() == "content")
+                    .filter_map(|n| n.attribute("src"))
+                    .peekable();
+                if sources.peek().is_some() && sources.all(points_to_removed) {
+                    ranges.push(node.range());
+                }
+            }
+            // XHTML, nav document included: unlink references to removed documents
+            _ if !is_opf => {
+                if let Some(attribute) = node
+                    .attributes()
+                    .find(|a| a.name() == "href" && !a.value().starts_with('#') && points_to_removed(a.value()))
+                {
+                    ranges.push(attribute_span(text, attribute.range()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if ranges.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(remove_ranges(text, ranges)))
+}
+
+/// Copy the EPUB with only the selected chapters in its reading order
+pub fn write_chapter_subset(source: &Path, target: &Path, selection: &ChapterSelection) -> Result<(), String> {
+    let package = EpubPackage::open(source)?;
+    let selected = selected_documents(&package, selection)?;
+
+    let nav_href = package
+        .manifest
+        .iter()
+        .find(|i| i.properties.iter().any(|p| p == "nav"))
+        .map(|i| i.href.clone());
+    let removed_spine: HashSet<String> = package
+        .spine
+        .iter()
+        .filter(|s| s.href.as_ref().is_some_and(|h| !selected.contains(h)))
+        .map(|s| s.idref.clone())
+        .collect();
+    // The nav document leaves the reading order but stays in the package
+    let removed: HashSet<String> = package
+        .spine
+        .iter()
+        .filter(|s| removed_spine.contains(&s.idref))
+        .filter_map(|s| s.href.clone())
+        .filter(|h| Some(h) != nav_href.as_ref())
+        .collect();
+    let editable: HashSet<&str> = package
+        .manifest
+        .iter()
+        .filter(|i| {
+            matches!(
+                i.media_type.as_str(),
+                "application/xhtml+xml" | "application/x-dtbncx+xml"
+            )
+        })
+        .map(|i| i.href.as_str())
+        .chain([package.opf_path.as_str()])
+        .collect();
+
+    let input = File::open(source).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+    let mut archive = ZipArchive::new(input).map_err(|e| format!("Failed to read EPUB archive: {}", e))?;
+    let output = File::create(target).map_err(|e| format!("Failed to create output file: {}", e))?;
+    let mut writer = ZipWriter::new(output);
+    // A fixed timestamp keeps the copy identical for the same selection, which the conversion cache relies on
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+
+    for index in 0..archive.len() {
+        let name = archive
+            .by_index_raw(index)
+            .map_err(|e| format!("Failed to read EPUB entry: {}", e))?
+            .name()
+            .to_string();
+        if removed.contains(&name) {
+            continue;
+        }
+
+        let edited = if editable.contains(name.as_str()) {
+            let mut bytes = Vec::new();
+            archive
+                .by_index(index)
+                .and_then(|mut entry| entry.read_to_end(&mut bytes).map_err(Into::into))
+                .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+            let text = prepare_xml(&bytes);
+            edited_document(&name, &text, name == package.opf_path, &removed, &removed_spine)?
+        } else {
+            None
+        };
+
+        match edited {
+            Some(text) => writer
+                .start_file(name.as_str(), options)
+                .and_then(|_| writer.write_all(text.as_bytes()).map_err(Into::into))
+                .map_err(|e| format!("Failed to write {}: {}", name, e))?,
+            None => {
+                let entry = archive
+                    .by_index_raw(index)
+                    .map_err(|e| format!("Failed to read EPUB entry: {}", e))?;
+                writer
+                    .raw_copy_file(entry)
+                    .map_err(|e| format!("Failed to copy {}: {}", name, e))?;
+            }
+        }
+    }
+    writer.finish().map_err(|e| format!("Failed to write output file: {}", e))?;
+    Ok(())
+}
+
+/// Temporary EPUB with the selected chapters, under the original file name; removed on drop
+pub struct ChapterSubset {
+    dir: PathBuf,
+    pub path: PathBuf,
+}
+
+impl ChapterSubset {
+    pub fn create(source: &Path, selection: &ChapterSelection) -> Result<Self, String> {
+        let dir = std::env::temp_dir().join(format!(
+            "stomy-chapters-{}-{}",
+            std::process::id(),
+            SUBSET_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create chapter folder: {}", e))?;
+        let path = dir.join(source.file_name().unwrap_or("book.epub".as_ref()));
+        let subset = ChapterSubset { dir, path };
+        write_chapter_subset(source, &subset.path, selection)?;
+        Ok(subset)
+    }
+}
+
+impl Drop for ChapterSubset {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// List the spine documents and table of contents entries of an EPUB
+#[command]
+pub fn list_epub_chapters(epub_path: String) -> Result<ChapterList, String> {
+    list_chapters(Path::new(&epub_path))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::epub_package::write_test_epub;
+
+    const PACKAGE: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata/>
+<manifest>
+  <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+  <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+  <item id="map" href="Images/map.png" media-type="image/png"/>
+  <item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>
+  <item id="c2" href="Text/c2.xhtml" media-type="application/xhtml+xml"/>
+  <item id="c3" href="Text/c3.xhtml" media-type="application/xhtml+xml"/>
+</manifest>
+<spine toc="ncx"><itemref idref="c1"/><itemref idref="c2"/><itemref idref="c3"/></spine></package>"#;
+
+    fn write_book(path: &Path) {
+        let chapter = |title: &str, body: &str| {
+            format!(
+                r#"<html xmlns="http://www.w3.org/1999/xhtml"><head><title>{0}</title></head><body><h1>{0}</h1>{1}</body></html>"#,
+                title, body
+            )
+        };
+        write_test_epub(
+            path,
+            PACKAGE,
+            &[
+                (
+                    "OEBPS/nav.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+<nav epub:type="toc"><ol>
+  <li><a href="Text/c1.xhtml">Book One</a><ol><li><a href="Text/c1.xhtml#s2">Part Two</a></li></ol></li>
+  <li><a href="Text/c2.xhtml">Book Two</a></li>
+  <li><a href="Text/c3.xhtml">Appendix</a></li>
+</ol></nav></body></html>"#
+                        .to_string(),
+                ),
+                (
+                    "OEBPS/toc.ncx",
+                    r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+<navPoint id="p1"><navLabel><text>Book One</text></navLabel><content src="Text/c1.xhtml"/></navPoint>
+<navPoint id="p2"><navLabel><text>Book Two</text></navLabel><content src="Text/c2.xhtml"/></navPoint>
+<navPoint id="p3"><navLabel><text>Appendix</text></navLabel><content src="Text/c3.xhtml"/></navPoint>
+</navMap></ncx>"#
+                        .to_string(),
+                ),
+                ("OEBPS/Images/map.png", "png".to_string()),
+                ("OEBPS/Text/c1.xhtml", chapter("Book One", r#"<p><a href="c3.xhtml#terms">Terms</a></p>"#)),
+                (
+                    "OEBPS/Text/c2.xhtml",
+                    chapter("Book Two", r#"<p><img src="../Images/map.png"/><a href="c3.xhtml">Terms</a> <a href="c1.xhtml">Back</a></p>"#),
+                ),
+                ("OEBPS/Text/c3.xhtml", chapter("Appendix", r#"<p id="terms">Terms</p>"#)),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_list_and_select_chapters() {
+        let dir = std::env::temp_dir().join(format!("stomy-chapters-list-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let book = dir.join("dune.epub");
+        write_book(&book);
+
+        let list = list_chapters(&book).unwrap();
+        assert_eq!(list.spine.len(), 3);
+        assert_eq!(list.spine[1].title.as_deref(), Some("Book Two"));
+        assert_eq!(list.toc.len(), 4);
+        assert_eq!(list.toc[1].spine_index, Some(0));
+        assert_eq!(list.toc[1].level, 1);
+
+        let package = EpubPackage::open(&book).unwrap();
+        let select = |spine: &[&str], toc: &[usize]| {
+            let selection = ChapterSelection {
+                spine: spine.iter().map(|s| s.to_string()).collect(),
+                toc: toc.to_vec(),
+            };
+            selected_documents(&package, &selection).map(|s| {
+                let mut s: Vec<String> = s.into_iter().collect();
+                s.sort();
+                s
+            })
+        };
+        // A section inside the first document selects only that document
+        assert_eq!(select(&[], &[1]).unwrap(), vec!["OEBPS/Text/c1.xhtml"]);
+        assert_eq!(select(&[], &[0]).unwrap(), vec!["OEBPS/Text/c1.xhtml"]);
+        assert_eq!(select(&["c3"], &[2]).unwrap(), vec!["OEBPS/Text/c2.xhtml", "OEBPS/Text/c3.xhtml"]);
+        assert!(select(&[], &[]).is_err());
+        assert!(select(&["c9"], &[]).is_err());
+        assert!(select(&[], &[4]).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_chapter_subset() {
+        let dir = std::env::temp_dir().join(format!("stomy-chapters-subset-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let book = dir.join("dune.epub");
+        write_book(&book);
+
+        let selection = ChapterSelection { spine: vec!["c1".to_string(), "c2".to_string()], toc: Vec::new() };
+        let subset = ChapterSubset::create(&book, &selection).unwrap();
+        assert_eq!(subset.path.file_name(), book.file_name());
+
+        let mut package = EpubPackage::open(&subset.path).unwrap();
+        let spine: Vec<&str> = package.spine.iter().map(|s| s.idref.as_str()).collect();
+        assert_eq!(spine, vec!["c1", "c2"]);
+        assert!(package.manifest.iter().all(|i| i.id != "c3"));
+        assert!(!package.has_resource("OEBPS/Text/c3.xhtml"));
+        assert!(package.has_resource("OEBPS/Images/map.png"));
+        // The table of contents keeps only the exported chapters
+        let titles: Vec<&str> = package.toc.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["Book One", "Part Two", "Book Two"]);
+
+        let c2 = String::from_utf8(package.read_resource("OEBPS/Text/c2.xhtml").unwrap()).unwrap();
+        assert!(c2.contains(r#"<img src="../Images/map.png"/><a>Terms</a> <a href="c1.xhtml">Back</a>"#));
+        let ncx = String::from_utf8(package.read_resource("OEBPS/toc.ncx").unwrap()).unwrap();
+        assert!(ncx.contains("Book Two") && !ncx.contains("Appendix"));
+
+        // Same selection, same bytes
+        let again = ChapterSubset::create(&book, &selection).unwrap();
+        assert_eq!(std::fs::read(&subset.path).unwrap(), std::fs::read(&again.path).unwrap());
+
+        let subset_dir = subset.dir.clone();
+        drop(subset);
+        assert!(!subset_dir.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
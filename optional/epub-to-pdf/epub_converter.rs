@@ -1,4 +1,6 @@
 /// EPUB to PDF Conversion Module
+/// Also converts between the other ebook formats listed in ebook_formats.rs,
+/// so every plugin shares the same converters.
 /// This file should be placed in the main Stomy project's src-tauri/src/ directory
 ///
 /// Instructions:
@@ -8,307 +10,1580 @@
 /// 4. Add the dependencies listed in Cargo.toml
 
 use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use tauri::command;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Emitter};
+
+use super::conversion_cache;
+use super::conversion_diagnostics::{self, ConversionDiagnostic};
+use super::custom_style::{calibre_style_args, inject_into_epub, pandoc_style_args, CustomStyle, StyleWorkspace};
+use super::converter_registry::{self, converter_command, PdfEngine};
+use super::converter_sandbox::{self, SandboxPolicy};
+use super::ebook_formats::{self, epub_to_kepub, kepub_to_epub, ConversionPath, EbookFormat};
+use super::epub_chapters::{ChapterSelection, ChapterSubset};
+use super::epub_native::{render_epub_to_pdf, write_epub_text};
+use super::epub_validator;
+use super::layout_profiles::{self, LayoutProfile};
+use super::output_naming::{resolve_outputs, OutputNaming, ResolvedOutput};
+use super::pdf_annotations::PdfAnnotations;
+use super::pdf_postprocess::{self, postprocess_pdf};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct ConversionResult {
     pub success: bool,
     pub output_path: Option<String>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub size_before: Option<u64>, // PDF size in bytes as the converter wrote it
+    #[serde(default)]
+    pub size_after: Option<u64>, // PDF size in bytes after post-processing
+    #[serde(default)]
+    pub cached: bool, // Copied from the conversion cache instead of converted
+    #[serde(default)]
+    pub diagnostic: Option<ConversionDiagnostic>, // Error code and suggested fix of a failure
+    #[serde(default)]
+    pub warnings: Vec<String>, // What went wrong without failing the conversion (fallback font, ...)
+}
+
+impl ConversionResult {
+    fn failure(error: String) -> Self {
+        ConversionResult {
+            success: false,
+            output_path: None,
+            error: Some(error),
+            size_before: None,
+            size_after: None,
+            cached: false,
+            diagnostic: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn success(path: &Path, warnings: Vec<String>) -> Self {
+        ConversionResult {
+            success: true,
+            output_path: Some(path.to_string_lossy().to_string()),
+            error: None,
+            size_before: None,
+            size_after: None,
+            cached: false,
+            diagnostic: None,
+            warnings,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct ConverterInfo {
     pub available: bool,
-    pub converter: String, // "calibre", "pandoc", or "none"
+    pub converter: String, // "calibre", "pandoc", "native" or "none"
     pub version: Option<String>,
     pub path: Option<String>,
+    #[serde(default)]
+    pub conversions: Vec<ConversionPath>, // Every source/target pair the installed converters handle
+    #[serde(default)]
+    pub pdf_engines: Vec<PdfEngine>, // Installed engines Pandoc can write PDF with, best first
+    #[serde(default)]
+    pub error: Option<String>, // Why the converter cannot be used (not found, too old, ...)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConversionJob {
+    #[serde(alias = "epubPath")]
     pub epub_path: String,
-    pub pdf_path: String,
+    #[serde(default, alias = "pdfPath")]
+    pub pdf_path: Option<String>, // None to build it from the output naming template
+    #[serde(default, alias = "bookId")]
+    pub book_id: Option<String>,
+    #[serde(default, alias = "layoutProfile")]
+    pub layout_profile: Option<String>, // Overrides the profile of the settings for this job
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct BatchConversionResult {
     pub success: bool,
     pub converted: usize,
     pub failed: usize,
     pub errors: Vec<ConversionError>,
     pub output_paths: Vec<String>,
+    #[serde(default)]
+    pub skipped: usize, // Existing files left alone by the "skip" collision policy
+    #[serde(default)]
+    pub resolved: Vec<ResolvedOutput>, // Target chosen for every job, in input order
+    #[serde(default)]
+    pub jobs: Vec<BatchJobResult>, // Outcome of every job, in input order
 }
 
+/// Outcome and wall-clock time of one batch job
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchJobResult {
+    pub source_path: String,
+    pub target_path: String,
+    pub status: String, // "converted", "failed" or "skipped"
+    pub error: Option<String>,
+    #[serde(default)]
+    pub diagnostic: Option<ConversionDiagnostic>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct ConversionError {
     pub file: String,
     pub error: String,
 }
 
-/// Check if ebook-convert (Calibre) is available
-fn check_calibre() -> Option<ConverterInfo> {
-    let result = if cfg!(target_os = "windows") {
-        Command::new("where").arg("ebook-convert").output()
-    } else {
-        Command::new("which").arg("ebook-convert").output()
-    };
+/// Page margins in points
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PdfMargins {
+    pub top: f64,
+    pub bottom: f64,
+    pub left: f64,
+    pub right: f64,
+}
 
-    if let Ok(output) = result {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-
-            // Try to get version
-            let version = Command::new("ebook-convert")
-                .arg("--version")
-                .output()
-                .ok()
-                .and_then(|v| {
-                    String::from_utf8(v.stdout)
-                        .ok()
-                        .map(|s| s.trim().to_string())
-                });
-
-            return Some(ConverterInfo {
-                available: true,
-                converter: "calibre".to_string(),
-                version,
-                path: Some(path),
-            });
+/// Rust side of the TypeScript `EpubToPdfSettings` (UI-only fields are ignored)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EpubToPdfSettings {
+    pub page_size: String,         // "A4", "Letter" or "auto"
+    pub margins: PdfMargins,
+    pub compression_level: String, // "none", "low", "medium" or "high"
+    pub include_table_of_contents: bool,
+    pub preserve_images: bool,
+    pub timeout_seconds: u64, // 0 disables the time limit
+    pub linearize: bool,      // Fast web view (needs qpdf)
+    pub pdf_a: bool,          // PDF/A-2b for archival
+    pub parallel_jobs: usize, // Batch conversions at once, 0 for one per CPU
+    pub max_latex_jobs: usize, // LaTeX runs at once, 0 for no separate limit
+    pub cache_enabled: bool,  // Reuse earlier conversions of the same file and settings
+    pub cache_max_mb: u64,    // Conversion cache size before the oldest entries are evicted
+    pub layout_profile: String, // Print layout profile id, empty for page size and margins
+    pub custom_css: String,         // Stylesheet applied on top of the book's, empty for none
+    pub custom_fonts: Vec<String>,  // TrueType/OpenType files; the body font comes from them
+    pub pandoc_template: String,    // Pandoc LaTeX template, empty for the default one
+    pub pandoc_pdf_engine: String,  // xelatex, lualatex, weasyprint, ...; empty for the best installed one
+    pub sandbox_converters: bool,   // Run Calibre and Pandoc in the converter sandbox
+    pub require_network_isolation: bool, // Refuse to run them when the network cannot be cut off
+    pub max_cpu_seconds: u64,       // CPU time of a sandboxed converter, 0 for no limit
+    pub max_output_mb: u64,         // Size of any file it writes, 0 for no limit
+}
+
+impl Default for EpubToPdfSettings {
+    /// Matches the fixed options used before settings were configurable
+    fn default() -> Self {
+        EpubToPdfSettings {
+            page_size: "A4".to_string(),
+            margins: PdfMargins {
+                top: 72.0,
+                bottom: 72.0,
+                left: 72.0,
+                right: 72.0,
+            },
+            compression_level: "medium".to_string(),
+            include_table_of_contents: false,
+            preserve_images: true,
+            timeout_seconds: 1800,
+            linearize: false,
+            pdf_a: false,
+            parallel_jobs: 0,
+            max_latex_jobs: 2,
+            cache_enabled: true,
+            cache_max_mb: 1024,
+            layout_profile: String::new(),
+            custom_css: String::new(),
+            custom_fonts: Vec::new(),
+            pandoc_template: String::new(),
+            pandoc_pdf_engine: String::new(),
+            sandbox_converters: true,
+            require_network_isolation: false,
+            max_cpu_seconds: 3600,
+            max_output_mb: 4096,
         }
     }
-    None
 }
 
-/// Check if pandoc is available
-fn check_pandoc() -> Option<ConverterInfo> {
-    let result = if cfg!(target_os = "windows") {
-        Command::new("where").arg("pandoc").output()
-    } else {
-        Command::new("which").arg("pandoc").output()
-    };
+/// Check which converter is available (Calibre preferred, then Pandoc, then the native renderer)
+/// and list the conversions the installed converters support
+#[command]
+pub fn check_epub_converter() -> ConverterInfo {
+    let converters = converter_registry::converters(false);
+    let available: Vec<&str> = converters
+        .iter()
+        .filter(|c| c.available)
+        .map(|c| c.converter.as_str())
+        .collect();
+    let conversions = ebook_formats::conversion_paths(&available);
 
-    if let Ok(output) = result {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-
-            // Try to get version
-            let version = Command::new("pandoc")
-                .arg("--version")
-                .output()
-                .ok()
-                .and_then(|v| {
-                    String::from_utf8(v.stdout)
-                        .ok()
-                        .and_then(|s| s.lines().next().map(|l| l.to_string()))
-                });
-
-            return Some(ConverterInfo {
-                available: true,
-                converter: "pandoc".to_string(),
-                version,
-                path: Some(path),
-            });
+    // The built-in renderer is always available, so there is always a first one
+    let mut info = converters
+        .iter()
+        .find(|c| c.available)
+        .cloned()
+        .unwrap_or(ConverterInfo {
+            available: false,
+            converter: "none".to_string(),
+            version: None,
+            path: None,
+            conversions: Vec::new(),
+            pdf_engines: Vec::new(),
+            error: None,
+        });
+    info.conversions = conversions;
+    info
+}
+
+/// Smallest supported page width (A4), used to bound the margins
+const MIN_PAGE_WIDTH_PT: f64 = 595.0;
+const MIN_PAGE_HEIGHT_PT: f64 = 792.0;
+
+/// Check the settings can be honoured by the selected converter
+fn validate_settings(settings: &EpubToPdfSettings, converter: &str) -> Result<(), String> {
+    // A layout profile replaces the page size and margins
+    let profile = layout_profiles::selected_profile(settings)?;
+    if profile.is_none() && !matches!(settings.page_size.as_str(), "A4" | "Letter" | "auto") {
+        return Err(format!("Unsupported page size: {}", settings.page_size));
+    }
+    if !matches!(settings.compression_level.as_str(), "none" | "low" | "medium" | "high") {
+        return Err(format!("Unsupported compression level: {}", settings.compression_level));
+    }
+    let engine = settings.pandoc_pdf_engine.as_str();
+    if !engine.is_empty() && !converter_registry::PDF_ENGINES.contains(&engine) {
+        return Err(format!("Unknown PDF engine: {}", engine));
+    }
+
+    CustomStyle::from_settings(settings)?;
+    if settings.linearize && pdf_postprocess::qpdf_path().is_none() {
+        return Err("Fast web view needs qpdf: install it or turn off linearisation".to_string());
+    }
+
+    let m = &settings.margins;
+    if profile.is_none() {
+        if [m.top, m.bottom, m.left, m.right].iter().any(|v| !v.is_finite() || *v < 0.0) {
+            return Err("Margins must be positive numbers".to_string());
+        }
+        if m.left + m.right >= MIN_PAGE_WIDTH_PT * 0.8 || m.top + m.bottom >= MIN_PAGE_HEIGHT_PT * 0.8 {
+            return Err("Margins leave no room for the page content".to_string());
+        }
+    }
+
+    match converter {
+        "calibre" => {
+            if !settings.preserve_images {
+                return Err("Calibre cannot drop images: enable 'Preserve Images'".to_string());
+            }
         }
+        "pandoc" => {
+            if profile.is_none() && settings.page_size == "auto" {
+                return Err("Pandoc needs an explicit page size (A4 or Letter)".to_string());
+            }
+            if !settings.preserve_images {
+                return Err("Pandoc cannot drop images: enable 'Preserve Images'".to_string());
+            }
+        }
+        "native" => {}
+        _ => return Err(format!("Unknown converter: {}", converter)),
     }
-    None
+
+    Ok(())
 }
 
-/// Check which converter is available (Calibre preferred, then Pandoc)
-#[command]
-pub fn check_epub_converter() -> ConverterInfo {
-    // Try Calibre first (better quality conversion)
-    if let Some(info) = check_calibre() {
-        return info;
-    }
-
-    // Try Pandoc as fallback
-    if let Some(info) = check_pandoc() {
-        return info;
-    }
-
-    // No converter found
-    ConverterInfo {
-        available: false,
-        converter: "none".to_string(),
-        version: None,
-        path: None,
-    }
-}
-
-/// Convert EPUB to PDF using ebook-convert (Calibre)
-fn convert_with_calibre(epub_path: &Path, pdf_path: &Path) -> ConversionResult {
-    let output = Command::new("ebook-convert")
-        .arg(epub_path)
-        .arg(pdf_path)
-        .arg("--paper-size")
-        .arg("a4")
-        .arg("--pdf-default-font-size")
-        .arg("12")
-        .arg("--pdf-mono-font-size")
-        .arg("12")
-        .arg("--margin-top")
-        .arg("72")
-        .arg("--margin-bottom")
-        .arg("72")
-        .arg("--margin-left")
-        .arg("72")
-        .arg("--margin-right")
-        .arg("72")
-        .arg("--preserve-cover-aspect-ratio")
-        .output();
-
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                ConversionResult {
-                    success: true,
-                    output_path: Some(pdf_path.to_string_lossy().to_string()),
-                    error: None,
-                }
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr).to_string();
-                ConversionResult {
-                    success: false,
-                    output_path: None,
-                    error: Some(format!("Calibre conversion failed: {}", error_msg)),
+/// ebook-convert arguments for the given settings
+fn calibre_args(settings: &EpubToPdfSettings) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    let profile = layout_profiles::selected_profile(settings).ok().flatten();
+
+    let mut margins = settings.margins.clone();
+    let mut font_size = "12".to_string();
+    match (&profile, settings.page_size.as_str()) {
+        (Some(profile), _) => {
+            args.extend(["--unit".to_string(), "point".to_string(), "--custom-size".to_string()]);
+            args.push(format!("{}x{}", profile.width, profile.height));
+            // Calibre has no mirrored margins: split the gutter between both sides
+            margins = profile.margins.clone();
+            margins.left += profile.gutter / 2.0;
+            margins.right += profile.gutter / 2.0;
+            // Calibre font sizes are CSS pixels
+            font_size = ((profile.font_size / 0.75).round() as u32).to_string();
+            if profile.hyphenation {
+                args.push("--pdf-hyphenate".to_string());
+            }
+        }
+        // Let the output profile pick the page size
+        (None, "auto") => args.push("--use-profile-size".to_string()),
+        (None, size) => {
+            args.push("--paper-size".to_string());
+            args.push(size.to_lowercase());
+        }
+    }
+
+    for (flag, value) in [
+        ("--pdf-default-font-size", font_size.clone()),
+        ("--pdf-mono-font-size", font_size),
+        ("--pdf-page-margin-top", margins.top.to_string()),
+        ("--pdf-page-margin-bottom", margins.bottom.to_string()),
+        ("--pdf-page-margin-left", margins.left.to_string()),
+        ("--pdf-page-margin-right", margins.right.to_string()),
+    ] {
+        args.push(flag.to_string());
+        args.push(value);
+    }
+
+    // Calibre always compresses streams unless told not to; post-processing applies the level
+    if settings.compression_level == "none" {
+        args.push("--uncompressed-pdf".to_string());
+    }
+    if settings.include_table_of_contents {
+        args.push("--pdf-add-toc".to_string());
+    }
+
+    args.push("--preserve-cover-aspect-ratio".to_string());
+    args
+}
+
+/// The PDF engine from the settings, or the best installed one
+fn pandoc_engine(settings: &EpubToPdfSettings) -> Result<PdfEngine, String> {
+    let engine = converter_registry::pdf_engine(&settings.pandoc_pdf_engine)?;
+    if !settings.custom_fonts.is_empty() && !engine.supports_fonts() {
+        return Err(format!(
+            "Custom fonts need xelatex, lualatex or tectonic, not {}: install one or remove the fonts",
+            engine.name
+        ));
+    }
+    Ok(engine)
+}
+
+/// pandoc arguments for the given settings and PDF engine
+fn pandoc_args(settings: &EpubToPdfSettings, engine: &PdfEngine) -> Vec<String> {
+    let m = &settings.margins;
+    let zlib_level = match settings.compression_level.as_str() {
+        "none" => 0,
+        "low" => 3,
+        "medium" => 6,
+        _ => 9,
+    };
+
+    // The full path, so TeX installs outside the app's PATH work too
+    let mut args = vec![format!("--pdf-engine={}", engine.path)];
+    let profile = layout_profiles::selected_profile(settings).ok().flatten();
+    match profile {
+        _ if !engine.is_latex() => args.extend(pandoc_html_page_args(settings, profile.as_ref(), engine)),
+        Some(profile) => args.extend(pandoc_profile_args(&profile)),
+        None => args.extend([
+            "-V".to_string(),
+            format!("papersize={}", settings.page_size.to_lowercase()),
+            "-V".to_string(),
+            format!(
+                "geometry:top={}pt,bottom={}pt,left={}pt,right={}pt",
+                m.top, m.bottom, m.left, m.right
+            ),
+        ]),
+    }
+    // Only xelatex goes through xdvipdfmx; the other engines are compressed by post-processing
+    if engine.name == "xelatex" {
+        args.push(format!("--pdf-engine-opt=-output-driver=xdvipdfmx -z{}", zlib_level));
+    }
+
+    if settings.include_table_of_contents {
+        args.push("--toc".to_string());
+    }
+
+    args
+}
+
+/// pandoc variables for a layout profile; the gutter becomes a two-sided binding offset
+fn pandoc_profile_args(profile: &LayoutProfile) -> Vec<String> {
+    let m = &profile.margins;
+    let mut args = vec![
+        "-V".to_string(),
+        format!(
+            "geometry:paperwidth={}pt,paperheight={}pt,top={}pt,bottom={}pt,inner={}pt,outer={}pt,bindingoffset={}pt,twoside",
+            profile.width, profile.height, m.top, m.bottom, m.left, m.right, profile.gutter
+        ),
+        // The standard LaTeX classes only know 10, 11 and 12 pt
+        "-V".to_string(),
+        "documentclass=scrbook".to_string(),
+        "-V".to_string(),
+        format!("fontsize={}pt", profile.font_size),
+    ];
+    if !profile.hyphenation {
+        args.push("-V".to_string());
+        args.push("header-includes=\\hyphenpenalty=10000 \\exhyphenpenalty=10000".to_string());
+    }
+    args
+}
+
+/// Page size and margins for the HTML-based engines: WeasyPrint reads them from CSS,
+/// wkhtmltopdf from its own options
+fn pandoc_html_page_args(
+    settings: &EpubToPdfSettings,
+    profile: Option<&LayoutProfile>,
+    engine: &PdfEngine,
+) -> Vec<String> {
+    let css = match profile {
+        Some(profile) => {
+            let m = &profile.margins;
+            let inner = m.left + profile.gutter;
+            format!(
+                "@page {{ size: {}pt {}pt }} \
+                 @page :right {{ margin: {}pt {}pt {}pt {}pt }} \
+                 @page :left {{ margin: {}pt {}pt {}pt {}pt }} \
+                 body {{ font-size: {}pt; hyphens: {} }}",
+                profile.width,
+                profile.height,
+                m.top,
+                m.right,
+                m.bottom,
+                inner,
+                m.top,
+                inner,
+                m.bottom,
+                m.right,
+                profile.font_size,
+                if profile.hyphenation { "auto" } else { "manual" }
+            )
+        }
+        None => {
+            let m = &settings.margins;
+            format!(
+                "@page {{ size: {}; margin: {}pt {}pt {}pt {}pt }}",
+                settings.page_size, m.top, m.right, m.bottom, m.left
+            )
+        }
+    };
+    let mut args = vec!["-V".to_string(), format!("header-includes=<style>{}</style>", css)];
+
+    if engine.name == "wkhtmltopdf" {
+        // wkhtmltopdf has no mirrored margins: split the gutter between both sides
+        let mm = |pt: f64| format!("{:.1}mm", pt * 25.4 / 72.0);
+        let (m, gutter) = match profile {
+            Some(profile) => (profile.margins.clone(), profile.gutter),
+            None => (settings.margins.clone(), 0.0),
+        };
+        for (name, value) in [
+            ("margin-top", m.top),
+            ("margin-bottom", m.bottom),
+            ("margin-left", m.left + gutter / 2.0),
+            ("margin-right", m.right + gutter / 2.0),
+        ] {
+            args.push("-V".to_string());
+            args.push(format!("{}={}", name, mm(value)));
+        }
+        match profile {
+            Some(profile) => {
+                for (option, value) in [("--page-width", profile.width), ("--page-height", profile.height)] {
+                    args.push(format!("--pdf-engine-opt={}", option));
+                    args.push(format!("--pdf-engine-opt={}", mm(value)));
                 }
             }
+            None => {
+                args.push("-V".to_string());
+                args.push(format!("papersize={}", settings.page_size));
+            }
         }
-        Err(e) => ConversionResult {
-            success: false,
-            output_path: None,
-            error: Some(format!("Failed to execute ebook-convert: {}", e)),
-        },
     }
+    args
 }
 
-/// Convert EPUB to PDF using Pandoc
-fn convert_with_pandoc(epub_path: &Path, pdf_path: &Path) -> ConversionResult {
-    let output = Command::new("pandoc")
-        .arg(epub_path)
-        .arg("-o")
-        .arg(pdf_path)
-        .arg("--pdf-engine=xelatex")
-        .arg("-V")
-        .arg("geometry:margin=1in")
-        .output();
-
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                ConversionResult {
-                    success: true,
-                    output_path: Some(pdf_path.to_string_lossy().to_string()),
-                    error: None,
+/// ebook-convert arguments for non-PDF targets
+fn calibre_format_args(to: EbookFormat) -> Vec<String> {
+    match to {
+        EbookFormat::Mobi => vec!["--output-profile".to_string(), "kindle".to_string()],
+        EbookFormat::Azw3 => vec!["--output-profile".to_string(), "kindle_pw3".to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Pandoc reader for a source format (plain text is read as Markdown)
+fn pandoc_reader(from: EbookFormat) -> &'static str {
+    match from {
+        EbookFormat::Html => "html",
+        EbookFormat::Docx => "docx",
+        EbookFormat::Txt => "markdown",
+        _ => "epub",
+    }
+}
+
+/// pandoc arguments for non-PDF targets
+fn pandoc_format_args(to: EbookFormat) -> Vec<String> {
+    let writer = match to {
+        EbookFormat::Html => "html5",
+        EbookFormat::Docx => "docx",
+        EbookFormat::Txt => "plain",
+        _ => "epub3",
+    };
+    vec!["-t".to_string(), writer.to_string(), "--standalone".to_string()]
+}
+
+/// How often a running converter is checked for cancellation and timeout
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Converter output kept in memory for diagnostics; the rest is discarded
+const MAX_LOG_BYTES: usize = 1024 * 1024;
+
+/// Cancellation flag, time limit and sandbox shared with a running conversion
+#[derive(Debug, Clone)]
+pub struct ConversionControl {
+    pub cancel: Arc<AtomicBool>,
+    pub timeout: Option<Duration>,
+    pub sandbox: Option<SandboxPolicy>, // None runs external converters unconfined
+}
+
+impl ConversionControl {
+    fn new(settings: &EpubToPdfSettings) -> Self {
+        ConversionControl {
+            cancel: Arc::new(AtomicBool::new(false)),
+            timeout: (settings.timeout_seconds > 0).then(|| Duration::from_secs(settings.timeout_seconds)),
+            sandbox: SandboxPolicy::from_settings(settings),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+/// Settings of one job: its own layout profile replaces the one of the batch
+fn job_settings(settings: &EpubToPdfSettings, job: &ConversionJob) -> EpubToPdfSettings {
+    let mut settings = settings.clone();
+    if let Some(profile) = &job.layout_profile {
+        settings.layout_profile = profile.clone();
+    }
+    settings
+}
+
+/// Conversions a batch or the queue runs at once: the setting, else one per CPU
+fn parallel_jobs(settings: &EpubToPdfSettings) -> usize {
+    match settings.parallel_jobs {
+        0 => std::thread::available_parallelism().map_or(2, |n| n.get()),
+        n => n,
+    }
+}
+
+/// LaTeX runs in progress across batches and the queue
+struct LatexSlots {
+    running: Mutex<usize>,
+    released: Condvar,
+}
+
+fn latex_slots() -> &'static LatexSlots {
+    static SLOTS: OnceLock<LatexSlots> = OnceLock::new();
+    SLOTS.get_or_init(|| LatexSlots {
+        running: Mutex::new(0),
+        released: Condvar::new(),
+    })
+}
+
+/// A running LaTeX conversion; frees its slot when dropped
+struct LatexSlot;
+
+impl Drop for LatexSlot {
+    fn drop(&mut self) {
+        let slots = latex_slots();
+        *slots.running.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        slots.released.notify_all();
+    }
+}
+
+/// Wait for a free LaTeX slot; None when the job is cancelled meanwhile
+fn acquire_latex_slot(settings: &EpubToPdfSettings, control: &ConversionControl) -> Option<LatexSlot> {
+    let limit = match settings.max_latex_jobs {
+        0 => usize::MAX,
+        n => n,
+    };
+    let slots = latex_slots();
+    let mut running = slots.running.lock().unwrap_or_else(|e| e.into_inner());
+    while *running >= limit {
+        if control.is_cancelled() {
+            return None;
+        }
+        running = slots
+            .released
+            .wait_timeout(running, Duration::from_millis(200))
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+    *running += 1;
+    Some(LatexSlot)
+}
+
+enum ProcessOutcome {
+    Exited { success: bool, log: String }, // stdout then stderr
+    Cancelled,
+    TimedOut(Duration),
+}
+
+/// Kill a converter and everything it started (xelatex, Calibre workers, ...)
+fn kill_process_tree(child: &mut Child) {
+    let pid = child.id().to_string();
+    let _ = if cfg!(target_os = "windows") {
+        Command::new("taskkill").args(["/PID", &pid, "/T", "/F"]).status()
+    } else {
+        // The child leads its own process group, see run_converter
+        Command::new("kill").args(["-KILL", "--", &format!("-{}", pid)]).status()
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Run a converter, passing each stdout line to `on_line`, until it exits,
+/// is cancelled or runs out of time; the output is kept for diagnostics
+fn run_converter(
+    command: Command,
+    control: &ConversionControl,
+    mut on_line: impl FnMut(&str),
+) -> std::io::Result<ProcessOutcome> {
+    // The sandbox folder is the converter's working directory until it exits
+    let (mut command, _sandbox_dir) = match &control.sandbox {
+        Some(policy) => {
+            let (command, dir) = converter_sandbox::confine(command, policy).map_err(std::io::Error::other)?;
+            (command, Some(dir))
+        }
+        None => (command, None),
+    };
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command.spawn()?;
+
+    // Read both pipes on their own threads so a chatty converter cannot block
+    let stderr = child.stderr.take();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = (&mut stderr).take(MAX_LOG_BYTES as u64).read_to_string(&mut output);
+            let _ = std::io::copy(&mut stderr, &mut std::io::sink());
+        }
+        output
+    });
+    let (lines_tx, lines) = mpsc::channel();
+    let stdout = child.stdout.take();
+    let stdout_reader = std::thread::spawn(move || {
+        if let Some(stdout) = stdout {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if lines_tx.send(line).is_err() {
+                    break;
                 }
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr).to_string();
-                ConversionResult {
-                    success: false,
-                    output_path: None,
-                    error: Some(format!("Pandoc conversion failed: {}", error_msg)),
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let mut log = String::new();
+    let mut record = |line: String| {
+        on_line(&line);
+        if log.len() < MAX_LOG_BYTES {
+            log.push_str(&line);
+            log.push('\n');
+        }
+    };
+    loop {
+        match lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => record(line),
+            Err(error) => {
+                if let Some(status) = child.try_wait()? {
+                    // Lines still in the pipe arrive until the reader hits the end of stdout
+                    lines.iter().for_each(&mut record);
+                    let _ = stdout_reader.join();
+                    log.push_str(&stderr_reader.join().unwrap_or_default());
+                    return Ok(ProcessOutcome::Exited { success: status.success(), log });
                 }
+                if error == RecvTimeoutError::Disconnected {
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+
+        if control.is_cancelled() {
+            kill_process_tree(&mut child);
+            return Ok(ProcessOutcome::Cancelled);
+        }
+        if let Some(timeout) = control.timeout {
+            if started.elapsed() > timeout {
+                kill_process_tree(&mut child);
+                return Ok(ProcessOutcome::TimedOut(timeout));
             }
         }
+    }
+}
+
+/// Map the outcome of an external converter to a ConversionResult; failures are
+/// diagnosed and their full log saved
+fn process_result(
+    converter: &str,
+    program: &str,
+    pdf_path: &Path,
+    outcome: std::io::Result<ProcessOutcome>,
+) -> ConversionResult {
+    let mut diagnostic = None;
+    let error = match outcome {
+        Ok(ProcessOutcome::Exited { success: true, .. }) => {
+            return ConversionResult::success(pdf_path, Vec::new())
+        }
+        Ok(ProcessOutcome::Exited { log, .. }) => {
+            let mut found = conversion_diagnostics::diagnose(converter, &log);
+            let logs_dir = conversion_diagnostics::logs_dir();
+            found.log_path = conversion_diagnostics::save_log(&logs_dir, converter, pdf_path, &log)
+                .map(|path| path.to_string_lossy().to_string());
+            let error = format!("{} conversion failed: {}", converter, found.message);
+            diagnostic = Some(found);
+            error
+        }
+        Ok(ProcessOutcome::Cancelled) => "Conversion cancelled".to_string(),
+        Ok(ProcessOutcome::TimedOut(timeout)) => {
+            format!("Conversion timed out after {} seconds", timeout.as_secs())
+        }
+        Err(e) => format!("Failed to execute {}: {}", program, e),
+    };
+
+    ConversionResult { diagnostic, ..ConversionResult::failure(error) }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Percentage from an ebook-convert progress line ("34% Running transforms on e-book")
+fn parse_calibre_progress(line: &str) -> Option<f64> {
+    let (value, _) = line.trim_start().split_once('%')?;
+    let value: f64 = value.trim().parse().ok()?;
+    (0.0..=100.0).contains(&value).then_some(value)
+}
+
+/// Convert using ebook-convert (Calibre), which picks the output format from the extension
+fn convert_with_calibre(
+    source_path: &Path,
+    target_path: &Path,
+    args: Vec<String>,
+    control: &ConversionControl,
+    on_progress: &mut dyn FnMut(f64),
+) -> ConversionResult {
+    let mut command = match converter_command("calibre") {
+        Ok(command) => command,
+        Err(e) => {
+            return ConversionResult::failure(e)
+        }
+    };
+    // Sandboxed converters run in their own folder, so relative paths would not resolve
+    command.arg(absolute(source_path)).arg(absolute(target_path)).args(args);
+
+    let outcome = run_converter(command, control, |line| {
+        if let Some(progress) = parse_calibre_progress(line) {
+            on_progress(progress);
+        }
+    });
+    process_result("Calibre", "ebook-convert", target_path, outcome)
+}
+
+/// Convert using Pandoc
+fn convert_with_pandoc(
+    source_path: &Path,
+    target_path: &Path,
+    from: EbookFormat,
+    args: Vec<String>,
+    control: &ConversionControl,
+) -> ConversionResult {
+    let mut command = match converter_command("pandoc") {
+        Ok(command) => command,
+        Err(e) => {
+            return ConversionResult::failure(e)
+        }
+    };
+    command
+        .arg(absolute(source_path))
+        .args(["-f", pandoc_reader(from)])
+        .arg("-o")
+        .arg(absolute(target_path))
+        .args(args);
+
+    let outcome = run_converter(command, control, |_| {});
+    process_result("Pandoc", "pandoc", target_path, outcome)
+}
+
+/// Convert with the built-in renderer and KEPUB transforms
+fn convert_with_native(
+    source_path: &Path,
+    target_path: &Path,
+    to: EbookFormat,
+    settings: &EpubToPdfSettings,
+    control: &ConversionControl,
+) -> ConversionResult {
+    // Runs in-process: cancellation and timeout are checked between chapters
+    let started = Instant::now();
+    let timed_out = || control.timeout.is_some_and(|t| started.elapsed() > t);
+    let is_cancelled = || control.is_cancelled() || timed_out();
+    let outcome = match to {
+        EbookFormat::Pdf => render_epub_to_pdf(source_path, target_path, settings, &is_cancelled),
+        EbookFormat::Txt => write_epub_text(source_path, target_path, &is_cancelled).map(|()| Vec::new()),
+        EbookFormat::Kepub => epub_to_kepub(source_path, target_path).map(|()| Vec::new()),
+        EbookFormat::Epub => kepub_to_epub(source_path, target_path).map(|()| Vec::new()),
+        _ => Err(format!("Cannot convert to {}", to.label())),
+    };
+
+    match outcome {
+        Ok(warnings) => ConversionResult::success(target_path, warnings),
+        Err(_) if control.is_cancelled() => ConversionResult::failure("Conversion cancelled".to_string()),
+        Err(_) if timed_out() => ConversionResult::failure(format!(
+            "Conversion timed out after {} seconds",
+            settings.timeout_seconds
+        )),
         Err(e) => ConversionResult {
-            success: false,
-            output_path: None,
-            error: Some(format!("Failed to execute pandoc: {}", e)),
+            diagnostic: conversion_diagnostics::classify(&e),
+            ..ConversionResult::failure(format!("Native conversion failed: {}", e))
         },
     }
 }
 
-/// Convert a single EPUB file to PDF
+/// Converters write next to the target and the file is renamed once complete,
+/// so a failed or cancelled run never leaves a truncated file behind
+fn partial_output_path(target_path: &Path) -> PathBuf {
+    let stem = target_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Keep the extension: Calibre picks the output format from it
+    let extension = target_path
+        .extension()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    target_path.with_file_name(format!(".{}.partial.{}", stem, extension))
+}
+
+/// Convert a single EPUB file to PDF, optionally only some of its chapters and
+/// with the reader's highlights marked
 #[command]
 pub fn convert_epub_to_pdf(
     epub_path: String,
     pdf_path: String,
     converter: String,
+    chapters: Option<ChapterSelection>,
+    annotations: Option<PdfAnnotations>,
+) -> ConversionResult {
+    convert_epub_to_pdf_with_settings(
+        epub_path,
+        pdf_path,
+        converter,
+        EpubToPdfSettings::default(),
+        chapters,
+        annotations,
+    )
+}
+
+/// Convert a single EPUB file to PDF using the plugin settings
+#[command]
+pub fn convert_epub_to_pdf_with_settings(
+    epub_path: String,
+    pdf_path: String,
+    converter: String,
+    settings: EpubToPdfSettings,
+    chapters: Option<ChapterSelection>,
+    annotations: Option<PdfAnnotations>,
+) -> ConversionResult {
+    let control = ConversionControl::new(&settings);
+    let annotations = annotations.as_ref();
+    let Some(selection) = chapters else {
+        return run_conversion(&epub_path, &pdf_path, &converter, &settings, annotations, &control, &mut |_| {});
+    };
+
+    // The selected chapters are converted from a trimmed copy of the book
+    let subset = match ChapterSubset::create(Path::new(&epub_path), &selection) {
+        Ok(subset) => subset,
+        Err(e) => {
+            return ConversionResult::failure(format!("Failed to select chapters: {}", e))
+        }
+    };
+    let source = subset.path.to_string_lossy();
+    run_conversion(&source, &pdf_path, &converter, &settings, annotations, &control, &mut |_| {})
+}
+
+/// Validate the job and run the selected converter, reporting progress (0-100) when known.
+/// Source and target formats come from the file extensions.
+fn run_conversion(
+    source_path: &str,
+    target_path: &str,
+    converter: &str,
+    settings: &EpubToPdfSettings,
+    annotations: Option<&PdfAnnotations>,
+    control: &ConversionControl,
+    on_progress: &mut dyn FnMut(f64),
 ) -> ConversionResult {
-    let epub = Path::new(&epub_path);
-    let pdf = Path::new(&pdf_path);
+    let source = Path::new(source_path);
+    let target = Path::new(target_path);
+    // Errors of our own, such as a full disk while moving the file, are diagnosed too
+    let failure = |error: String| ConversionResult {
+        diagnostic: conversion_diagnostics::classify(&error),
+        ..ConversionResult::failure(error)
+    };
+
+    let Some(from) = EbookFormat::from_path(source) else {
+        return failure(format!("Unsupported input format: {}", source_path));
+    };
+    let Some(to) = EbookFormat::from_path(target) else {
+        return failure(format!("Unsupported output format: {}", target_path));
+    };
 
     // Check if input file exists
-    if !epub.exists() {
-        return ConversionResult {
-            success: false,
-            output_path: None,
-            error: Some(format!("EPUB file not found: {}", epub_path)),
-        };
+    if !source.exists() {
+        return failure(format!("{} file not found: {}", from.label(), source_path));
     }
 
-    // Check if input is an EPUB
-    if epub.extension().and_then(|s| s.to_str()) != Some("epub") {
-        return ConversionResult {
-            success: false,
-            output_path: None,
-            error: Some("Input file must be an EPUB file".to_string()),
-        };
+    // Reject pairs and settings the converter cannot honour before running it
+    if !ebook_formats::CONVERTERS.contains(&converter) {
+        return failure(format!("Unknown converter: {}", converter));
+    }
+    if !ebook_formats::supports(converter, from, to) {
+        return failure(format!(
+            "The {} converter cannot convert {} to {}",
+            converter,
+            from.label(),
+            to.label()
+        ));
+    }
+    if to == EbookFormat::Pdf {
+        if let Err(e) = validate_settings(settings, converter) {
+            return failure(e);
+        }
+    }
+
+    // Report DRM and broken books clearly instead of a converter stack trace
+    if matches!(from, EbookFormat::Epub | EbookFormat::Kepub) {
+        if let Some(error) = epub_validator::validate(source).errors.first() {
+            return ConversionResult {
+                diagnostic: Some(conversion_diagnostics::from_validation(error)),
+                ..failure(error.message.clone())
+            };
+        }
     }
 
     // Create output directory if it doesn't exist
-    if let Some(parent) = pdf.parent() {
+    if let Some(parent) = target.parent() {
         if !parent.exists() {
             if let Err(e) = std::fs::create_dir_all(parent) {
-                return ConversionResult {
-                    success: false,
-                    output_path: None,
-                    error: Some(format!("Failed to create output directory: {}", e)),
-                };
+                return failure(format!("Failed to create output directory: {}", e));
             }
         }
     }
 
+    // An unchanged book converted with the same converter and settings is copied from the cache.
+    // Highlights change with every reading session, so annotated exports are not cached.
+    let partial = partial_output_path(target);
+    let cache_dir = conversion_cache::cache_dir();
+    let cache_key = (settings.cache_enabled && annotations.is_none())
+        .then(|| conversion_cache::cache_key(source, converter, to, settings).ok())
+        .flatten();
+    if let Some(cached) = cache_key
+        .as_deref()
+        .and_then(|key| conversion_cache::lookup(&cache_dir, key, to.extension()))
+    {
+        match std::fs::copy(&cached, &partial).and_then(|_| std::fs::rename(&partial, target)) {
+            Ok(()) => {
+                on_progress(100.0);
+                return ConversionResult { cached: true, ..ConversionResult::success(target, Vec::new()) };
+            }
+            Err(_) => {
+                let _ = std::fs::remove_file(&partial);
+            }
+        }
+    }
+
+    // The user's stylesheet and fonts; Calibre reads them from a copy of the book
+    let style = match to {
+        EbookFormat::Pdf => match CustomStyle::from_settings(settings) {
+            Ok(style) => style,
+            Err(e) => return failure(e),
+        },
+        _ => None,
+    };
+    let workspace = match style.as_ref().map(StyleWorkspace::create).transpose() {
+        Ok(workspace) => workspace,
+        Err(e) => return failure(e),
+    };
+    let mut input = source.to_path_buf();
+    if let (Some(style), Some(workspace), "calibre") = (&style, &workspace, converter) {
+        if matches!(from, EbookFormat::Epub | EbookFormat::Kepub) {
+            if let Err(e) = inject_into_epub(source, &workspace.epub_path(), style) {
+                return failure(e);
+            }
+            input = workspace.epub_path();
+        }
+    }
+    let style_args = |build: fn(&CustomStyle, &StyleWorkspace) -> Vec<String>| match (&style, &workspace) {
+        (Some(style), Some(workspace)) => build(style, workspace),
+        _ => Vec::new(),
+    };
+
     // Perform conversion based on specified converter
-    match converter.as_str() {
-        "calibre" => convert_with_calibre(epub, pdf),
-        "pandoc" => convert_with_pandoc(epub, pdf),
-        _ => ConversionResult {
-            success: false,
-            output_path: None,
-            error: Some(format!("Unknown converter: {}", converter)),
+    let result = match (converter, to) {
+        ("calibre", EbookFormat::Pdf) => {
+            let args = [calibre_args(settings), style_args(calibre_style_args)].concat();
+            convert_with_calibre(&input, &partial, args, control, on_progress)
+        }
+        ("calibre", _) => convert_with_calibre(source, &partial, calibre_format_args(to), control, on_progress),
+        ("pandoc", EbookFormat::Pdf) => match pandoc_engine(settings) {
+            Ok(engine) => {
+                // LaTeX needs far more memory than the other converters, so it has its own limit
+                let slot = engine.is_latex().then(|| acquire_latex_slot(settings, control));
+                if matches!(slot, Some(None)) {
+                    failure("Conversion cancelled".to_string())
+                } else {
+                    let args = [pandoc_args(settings, &engine), style_args(pandoc_style_args)].concat();
+                    convert_with_pandoc(source, &partial, from, args, control)
+                }
+            }
+            Err(e) => failure(e),
         },
+        ("pandoc", _) => convert_with_pandoc(source, &partial, from, pandoc_format_args(to), control),
+        _ => convert_with_native(source, &partial, to, settings, control),
+    };
+
+    if !result.success {
+        let _ = std::fs::remove_file(&partial);
+        return result;
+    }
+    let mut warnings = result.warnings;
+    if control.sandbox.is_some() && converter != "native" {
+        warnings.extend(converter_sandbox::status().warning);
+    }
+    // The file size limit of the sandbox is only enforced by the system on Unix
+    if let (Some(policy), true) = (&control.sandbox, converter != "native") {
+        if let Err(e) = converter_sandbox::check_output(&partial, policy) {
+            let _ = std::fs::remove_file(&partial);
+            return failure(e);
+        }
+    }
+
+    // Pandoc drops the title, author, language and cover; restore them for every backend,
+    // then apply the compression level, PDF/A and linearisation settings
+    let mut sizes = (None, None);
+    if to == EbookFormat::Pdf {
+        let epub = matches!(from, EbookFormat::Epub | EbookFormat::Kepub).then_some(source);
+        match postprocess_pdf(&partial, epub, settings, annotations) {
+            Ok(report) => {
+                sizes = (Some(report.size_before), Some(report.size_after));
+                warnings.extend(report.warnings);
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                return failure(e);
+            }
+        }
+    }
+    // A full or read-only cache must not fail the conversion itself
+    if let Some(key) = &cache_key {
+        let max_bytes = settings.cache_max_mb.saturating_mul(1024 * 1024);
+        if let Err(e) = conversion_cache::store(&cache_dir, key, to.extension(), &partial, max_bytes) {
+            warnings.push(e);
+        }
+    }
+    if let Err(e) = std::fs::rename(&partial, target) {
+        let _ = std::fs::remove_file(&partial);
+        return failure(format!("Failed to move {} into place: {}", to.label(), e));
+    }
+
+    ConversionResult {
+        size_before: sizes.0,
+        size_after: sizes.1,
+        ..ConversionResult::success(target, warnings)
     }
 }
 
-/// Convert multiple EPUB files to PDF in batch
+/// Convert between any two formats of the matrix; without a converter the
+/// preferred available one for the pair is used
+#[command]
+pub fn convert_ebook(
+    source_path: String,
+    target_path: String,
+    converter: Option<String>,
+    settings: Option<EpubToPdfSettings>,
+) -> ConversionResult {
+    let settings = settings.unwrap_or_default();
+    let converter = match converter {
+        Some(converter) => converter,
+        None => match preferred_converter_for(&source_path, &target_path) {
+            Ok(converter) => converter.to_string(),
+            Err(e) => {
+                return ConversionResult::failure(e)
+            }
+        },
+    };
+
+    let control = ConversionControl::new(&settings);
+    run_conversion(&source_path, &target_path, &converter, &settings, None, &control, &mut |_| {})
+}
+
+/// Best installed converter for the formats of the two paths
+pub fn preferred_converter_for(source_path: &str, target_path: &str) -> Result<&'static str, String> {
+    let from = EbookFormat::from_path(Path::new(source_path))
+        .ok_or_else(|| format!("Unsupported input format: {}", source_path))?;
+    let to = EbookFormat::from_path(Path::new(target_path))
+        .ok_or_else(|| format!("Unsupported output format: {}", target_path))?;
+
+    let available = converter_registry::available_converters();
+    ebook_formats::preferred_converter(&available, from, to).ok_or_else(|| {
+        let needed: Vec<&str> = ebook_formats::CONVERTERS
+            .into_iter()
+            .filter(|c| ebook_formats::supports(c, from, to))
+            .collect();
+        if needed.is_empty() {
+            format!("No converter can convert {} to {}", from.label(), to.label())
+        } else {
+            format!(
+                "No converter available for {} to {}: install {}",
+                from.label(),
+                to.label(),
+                needed.join(" or ")
+            )
+        }
+    })
+}
+
+/// Convert EPUB to MOBI for Kindle devices (needs Calibre)
+#[command]
+pub fn convert_epub_to_mobi(epub_path: String, mobi_path: String) -> ConversionResult {
+    convert_ebook(epub_path, mobi_path, None, None)
+}
+
+/// Convert MOBI back to EPUB (needs Calibre)
+#[command]
+pub fn convert_mobi_to_epub(mobi_path: String, epub_path: String) -> ConversionResult {
+    convert_ebook(mobi_path, epub_path, None, None)
+}
+
+/// Targets for a batch: explicit `pdf_path`s, or the naming template for jobs without one
+fn resolve_jobs(jobs: &[ConversionJob], naming: Option<&OutputNaming>) -> Result<Vec<ResolvedOutput>, String> {
+    let requests: Vec<(String, Option<String>)> = jobs
+        .iter()
+        .map(|job| (job.epub_path.clone(), job.pdf_path.clone()))
+        .collect();
+    resolve_outputs(&requests, naming)
+}
+
+/// Convert multiple EPUB files to PDF in batch, several at a time (see `parallel_jobs`).
+/// Results are reported in input order.
 #[command]
 pub fn convert_multiple_epub_to_pdf(
     jobs: Vec<ConversionJob>,
     converter: String,
+    naming: Option<OutputNaming>,
+    settings: Option<EpubToPdfSettings>,
 ) -> BatchConversionResult {
-    let mut converted = 0;
-    let mut failed = 0;
-    let mut errors = Vec::new();
-    let mut output_paths = Vec::new();
-
-    for job in jobs {
-        let result = convert_epub_to_pdf(job.epub_path.clone(), job.pdf_path.clone(), converter.clone());
-
-        if result.success {
-            converted += 1;
-            if let Some(path) = result.output_path {
-                output_paths.push(path);
+    let settings = settings.unwrap_or_default();
+    let resolved = match resolve_jobs(&jobs, naming.as_ref()) {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            return BatchConversionResult {
+                success: false,
+                converted: 0,
+                failed: jobs.len(),
+                errors: vec![ConversionError {
+                    file: "batch".to_string(),
+                    error: e,
+                }],
+                output_paths: Vec::new(),
+                skipped: 0,
+                resolved: Vec::new(),
+                jobs: Vec::new(),
             }
-        } else {
-            failed += 1;
-            errors.push(ConversionError {
-                file: job.epub_path,
-                error: result.error.unwrap_or_else(|| "Unknown error".to_string()),
+        }
+    };
+
+    // Workers take the next job until none are left
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<(ConversionResult, Duration)>>> = Mutex::new(vec![None; resolved.len()]);
+    let workers = parallel_jobs(&settings).min(resolved.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(output) = resolved.get(index) else {
+                    return;
+                };
+                if output.action == "skip" {
+                    continue;
+                }
+                let started = Instant::now();
+                let settings = job_settings(&settings, &jobs[index]);
+                let control = ConversionControl::new(&settings);
+                let result = run_conversion(
+                    &output.source_path,
+                    &output.target_path,
+                    &converter,
+                    &settings,
+                    None,
+                    &control,
+                    &mut |_| {},
+                );
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some((result, started.elapsed()));
             });
         }
+    });
+
+    let mut batch = BatchConversionResult {
+        success: true,
+        converted: 0,
+        failed: 0,
+        errors: Vec::new(),
+        output_paths: Vec::new(),
+        skipped: 0,
+        resolved: Vec::new(),
+        jobs: Vec::new(),
+    };
+    let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    for (output, outcome) in resolved.iter().zip(results) {
+        let mut job = BatchJobResult {
+            source_path: output.source_path.clone(),
+            target_path: output.target_path.clone(),
+            status: "skipped".to_string(),
+            error: None,
+            diagnostic: None,
+            duration_ms: 0,
+        };
+        match outcome {
+            None => batch.skipped += 1,
+            Some((result, duration)) => {
+                job.duration_ms = duration.as_millis() as u64;
+                if result.success {
+                    batch.converted += 1;
+                    job.status = "converted".to_string();
+                    batch.output_paths.extend(result.output_path);
+                } else {
+                    let error = result.error.unwrap_or_else(|| "Unknown error".to_string());
+                    batch.failed += 1;
+                    job.status = "failed".to_string();
+                    job.error = Some(error.clone());
+                    job.diagnostic = result.diagnostic;
+                    batch.errors.push(ConversionError {
+                        file: output.source_path.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        batch.jobs.push(job);
+    }
+    batch.success = batch.failed == 0;
+    batch.resolved = resolved;
+    batch
+}
+
+// ============================================================================
+// Job Queue
+// ============================================================================
+
+const EVENT_QUEUED: &str = "epub-conversion-queued";
+const EVENT_STARTED: &str = "epub-conversion-started";
+const EVENT_PROGRESS: &str = "epub-conversion-progress";
+const EVENT_COMPLETED: &str = "epub-conversion-completed";
+const EVENT_FAILED: &str = "epub-conversion-failed";
+/// Completed, failed and cancelled jobs kept for list_conversion_jobs; older ones are dropped
+const MAX_FINISHED_JOBS: usize = 200;
+
+/// A conversion tracked by the job queue, mirrored by the TypeScript `ConversionJob`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedConversion {
+    pub id: String,
+    pub book_id: Option<String>,
+    pub source_path: String,
+    pub target_path: String,
+    pub converter: String,
+    pub status: String, // "pending", "converting", "completed", "failed" or "cancelled"
+    pub progress: f64,  // 0-100
+    pub output_path: Option<String>,
+    pub error: Option<String>,
+    pub diagnostic: Option<ConversionDiagnostic>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    pub queued_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+/// Called with every job of an enqueue call once it has completed or failed
+pub type JobHook = Arc<dyn Fn(&QueuedConversion) + Send + Sync>;
+
+struct QueueEntry {
+    job: QueuedConversion,
+    settings: EpubToPdfSettings,
+    control: ConversionControl,
+    on_finished: Option<JobHook>,
+}
+
+#[derive(Default)]
+struct JobQueue {
+    entries: Vec<QueueEntry>,
+    workers: usize,
+    max_workers: usize, // From the settings of the latest enqueue
+    next_id: u64,
+    app: Option<AppHandle>, // Events are only emitted once a command provided the handle
+}
+
+impl JobQueue {
+    fn entry_mut(&mut self, job_id: &str) -> Option<&mut QueueEntry> {
+        self.entries.iter_mut().find(|e| e.job.id == job_id)
+    }
+
+    /// Remove the oldest finished jobs beyond `keep`; returns how many were removed
+    fn prune_finished(&mut self, keep: usize) -> usize {
+        let is_finished =
+            |entry: &QueueEntry| matches!(entry.job.status.as_str(), "completed" | "failed" | "cancelled");
+        let mut excess = self.entries.iter().filter(|e| is_finished(e)).count().saturating_sub(keep);
+        let removed = excess;
+        // Entries are in queue order, so the first finished ones are the oldest
+        self.entries.retain(|entry| {
+            if excess > 0 && is_finished(entry) {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+        removed
+    }
+
+    /// Start workers for pending jobs, up to the pool size
+    fn spawn_workers(&mut self) {
+        let pending = self.entries.iter().filter(|e| e.job.status == "pending").count();
+        let wanted = self.max_workers.max(1).min(self.workers + pending);
+        while self.workers < wanted {
+            self.workers += 1;
+            std::thread::spawn(run_worker);
+        }
+    }
+}
+
+fn job_queue() -> MutexGuard<'static, JobQueue> {
+    static QUEUE: OnceLock<Mutex<JobQueue>> = OnceLock::new();
+    QUEUE
+        .get_or_init(|| Mutex::new(JobQueue::default()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+/// Emitting only fails while the app shuts down; list_conversion_jobs still has the job
+fn emit_job_event(app: &Option<AppHandle>, event: &str, job: &QueuedConversion) {
+    if let Some(app) = app {
+        let _ = app.emit(event, job);
+    }
+}
+
+/// Pick pending jobs until none are left
+fn run_worker() {
+    loop {
+        let (app, job, settings, control) = {
+            let mut queue = job_queue();
+            let app = queue.app.clone();
+            let Some(entry) = queue.entries.iter_mut().find(|e| e.job.status == "pending") else {
+                queue.workers -= 1;
+                return;
+            };
+            entry.job.status = "converting".to_string();
+            entry.job.progress = 0.0;
+            entry.job.started_at = Some(now());
+            (app, entry.job.clone(), entry.settings.clone(), entry.control.clone())
+        };
+        emit_job_event(&app, EVENT_STARTED, &job);
+
+        let mut last_progress = 0.0;
+        let (source, target) = (&job.source_path, &job.target_path);
+        let result = run_conversion(source, target, &job.converter, &settings, None, &control, &mut |progress| {
+            if progress <= last_progress {
+                return;
+            }
+            last_progress = progress;
+            let updated = job_queue().entry_mut(&job.id).map(|entry| {
+                entry.job.progress = progress;
+                entry.job.clone()
+            });
+            if let Some(updated) = updated {
+                emit_job_event(&app, EVENT_PROGRESS, &updated);
+            }
+        });
+
+        let mut queue = job_queue();
+        let finished = queue.entry_mut(&job.id).map(|entry| {
+            let on_finished = entry.on_finished.clone();
+            entry.job.completed_at = Some(now());
+            if result.success {
+                entry.job.status = "completed".to_string();
+                entry.job.progress = 100.0;
+                entry.job.output_path = result.output_path.clone();
+                entry.job.warnings = result.warnings.clone();
+            } else {
+                let cancelled = control.is_cancelled();
+                entry.job.status = if cancelled { "cancelled" } else { "failed" }.to_string();
+                entry.job.error = Some(result.error.clone().unwrap_or_else(|| "Unknown error".to_string()));
+                entry.job.diagnostic = result.diagnostic.clone();
+            }
+            (entry.job.clone(), on_finished)
+        });
+        queue.prune_finished(MAX_FINISHED_JOBS);
+        drop(queue);
+        if let Some((finished, on_finished)) = finished {
+            let event = if result.success { EVENT_COMPLETED } else { EVENT_FAILED };
+            emit_job_event(&app, event, &finished);
+            if let Some(on_finished) = on_finished {
+                on_finished(&finished);
+            }
+        }
     }
+}
+
+/// Queue jobs for the worker pool; `on_finished` sees each of them complete or fail
+pub fn enqueue(
+    app: Option<AppHandle>,
+    jobs: Vec<ConversionJob>,
+    converter: String,
+    settings: EpubToPdfSettings,
+    naming: Option<OutputNaming>,
+    on_finished: Option<JobHook>,
+) -> Result<Vec<QueuedConversion>, String> {
+    let resolved = resolve_jobs(&jobs, naming.as_ref())?;
+
+    let (app, queued) = {
+        let mut queue = job_queue();
+        if app.is_some() {
+            queue.app = app;
+        }
+        queue.max_workers = parallel_jobs(&settings);
+
+        let mut queued = Vec::new();
+        for (job, output) in jobs.into_iter().zip(resolved) {
+            if output.action == "skip" {
+                continue;
+            }
+            queue.next_id += 1;
+            let settings = job_settings(&settings, &job);
+            let entry = QueueEntry {
+                job: QueuedConversion {
+                    id: format!("conversion-{}", queue.next_id),
+                    book_id: job.book_id,
+                    source_path: output.source_path,
+                    target_path: output.target_path,
+                    converter: converter.clone(),
+                    status: "pending".to_string(),
+                    progress: 0.0,
+                    output_path: None,
+                    error: None,
+                    diagnostic: None,
+                    warnings: Vec::new(),
+                    queued_at: now(),
+                    started_at: None,
+                    completed_at: None,
+                },
+                control: ConversionControl::new(&settings),
+                settings,
+                on_finished: on_finished.clone(),
+            };
+            queued.push(entry.job.clone());
+            queue.entries.push(entry);
+        }
+        (queue.app.clone(), queued)
+    };
 
-    BatchConversionResult {
-        success: failed == 0,
-        converted,
-        failed,
-        errors,
-        output_paths,
+    for job in &queued {
+        emit_job_event(&app, EVENT_QUEUED, job);
     }
+    job_queue().spawn_workers();
+    Ok(queued)
+}
+
+/// Queue conversions and return immediately; progress is reported through events.
+/// Jobs skipped by the collision policy are not queued.
+#[command]
+pub fn enqueue_epub_conversions(
+    app: AppHandle,
+    jobs: Vec<ConversionJob>,
+    converter: String,
+    settings: Option<EpubToPdfSettings>,
+    naming: Option<OutputNaming>,
+) -> Result<Vec<QueuedConversion>, String> {
+    enqueue(Some(app), jobs, converter, settings.unwrap_or_default(), naming, None)
+}
+
+/// All jobs known to the queue, oldest first
+#[command]
+pub fn list_conversion_jobs() -> Vec<QueuedConversion> {
+    job_queue().entries.iter().map(|e| e.job.clone()).collect()
+}
+
+/// Remove completed, failed and cancelled jobs; returns how many were removed
+#[command]
+pub fn clear_finished_conversion_jobs() -> usize {
+    job_queue().prune_finished(0)
+}
+
+/// Cancel a job. A running converter is killed with its child processes and
+/// the job turns "cancelled" once it has exited.
+#[command]
+pub fn cancel_conversion_job(job_id: String) -> Result<QueuedConversion, String> {
+    let (app, job) = {
+        let mut queue = job_queue();
+        let app = queue.app.clone();
+        let entry = queue
+            .entry_mut(&job_id)
+            .ok_or_else(|| format!("Conversion job not found: {}", job_id))?;
+        match entry.job.status.as_str() {
+            "pending" => {}
+            "converting" => {
+                entry.control.cancel.store(true, Ordering::SeqCst);
+                return Ok(entry.job.clone());
+            }
+            status => return Err(format!("Cannot cancel a job that is {}", status)),
+        }
+        entry.job.status = "cancelled".to_string();
+        entry.job.error = Some("Cancelled".to_string());
+        entry.job.completed_at = Some(now());
+        (app, entry.job.clone())
+    };
+
+    emit_job_event(&app, EVENT_FAILED, &job);
+    Ok(job)
+}
+
+/// Queue a failed or cancelled job again
+#[command]
+pub fn retry_conversion_job(job_id: String) -> Result<QueuedConversion, String> {
+    let (app, job) = {
+        let mut queue = job_queue();
+        let app = queue.app.clone();
+        let entry = queue
+            .entry_mut(&job_id)
+            .ok_or_else(|| format!("Conversion job not found: {}", job_id))?;
+        if !matches!(entry.job.status.as_str(), "failed" | "cancelled") {
+            return Err(format!("Cannot retry a job that is {}", entry.job.status));
+        }
+        entry.job.status = "pending".to_string();
+        entry.job.progress = 0.0;
+        entry.job.error = None;
+        entry.job.diagnostic = None;
+        entry.job.warnings.clear();
+        entry.job.output_path = None;
+        entry.job.started_at = None;
+        entry.job.completed_at = None;
+        entry.control = ConversionControl::new(&entry.settings);
+        let job = entry.job.clone();
+        queue.spawn_workers();
+        (app, job)
+    };
+
+    emit_job_event(&app, EVENT_QUEUED, &job);
+    Ok(job)
 }
 
 #[cfg(test)]
@@ -319,15 +1594,380 @@ mod tests {
     fn test_check_epub_converter() {
         let info = check_epub_converter();
         println!("Converter info: {:?}", info);
+        assert!(info.conversions.iter().any(|p| p.from == EbookFormat::Epub && p.to == EbookFormat::Pdf));
         // This test just checks if the function runs without panicking
     }
 
+    #[test]
+    fn test_result_field_names() {
+        let result = ConversionResult {
+            size_before: Some(2048),
+            size_after: Some(1024),
+            ..ConversionResult::success(Path::new("/out/Dune.pdf"), Vec::new())
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["outputPath"], "/out/Dune.pdf");
+        assert_eq!(json["sizeBefore"], 2048);
+        assert_eq!(json["sizeAfter"], 1024);
+
+        let info = ConverterInfo {
+            available: true,
+            converter: "pandoc".to_string(),
+            version: None,
+            path: None,
+            conversions: Vec::new(),
+            pdf_engines: vec![PdfEngine { name: "xelatex".to_string(), path: "/usr/bin/xelatex".to_string() }],
+            error: None,
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["pdfEngines"][0]["name"], "xelatex");
+
+        let batch = BatchConversionResult {
+            success: false,
+            converted: 0,
+            failed: 1,
+            errors: vec![ConversionError { file: "dune.epub".to_string(), error: "Failed".to_string() }],
+            output_paths: Vec::new(),
+            skipped: 0,
+            resolved: Vec::new(),
+            jobs: Vec::new(),
+        };
+        let json = serde_json::to_value(&batch).unwrap();
+        assert!(json["outputPaths"].is_array());
+    }
+
     #[test]
     fn test_converter_detection() {
-        let calibre = check_calibre();
-        let pandoc = check_pandoc();
+        let converters = converter_registry::converters(true);
+        for converter in &converters {
+            println!("{}: {:?}", converter.converter, converter);
+        }
+        assert_eq!(converters.len(), 3);
+        assert!(converters.iter().any(|c| c.converter == "native" && c.available));
+    }
+
+    #[test]
+    fn test_default_settings_match_legacy_arguments() {
+        let settings = EpubToPdfSettings::default();
+        let args = calibre_args(&settings);
+        assert!(args.windows(2).any(|w| w == ["--paper-size", "a4"]));
+        assert!(args.windows(2).any(|w| w == ["--pdf-page-margin-top", "72"]));
+        assert!(!args.contains(&"--pdf-add-toc".to_string()));
+
+        let args = pandoc_args(&settings, &engine("xelatex"));
+        assert!(args.contains(&"papersize=a4".to_string()));
+        assert!(args.contains(&"geometry:top=72pt,bottom=72pt,left=72pt,right=72pt".to_string()));
+    }
+
+    fn engine(name: &str) -> PdfEngine {
+        PdfEngine {
+            name: name.to_string(),
+            path: format!("/usr/bin/{}", name),
+        }
+    }
+
+    #[test]
+    fn test_settings_mapping() {
+        let settings: EpubToPdfSettings = serde_json::from_str(
+            r#"{"pageSize":"auto","margins":{"top":20,"bottom":20,"left":10,"right":10},
+                "compressionLevel":"none","includeTableOfContents":true,"showNotifications":true}"#,
+        )
+        .unwrap();
+
+        let args = calibre_args(&settings);
+        assert!(args.contains(&"--use-profile-size".to_string()));
+        assert!(args.contains(&"--uncompressed-pdf".to_string()));
+        assert!(args.contains(&"--pdf-add-toc".to_string()));
+        assert!(args.windows(2).any(|w| w == ["--pdf-page-margin-left", "10"]));
+
+        let settings = EpubToPdfSettings {
+            compression_level: "high".to_string(),
+            include_table_of_contents: true,
+            ..EpubToPdfSettings::default()
+        };
+        let args = pandoc_args(&settings, &engine("xelatex"));
+        assert!(args.contains(&"--pdf-engine=/usr/bin/xelatex".to_string()));
+        assert!(args.contains(&"--toc".to_string()));
+        assert!(args.contains(&"--pdf-engine-opt=-output-driver=xdvipdfmx -z9".to_string()));
+
+        // Without TeX the page comes from CSS, and only xelatex takes the xdvipdfmx option
+        let args = pandoc_args(&settings, &engine("weasyprint"));
+        let page = "header-includes=<style>@page { size: A4; margin: 72pt 72pt 72pt 72pt }</style>";
+        assert!(args.contains(&page.to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("geometry:") || a.contains("xdvipdfmx")));
+        let args = pandoc_args(&settings, &engine("wkhtmltopdf"));
+        assert!(args.contains(&"margin-left=25.4mm".to_string()));
+        assert!(args.contains(&"papersize=A4".to_string()));
+        assert!(!pandoc_args(&settings, &engine("lualatex")).iter().any(|a| a.contains("xdvipdfmx")));
+    }
+
+    #[test]
+    fn test_layout_profile_arguments() {
+        let settings = EpubToPdfSettings {
+            layout_profile: "trade-6x9".to_string(),
+            page_size: "auto".to_string(),
+            ..EpubToPdfSettings::default()
+        };
+        assert_eq!(validate_settings(&settings, "pandoc"), Ok(()));
+
+        let args = calibre_args(&settings);
+        assert!(args.windows(2).any(|w| w == ["--custom-size", "432x648"]));
+        assert!(args.windows(2).any(|w| w == ["--pdf-page-margin-left", "63"]));
+        assert!(args.windows(2).any(|w| w == ["--pdf-default-font-size", "15"]));
+        assert!(args.contains(&"--pdf-hyphenate".to_string()));
+        assert!(!args.contains(&"--use-profile-size".to_string()));
+
+        let args = pandoc_args(&settings, &engine("xelatex"));
+        assert!(args.iter().any(|a| a.starts_with("geometry:paperwidth=432pt,paperheight=648pt")
+            && a.contains("bindingoffset=18pt")));
+        assert!(args.contains(&"fontsize=11pt".to_string()));
+        let args = pandoc_args(&settings, &engine("weasyprint"));
+        assert!(args.iter().any(|a| a.contains("@page :left { margin: 54pt 72pt 63pt 54pt }")));
+
+        // A job's own profile wins over the batch settings
+        let job = ConversionJob {
+            epub_path: "dune.epub".to_string(),
+            pdf_path: None,
+            book_id: None,
+            layout_profile: Some("large-print".to_string()),
+        };
+        assert_eq!(job_settings(&settings, &job).layout_profile, "large-print");
+
+        let unknown = EpubToPdfSettings {
+            layout_profile: "folio".to_string(),
+            ..EpubToPdfSettings::default()
+        };
+        assert_eq!(validate_settings(&unknown, "native"), Err("Unknown layout profile: folio".to_string()));
+    }
+
+    #[test]
+    fn test_parse_calibre_progress() {
+        assert_eq!(parse_calibre_progress("34% Running transforms on e-book"), Some(34.0));
+        assert_eq!(parse_calibre_progress("  1% Converting input to HTML..."), Some(1.0));
+        assert_eq!(parse_calibre_progress("Output saved to dune.pdf"), None);
+        assert_eq!(parse_calibre_progress("150% nonsense"), None);
+    }
+
+    #[test]
+    fn test_partial_output_path() {
+        assert_eq!(
+            partial_output_path(Path::new("/books/Dune.pdf")),
+            PathBuf::from("/books/.Dune.partial.pdf")
+        );
+        assert_eq!(
+            partial_output_path(Path::new("/books/Dune.kepub.epub")),
+            PathBuf::from("/books/.Dune.kepub.partial.epub")
+        );
+    }
+
+    #[test]
+    fn test_unsupported_conversions() {
+        let control = ConversionControl::new(&EpubToPdfSettings::default());
+        let settings = EpubToPdfSettings::default();
+        let run = |source: &str, target: &str, converter: &str| {
+            run_conversion(source, target, converter, &settings, None, &control, &mut |_| {})
+                .error
+                .unwrap()
+        };
+
+        assert!(run("/books/Dune.fb2", "/out/Dune.pdf", "calibre").starts_with("Unsupported input format"));
+        assert!(run("/books/Dune.epub", "/out/Dune", "calibre").starts_with("Unsupported output format"));
+        assert_eq!(run("/books/Dune.MOBI", "/out/Dune.epub", "calibre"), "MOBI file not found: /books/Dune.MOBI");
+
+        let epub = std::env::temp_dir().join(format!("stomy-formats-{}.epub", std::process::id()));
+        std::fs::write(&epub, b"").unwrap();
+        let epub = epub.to_string_lossy().to_string();
+        assert_eq!(run(&epub, "/out/Dune.kepub.epub", "calibre"), "The calibre converter cannot convert EPUB to KEPUB");
+        assert_eq!(run(&epub, "/out/Dune.mobi", "native"), "The native converter cannot convert EPUB to MOBI");
+        assert_eq!(run(&epub, "/out/Dune.mobi", "kindlegen"), "Unknown converter: kindlegen");
+        assert!(run(&epub, "/out/Dune.pdf", "native").starts_with("Not an EPUB: the file is not a ZIP archive"));
+        std::fs::remove_file(&epub).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_converter_timeout_and_cancel() {
+        let control = ConversionControl {
+            cancel: Arc::new(AtomicBool::new(false)),
+            timeout: Some(Duration::from_millis(300)),
+            sandbox: None,
+        };
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo 5% Starting; sleep 30 & sleep 30"]);
+
+        let started = Instant::now();
+        let mut lines = Vec::new();
+        let outcome = run_converter(command, &control, |line| lines.push(line.to_string())).unwrap();
+        assert!(matches!(outcome, ProcessOutcome::TimedOut(_)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(lines, vec!["5% Starting"]);
+
+        let control = ConversionControl { timeout: None, ..control };
+        control.cancel.store(true, Ordering::SeqCst);
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30"]);
+        let outcome = run_converter(command, &control, |_| {}).unwrap();
+        assert!(matches!(outcome, ProcessOutcome::Cancelled));
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo working; echo oops >&2; exit 3"]);
+        let control = ConversionControl { cancel: Arc::new(AtomicBool::new(false)), timeout: None, sandbox: None };
+        match run_converter(command, &control, |_| {}).unwrap() {
+            ProcessOutcome::Exited { success, log } => {
+                assert!(!success);
+                assert_eq!(log, "working\noops\n");
+            }
+            _ => panic!("expected the process to exit"),
+        }
+
+        // Every line written before the exit reaches on_line
+        let mut command = Command::new("sh");
+        command.args(["-c", "seq 1 20000"]);
+        let mut count = 0;
+        run_converter(command, &control, |_| count += 1).unwrap();
+        assert_eq!(count, 20000);
+    }
+
+    #[test]
+    fn test_job_queue() {
+        let jobs = vec![ConversionJob {
+            epub_path: "/nonexistent/dune.epub".to_string(),
+            pdf_path: Some("/nonexistent/dune.pdf".to_string()),
+            book_id: Some("42".to_string()),
+            layout_profile: None,
+        }];
+        let queued = enqueue(None, jobs, "native".to_string(), EpubToPdfSettings::default(), None, None).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].book_id.as_deref(), Some("42"));
+
+        let id = queued[0].id.clone();
+        let wait_for_failure = || {
+            for _ in 0..200 {
+                let job = list_conversion_jobs().into_iter().find(|j| j.id == id).unwrap();
+                if job.status == "failed" {
+                    return job;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            panic!("job did not finish");
+        };
+
+        let job = wait_for_failure();
+        assert!(job.error.unwrap().contains("EPUB file not found"));
+        assert!(cancel_conversion_job(id.clone()).is_err());
+
+        let retried = retry_conversion_job(id.clone()).unwrap();
+        assert_eq!(retried.error, None);
+        wait_for_failure();
+        assert!(retry_conversion_job("missing".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_prune_finished_jobs() {
+        let entry = |id: usize, status: &str| QueueEntry {
+            job: QueuedConversion {
+                id: format!("conversion-{}", id),
+                book_id: None,
+                source_path: String::new(),
+                target_path: String::new(),
+                converter: "native".to_string(),
+                status: status.to_string(),
+                progress: 0.0,
+                output_path: None,
+                error: None,
+                diagnostic: None,
+                warnings: Vec::new(),
+                queued_at: now(),
+                started_at: None,
+                completed_at: None,
+            },
+            settings: EpubToPdfSettings::default(),
+            control: ConversionControl::new(&EpubToPdfSettings::default()),
+            on_finished: None,
+        };
+        let statuses = ["completed", "pending", "failed", "converting", "cancelled", "completed"];
+        let mut queue = JobQueue {
+            entries: statuses.iter().enumerate().map(|(i, s)| entry(i, s)).collect(),
+            ..JobQueue::default()
+        };
+        let ids = |queue: &JobQueue| queue.entries.iter().map(|e| e.job.id.clone()).collect::<Vec<_>>();
+
+        assert_eq!(queue.prune_finished(2), 2);
+        assert_eq!(ids(&queue), vec!["conversion-1", "conversion-3", "conversion-4", "conversion-5"]);
+        // Pending and running jobs are never removed
+        assert_eq!(queue.prune_finished(0), 2);
+        assert_eq!(ids(&queue), vec!["conversion-1", "conversion-3"]);
+    }
+
+    #[test]
+    fn test_parallel_batch() {
+        let jobs: Vec<ConversionJob> = (0..5)
+            .map(|i| ConversionJob {
+                epub_path: format!("/nonexistent/book-{}.epub", i),
+                pdf_path: Some(format!("/nonexistent/book-{}.pdf", i)),
+                book_id: None,
+                layout_profile: None,
+            })
+            .collect();
+        let settings = EpubToPdfSettings {
+            parallel_jobs: 3,
+            ..EpubToPdfSettings::default()
+        };
+        let batch = convert_multiple_epub_to_pdf(jobs, "native".to_string(), None, Some(settings));
+        assert_eq!(batch.failed, 5);
+        let sources: Vec<&str> = batch.jobs.iter().map(|j| j.source_path.as_str()).collect();
+        assert_eq!(sources, (0..5).map(|i| format!("/nonexistent/book-{}.epub", i)).collect::<Vec<_>>());
+        assert!(batch.jobs.iter().all(|j| j.status == "failed"));
+        assert_eq!(batch.errors[4].file, "/nonexistent/book-4.epub");
+
+        let auto = EpubToPdfSettings::default();
+        assert!(parallel_jobs(&auto) >= 1);
+    }
+
+    #[test]
+    fn test_latex_slots() {
+        let settings = EpubToPdfSettings {
+            max_latex_jobs: 1,
+            ..EpubToPdfSettings::default()
+        };
+        let control = ConversionControl::new(&settings);
+        let slot = acquire_latex_slot(&settings, &control).unwrap();
+
+        // The second run waits for the first one and gives up when cancelled
+        let waiting = ConversionControl::new(&settings);
+        waiting.cancel.store(true, Ordering::SeqCst);
+        assert!(acquire_latex_slot(&settings, &waiting).is_none());
+
+        drop(slot);
+        assert!(acquire_latex_slot(&settings, &control).is_some());
+    }
 
-        println!("Calibre: {:?}", calibre);
-        println!("Pandoc: {:?}", pandoc);
+    #[test]
+    fn test_settings_validation() {
+        let auto = EpubToPdfSettings {
+            page_size: "auto".to_string(),
+            ..EpubToPdfSettings::default()
+        };
+        assert!(validate_settings(&auto, "calibre").is_ok());
+        assert!(validate_settings(&auto, "pandoc").is_err());
+
+        let no_images = EpubToPdfSettings {
+            preserve_images: false,
+            ..EpubToPdfSettings::default()
+        };
+        assert!(validate_settings(&no_images, "calibre").is_err());
+        assert!(validate_settings(&no_images, "native").is_ok());
+        assert!(validate_settings(&auto, "native").is_ok());
+
+        let mut huge_margins = EpubToPdfSettings::default();
+        huge_margins.margins.left = 300.0;
+        huge_margins.margins.right = 300.0;
+        assert!(validate_settings(&huge_margins, "calibre").is_err());
+
+        let tabloid = EpubToPdfSettings {
+            page_size: "Tabloid".to_string(),
+            ..EpubToPdfSettings::default()
+        };
+        assert!(validate_settings(&tabloid, "calibre").is_err());
     }
 }
@@ -0,0 +1,1455 @@
+/// Native EPUB to PDF Renderer
+/// Lays out the EPUB spine directly into PDF pages, without Calibre or Pandoc.
+/// Handles headings, paragraphs, bold/italic text, lists, images, fonts
+/// embedded in the EPUB and bookmarks built from the table of contents.
+/// The same parser also extracts the plain text of a book.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/epub_native.rs (needs epub_package.rs and layout_profiles.rs)
+/// 2. Add `mod epub_native;` to src-tauri/src/main.rs
+
+use printpdf::lopdf::{self, Dictionary, Object, ObjectId, StringFormat};
+use printpdf::{
+    BuiltinFont, ColorBits, ColorSpace, CustomPdfConformance, Image, ImageFilter, ImageTransform,
+    ImageXObject, IndirectFontRef, Mm, PdfConformance, PdfDocument, Px,
+};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+
+use super::custom_style::CustomStyle;
+use super::epub_converter::EpubToPdfSettings;
+use super::epub_package::{parse_xml, prepare_xml, resolve_href, strip_fragment, EpubPackage};
+use super::layout_profiles;
+
+const BASE_FONT_SIZE: f32 = 11.0; // Body text without a layout profile
+const LINE_HEIGHT: f32 = 1.35;
+const PARAGRAPH_SPACING: f32 = 0.45; // In lines of the block's font size
+const INDENT_STEP: f32 = 18.0; // Points per list / blockquote level
+const CSS_PX_TO_PT: f32 = 0.75;
+
+/// System serif families tried when the EPUB does not embed a body font
+/// (regular, bold, italic, bold italic)
+const SYSTEM_FONTS: &[[&str; 4]] = &[
+    [
+        "/usr/share/fonts/truetype/dejavu/DejaVuSerif.ttf",
+        "/usr/share/fonts/truetype/dejavu/DejaVuSerif-Bold.ttf",
+        "/usr/share/fonts/truetype/dejavu/DejaVuSerif-Italic.ttf",
+        "/usr/share/fonts/truetype/dejavu/DejaVuSerif-BoldItalic.ttf",
+    ],
+    [
+        "/usr/share/fonts/dejavu-serif-fonts/DejaVuSerif.ttf",
+        "/usr/share/fonts/dejavu-serif-fonts/DejaVuSerif-Bold.ttf",
+        "/usr/share/fonts/dejavu-serif-fonts/DejaVuSerif-Italic.ttf",
+        "/usr/share/fonts/dejavu-serif-fonts/DejaVuSerif-BoldItalic.ttf",
+    ],
+    [
+        "/usr/share/fonts/truetype/liberation/LiberationSerif-Regular.ttf",
+        "/usr/share/fonts/truetype/liberation/LiberationSerif-Bold.ttf",
+        "/usr/share/fonts/truetype/liberation/LiberationSerif-Italic.ttf",
+        "/usr/share/fonts/truetype/liberation/LiberationSerif-BoldItalic.ttf",
+    ],
+    [
+        "/System/Library/Fonts/Supplemental/Georgia.ttf",
+        "/System/Library/Fonts/Supplemental/Georgia Bold.ttf",
+        "/System/Library/Fonts/Supplemental/Georgia Italic.ttf",
+        "/System/Library/Fonts/Supplemental/Georgia Bold Italic.ttf",
+    ],
+    [
+        "C:\\Windows\\Fonts\\georgia.ttf",
+        "C:\\Windows\\Fonts\\georgiab.ttf",
+        "C:\\Windows\\Fonts\\georgiai.ttf",
+        "C:\\Windows\\Fonts\\georgiaz.ttf",
+    ],
+];
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontStyle {
+    fn new(bold: bool, italic: bool) -> Self {
+        match (bold, italic) {
+            (false, false) => FontStyle::Regular,
+            (true, false) => FontStyle::Bold,
+            (false, true) => FontStyle::Italic,
+            (true, true) => FontStyle::BoldItalic,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    text: String,
+    style: FontStyle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockContent {
+    Text { heading: Option<u8>, runs: Vec<Run>, indent: f32 },
+    Image { href: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    content: BlockContent,
+    anchors: Vec<String>, // Full hrefs ("path#id") pointing at this block
+}
+
+#[derive(Debug, Clone)]
+enum DrawOp {
+    Text { x: f32, y: f32, size: f32, style: FontStyle, text: String },
+    Image { index: usize, x: f32, y: f32, width: f32, height: f32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PageGeometry {
+    width: f32, // Points
+    height: f32,
+    top: f32,
+    bottom: f32,
+    left: f32,
+    right: f32,
+    gutter: f32, // Added to the inner margin: left on odd pages, right on even ones
+}
+
+impl PageGeometry {
+    fn from_settings(settings: &EpubToPdfSettings) -> Self {
+        if let Some(profile) = layout_profiles::selected_profile(settings).ok().flatten() {
+            let m = &profile.margins;
+            return PageGeometry {
+                width: profile.width as f32,
+                height: profile.height as f32,
+                top: m.top as f32,
+                bottom: m.bottom as f32,
+                left: m.left as f32,
+                right: m.right as f32,
+                gutter: profile.gutter as f32,
+            };
+        }
+
+        let (width, height) = match settings.page_size.as_str() {
+            "Letter" => (612.0, 792.0),
+            // No output profile to follow: use a reading-friendly A5
+            "auto" => (419.53, 595.28),
+            _ => (595.28, 841.89),
+        };
+        let m = &settings.margins;
+        PageGeometry {
+            width,
+            height,
+            top: m.top as f32,
+            bottom: m.bottom as f32,
+            left: m.left as f32,
+            right: m.right as f32,
+            gutter: 0.0,
+        }
+    }
+
+    fn content_width(&self) -> f32 {
+        self.width - self.left - self.right - self.gutter
+    }
+
+    fn content_height(&self) -> f32 {
+        self.height - self.top - self.bottom
+    }
+
+    /// Horizontal shift of the page at `index` in the final document (0 is the first, right-hand page)
+    fn gutter_offset(&self, index: usize) -> f32 {
+        if index.is_multiple_of(2) {
+            self.gutter
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Body font size and hyphenation language
+#[derive(Debug, Clone, Copy)]
+struct Typography {
+    font_size: f32,
+    hyphenation: Option<hypher::Lang>,
+}
+
+impl Typography {
+    /// Hyphenation needs a layout profile that asks for it and a book language hypher knows
+    fn from_settings(settings: &EpubToPdfSettings, language: Option<&str>) -> Self {
+        let Some(profile) = layout_profiles::selected_profile(settings).ok().flatten() else {
+            return Typography { font_size: BASE_FONT_SIZE, hyphenation: None };
+        };
+        let hyphenation = language
+            .filter(|_| profile.hyphenation)
+            .and_then(|l| l.as_bytes().get(..2))
+            .and_then(|code| hypher::Lang::from_iso([code[0].to_ascii_lowercase(), code[1].to_ascii_lowercase()]));
+        Typography {
+            font_size: profile.font_size as f32,
+            hyphenation,
+        }
+    }
+}
+
+// ============================================================================
+// Fonts
+// ============================================================================
+
+struct FontFace {
+    data: Option<Vec<u8>>, // None for the builtin Times fallback
+    widths: HashMap<char, f32>, // Advance in em, cached per character
+}
+
+impl FontFace {
+    fn builtin() -> Self {
+        FontFace { data: None, widths: HashMap::new() }
+    }
+
+    /// Accept TrueType outlines only, which is what printpdf can embed
+    fn truetype(data: Vec<u8>) -> Option<Self> {
+        let face = ttf_parser::Face::parse(&data, 0).ok()?;
+        face.tables().glyf?;
+        Some(FontFace { data: Some(data), widths: HashMap::new() })
+    }
+
+    fn char_width(&mut self, c: char) -> f32 {
+        if let Some(width) = self.widths.get(&c) {
+            return *width;
+        }
+        let width = match &self.data {
+            Some(data) => ttf_parser::Face::parse(data, 0)
+                .ok()
+                .and_then(|face| {
+                    let advance = face.glyph_index(c).and_then(|id| face.glyph_hor_advance(id))?;
+                    Some(advance as f32 / face.units_per_em() as f32)
+                })
+                .unwrap_or(0.0),
+            // Rough Times-Roman averages, good enough for line breaking
+            None if c == ' ' || c == '\u{a0}' => 0.25,
+            None if c.is_uppercase() => 0.68,
+            None => 0.46,
+        };
+        self.widths.insert(c, width);
+        width
+    }
+
+    fn text_width(&mut self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c)).sum::<f32>() * size
+    }
+}
+
+struct FontSet {
+    faces: Vec<FontFace>,
+    styles: [usize; 4], // Face index for each FontStyle
+}
+
+impl FontSet {
+    fn builtin() -> Self {
+        FontSet {
+            faces: (0..4).map(|_| FontFace::builtin()).collect(),
+            styles: [0, 1, 2, 3],
+        }
+    }
+
+    /// Build a set from up to four faces; missing styles use the regular face
+    fn from_faces(regular: FontFace, others: [Option<FontFace>; 3]) -> Self {
+        let mut faces = vec![regular];
+        let mut styles = [0; 4];
+        for (i, face) in others.into_iter().enumerate() {
+            if let Some(face) = face {
+                faces.push(face);
+                styles[i + 1] = faces.len() - 1;
+            }
+        }
+        FontSet { faces, styles }
+    }
+
+    fn is_builtin(&self) -> bool {
+        self.faces[0].data.is_none()
+    }
+
+    fn face(&mut self, style: FontStyle) -> &mut FontFace {
+        &mut self.faces[self.styles[style.index()]]
+    }
+
+    fn text_width(&mut self, text: &str, style: FontStyle, size: f32) -> f32 {
+        self.face(style).text_width(text, size)
+    }
+}
+
+fn load_system_fonts() -> Option<FontSet> {
+    SYSTEM_FONTS.iter().find_map(|paths| {
+        let load = |path: &str| std::fs::read(path).ok().and_then(FontFace::truetype);
+        let regular = load(paths[0])?;
+        Some(FontSet::from_faces(regular, [load(paths[1]), load(paths[2]), load(paths[3])]))
+    })
+}
+
+/// The user's body fonts from the settings; CFF-based OpenType fonts cannot be embedded here
+fn custom_fonts(style: &CustomStyle) -> Option<FontSet> {
+    let load = |bold: bool, italic: bool| {
+        let font = style.face(bold, italic)?;
+        std::fs::read(&font.path).ok().and_then(FontFace::truetype)
+    };
+    let regular = load(false, false)?;
+    Some(FontSet::from_faces(regular, [load(true, false), load(false, true), load(true, true)]))
+}
+
+/// Rules of a stylesheet as (selector, declarations), comments and nested blocks skipped
+pub fn parse_css_rules(css: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut text = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        text.push_str(&rest[..start]);
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    text.push_str(rest);
+
+    let mut rules = Vec::new();
+    let mut depth = 0;
+    let mut selector_start = 0;
+    let mut body_start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    body_start = i + 1;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let selector = text[selector_start..body_start - 1].trim().to_string();
+                    let body = &text[body_start..i];
+                    if !body.contains('{') {
+                        let declarations = body
+                            .split(';')
+                            .filter_map(|d| d.split_once(':'))
+                            .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+                            .collect();
+                        rules.push((selector, declarations));
+                    }
+                    selector_start = i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    rules
+}
+
+pub fn first_font_family(value: &str) -> String {
+    value
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .to_lowercase()
+}
+
+/// Font family the EPUB applies to body text together with its @font-face sources
+fn embedded_body_fonts(package: &mut EpubPackage) -> Option<FontSet> {
+    let stylesheets: Vec<String> = package
+        .manifest
+        .iter()
+        .filter(|i| i.media_type == "text/css")
+        .map(|i| i.href.clone())
+        .collect();
+
+    let mut body_family = None;
+    let mut faces: Vec<(String, FontStyle, String)> = Vec::new(); // family, style, href
+    for href in stylesheets {
+        let Ok(bytes) = package.read_resource(&href) else { continue };
+        for (selector, declarations) in parse_css_rules(&String::from_utf8_lossy(&bytes)) {
+            let get = |name: &str| declarations.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+            if selector.eq_ignore_ascii_case("@font-face") {
+                let (Some(family), Some(src)) = (get("font-family"), get("src")) else { continue };
+                let Some(url) = src.split("url(").nth(1).and_then(|u| u.split(')').next()) else { continue };
+                let bold = get("font-weight").is_some_and(|w| {
+                    w == "bold" || w == "bolder" || w.parse::<u32>().is_ok_and(|w| w >= 600)
+                });
+                let italic = get("font-style").is_some_and(|s| s == "italic" || s == "oblique");
+                let url = url.trim().trim_matches(|c| c == '"' || c == '\'');
+                faces.push((first_font_family(family), FontStyle::new(bold, italic), resolve_href(&href, url)));
+            } else if body_family.is_none() {
+                let targets_body = selector
+                    .split(',')
+                    .any(|s| matches!(s.trim(), "body" | "p" | "html"));
+                if let (true, Some(family)) = (targets_body, get("font-family")) {
+                    body_family = Some(first_font_family(family));
+                }
+            }
+        }
+    }
+
+    let family = body_family?;
+    let mut load = |style: FontStyle| {
+        let href = faces.iter().find(|(f, s, _)| *f == family && *s == style)?.2.clone();
+        // Obfuscated or WOFF fonts fail to parse and are skipped
+        package.read_resource(&href).ok().and_then(FontFace::truetype)
+    };
+    let regular = load(FontStyle::Regular)?;
+    Some(FontSet::from_faces(
+        regular,
+        [load(FontStyle::Bold), load(FontStyle::Italic), load(FontStyle::BoldItalic)],
+    ))
+}
+
+// ============================================================================
+// XHTML to Blocks
+// ============================================================================
+
+#[derive(Debug, Clone, Copy)]
+struct BlockContext {
+    heading: Option<u8>,
+    indent: f32,
+    bold: bool,
+    italic: bool,
+    preformatted: bool,
+}
+
+struct BlockBuilder {
+    href: String,
+    blocks: Vec<Block>,
+    runs: Vec<Run>,
+    anchors: Vec<String>,
+    preserve_images: bool,
+}
+
+impl BlockBuilder {
+    fn push_text(&mut self, text: &str, ctx: &BlockContext) {
+        let style = FontStyle::new(ctx.bold || ctx.heading.is_some(), ctx.italic);
+        let text = if ctx.preformatted {
+            text.to_string()
+        } else {
+            text.replace('\n', " ")
+        };
+        match self.runs.last_mut() {
+            Some(last) if last.style == style => last.text.push_str(&text),
+            _ => self.runs.push(Run { text, style }),
+        }
+    }
+
+    /// Close the pending text block; anchors wait for the next block if it is empty
+    fn flush(&mut self, ctx: &BlockContext) {
+        let runs = std::mem::take(&mut self.runs);
+        if runs.iter().all(|r| r.text.trim().is_empty()) {
+            return;
+        }
+        self.blocks.push(Block {
+            content: BlockContent::Text { heading: ctx.heading, runs, indent: ctx.indent },
+            anchors: std::mem::take(&mut self.anchors),
+        });
+    }
+
+    fn push_image(&mut self, src: &str, ctx: &BlockContext) {
+        self.flush(ctx);
+        if !self.preserve_images {
+            return;
+        }
+        self.blocks.push(Block {
+            content: BlockContent::Image { href: resolve_href(&self.href, src) },
+            anchors: std::mem::take(&mut self.anchors),
+        });
+    }
+}
+
+fn list_marker(node: roxmltree::Node) -> String {
+    match node.parent_element() {
+        Some(list) if list.tag_name().name() == "ol" => {
+            let start: usize = list.attribute("start").and_then(|s| s.parse().ok()).unwrap_or(1);
+            let position = node.prev_siblings().filter(|n| n.tag_name().name() == "li").count();
+            format!("{}. ", start + position - 1)
+        }
+        _ => "• ".to_string(),
+    }
+}
+
+fn walk(node: roxmltree::Node, ctx: BlockContext, builder: &mut BlockBuilder) {
+    if node.is_text() {
+        if let Some(text) = node.text() {
+            builder.push_text(text, &ctx);
+        }
+        return;
+    }
+    if !node.is_element() {
+        return;
+    }
+
+    let name = node.tag_name().name().to_lowercase();
+    let is_block = matches!(
+        name.as_str(),
+        "p" | "div" | "section" | "article" | "blockquote" | "li" | "ul" | "ol" | "dl" | "dt" | "dd"
+            | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre" | "table" | "tr" | "figure"
+            | "figcaption" | "header" | "footer" | "aside" | "body" | "hr" | "caption"
+    );
+
+    // Text before a nested block belongs to the enclosing block
+    if is_block {
+        builder.flush(&ctx);
+    }
+    if let Some(id) = node.attribute("id") {
+        builder.anchors.push(format!("{}#{}", builder.href, id));
+    }
+
+    let mut inner = ctx;
+    match name.as_str() {
+        "head" | "script" | "style" | "title" => return,
+        "br" => {
+            builder.push_text("\n", &BlockContext { preformatted: true, ..ctx });
+            return;
+        }
+        "img" => {
+            if let Some(src) = node.attribute("src") {
+                builder.push_image(src, &ctx);
+            }
+            return;
+        }
+        "image" => {
+            let href = node
+                .attributes()
+                .find(|a| a.name() == "href")
+                .map(|a| a.value().to_string());
+            if let Some(href) = href {
+                builder.push_image(&href, &ctx);
+            }
+            return;
+        }
+        "b" | "strong" | "th" => inner.bold = true,
+        "i" | "em" | "cite" | "var" | "dfn" => inner.italic = true,
+        "td" => builder.push_text(" ", &ctx),
+        _ => {}
+    }
+
+    if !is_block {
+        for child in node.children() {
+            walk(child, inner, builder);
+        }
+        return;
+    }
+
+    match name.as_str() {
+        "blockquote" | "dd" | "li" => inner.indent += INDENT_STEP,
+        "pre" => inner.preformatted = true,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => inner.heading = name[1..].parse().ok(),
+        _ => {}
+    }
+    if name == "li" {
+        builder.push_text(&list_marker(node), &inner);
+    }
+    for child in node.children() {
+        walk(child, inner, builder);
+    }
+    builder.flush(&inner);
+}
+
+/// Blocks of one spine document
+fn parse_document(href: &str, xhtml: &str, preserve_images: bool) -> Result<Vec<Block>, String> {
+    let doc = parse_xml(xhtml).map_err(|e| format!("Failed to parse {}: {}", href, e))?;
+    let mut builder = BlockBuilder {
+        href: href.to_string(),
+        blocks: Vec::new(),
+        runs: Vec::new(),
+        anchors: Vec::new(),
+        preserve_images,
+    };
+    let ctx = BlockContext {
+        heading: None,
+        indent: 0.0,
+        bold: false,
+        italic: false,
+        preformatted: false,
+    };
+
+    let body = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "body")
+        .unwrap_or(doc.root_element());
+    walk(body, ctx, &mut builder);
+    builder.flush(&ctx);
+    Ok(builder.blocks)
+}
+
+// ============================================================================
+// Images
+// ============================================================================
+
+/// Number of colour components declared in a JPEG frame header
+pub fn jpeg_components(bytes: &[u8]) -> Option<u8> {
+    let mut i = 2;
+    while i + 9 < bytes.len() {
+        if bytes[i] != 0xFF {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+        if (0xC0..=0xC2).contains(&marker) {
+            return Some(bytes[i + 9]);
+        }
+        i += 2 + length;
+    }
+    None
+}
+
+fn prepare_image(bytes: &[u8]) -> Option<ImageXObject> {
+    let decoded = image::load_from_memory(bytes).ok()?;
+    let (width, height) = (decoded.width() as usize, decoded.height() as usize);
+
+    // Baseline JPEGs can be embedded as-is
+    let is_jpeg = bytes.starts_with(&[0xFF, 0xD8]);
+    let (color_space, image_data, image_filter) = match (is_jpeg, jpeg_components(bytes)) {
+        (true, Some(1)) => (ColorSpace::Greyscale, bytes.to_vec(), Some(ImageFilter::DCT)),
+        (true, Some(3)) => (ColorSpace::Rgb, bytes.to_vec(), Some(ImageFilter::DCT)),
+        _ => {
+            // Flatten transparency onto a white page
+            let rgba = decoded.to_rgba8();
+            let mut rgb = Vec::with_capacity(width * height * 3);
+            for pixel in rgba.pixels() {
+                let alpha = pixel[3] as u32;
+                for channel in &pixel.0[..3] {
+                    rgb.push(((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8);
+                }
+            }
+            (ColorSpace::Rgb, rgb, None)
+        }
+    };
+
+    Some(ImageXObject {
+        width: Px(width),
+        height: Px(height),
+        color_space,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data,
+        image_filter,
+        smask: None,
+        clipping_bbox: None,
+    })
+}
+
+// ============================================================================
+// Layout
+// ============================================================================
+
+struct Layout<'a> {
+    fonts: &'a mut FontSet,
+    geometry: PageGeometry,
+    typography: Typography,
+    pages: Vec<Vec<DrawOp>>,
+    images: Vec<ImageXObject>,
+    y: f32, // Distance from the top of the content area
+    anchors: HashMap<String, usize>, // Href -> page index
+}
+
+/// One word made of differently styled pieces ("<b>Dune</b>'s")
+type Word = Vec<(String, FontStyle)>;
+
+enum Token {
+    Word(Word),
+    Break,
+}
+
+fn tokenize(runs: &[Run]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word: Word = Vec::new();
+    let end_word = |word: &mut Word, tokens: &mut Vec<Token>| {
+        if !word.is_empty() {
+            tokens.push(Token::Word(std::mem::take(word)));
+        }
+    };
+
+    for run in runs {
+        for c in run.text.chars() {
+            match c {
+                '\n' => {
+                    end_word(&mut word, &mut tokens);
+                    tokens.push(Token::Break);
+                }
+                '\u{ad}' => {}
+                c if c.is_whitespace() && c != '\u{a0}' => end_word(&mut word, &mut tokens),
+                c => match word.last_mut() {
+                    Some((text, style)) if *style == run.style => text.push(c),
+                    _ => word.push((c.to_string(), run.style)),
+                },
+            }
+        }
+    }
+    end_word(&mut word, &mut tokens);
+    tokens
+}
+
+impl<'a> Layout<'a> {
+    fn new(fonts: &'a mut FontSet, geometry: PageGeometry, typography: Typography) -> Self {
+        Layout {
+            fonts,
+            geometry,
+            typography,
+            pages: vec![Vec::new()],
+            images: Vec::new(),
+            y: 0.0,
+            anchors: HashMap::new(),
+        }
+    }
+
+    fn page_index(&self) -> usize {
+        self.pages.len() - 1
+    }
+
+    fn new_page(&mut self) {
+        if self.y > 0.0 || !self.pages[self.page_index()].is_empty() {
+            self.pages.push(Vec::new());
+        }
+        self.y = 0.0;
+    }
+
+    /// Move to a new page when `height` does not fit on the current one
+    fn reserve(&mut self, height: f32) {
+        if self.y > 0.0 && self.y + height > self.geometry.content_height() {
+            self.new_page();
+        }
+    }
+
+    fn mark(&mut self, anchors: &[String]) {
+        let page = self.page_index();
+        for anchor in anchors {
+            self.anchors.entry(anchor.clone()).or_insert(page);
+        }
+    }
+
+    fn word_width(&mut self, word: &Word, size: f32) -> f32 {
+        word.iter().map(|(text, style)| self.fonts.text_width(text, *style, size)).sum()
+    }
+
+    /// Split a word wider than the line into chunks that fit
+    fn split_word(&mut self, word: Word, size: f32, max_width: f32) -> Vec<Word> {
+        let mut chunks = Vec::new();
+        let mut chunk: Word = Vec::new();
+        let mut width = 0.0;
+        for (text, style) in word {
+            for c in text.chars() {
+                let w = self.fonts.face(style).char_width(c) * size;
+                if width + w > max_width && !chunk.is_empty() {
+                    chunks.push(std::mem::take(&mut chunk));
+                    width = 0.0;
+                }
+                width += w;
+                match chunk.last_mut() {
+                    Some((t, s)) if *s == style => t.push(c),
+                    _ => chunk.push((c.to_string(), style)),
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    fn emit_line(&mut self, line: Vec<(String, FontStyle, f32)>, size: f32, indent: f32, anchors: &mut Vec<String>) {
+        let line_height = size * LINE_HEIGHT;
+        self.reserve(line_height);
+        if !anchors.is_empty() {
+            self.mark(anchors);
+            anchors.clear();
+        }
+
+        let baseline = self.geometry.height - self.geometry.top - self.y - size;
+        let page = self.page_index();
+        for (text, style, x) in line {
+            self.pages[page].push(DrawOp::Text {
+                x: self.geometry.left + indent + x,
+                y: baseline,
+                size,
+                style,
+                text,
+            });
+        }
+        self.y += line_height;
+    }
+
+    /// Split a single-style word at the last syllable break where the first part and a
+    /// hyphen fit in `room`; words that already contain a hyphen are left whole
+    fn hyphenate(&mut self, word: &Word, size: f32, room: f32) -> Option<(Word, Word)> {
+        let lang = self.typography.hyphenation?;
+        let [(text, style)] = word.as_slice() else {
+            return None;
+        };
+        if text.contains('-') {
+            return None;
+        }
+
+        let syllables: Vec<&str> = hypher::hyphenate(text, lang).collect();
+        let mut split = None;
+        let mut head = 0;
+        for syllable in &syllables[..syllables.len().saturating_sub(1)] {
+            head += syllable.len();
+            if self.fonts.text_width(&format!("{}-", &text[..head]), *style, size) > room {
+                break;
+            }
+            split = Some(head);
+        }
+        let split = split?;
+        Some((
+            vec![(format!("{}-", &text[..split]), *style)],
+            vec![(text[split..].to_string(), *style)],
+        ))
+    }
+
+    /// Append a word to the line, after a space unless it starts the line
+    fn push_word(&mut self, line: &mut Vec<(String, FontStyle, f32)>, x: &mut f32, word: Word, size: f32, space: f32) {
+        let mut gap = if line.is_empty() { 0.0 } else { space };
+        for (text, style) in word {
+            let piece_width = self.fonts.text_width(&text, style, size);
+            match line.last_mut() {
+                Some((last, last_style, _)) if *last_style == style => {
+                    if gap > 0.0 {
+                        last.push(' ');
+                    }
+                    last.push_str(&text);
+                }
+                _ => line.push((text, style, *x + gap)),
+            }
+            *x += gap + piece_width;
+            gap = 0.0;
+        }
+    }
+
+    fn add_text(&mut self, runs: &[Run], heading: Option<u8>, indent: f32, anchors: &[String]) {
+        let base = self.typography.font_size;
+        let size = match heading {
+            Some(1) => base * 2.0,
+            Some(2) => base * 1.6,
+            Some(3) => base * 1.3,
+            Some(_) => base * 1.1,
+            None => base,
+        };
+        if heading.is_some() && self.y > 0.0 {
+            self.y += size * 0.8;
+        }
+
+        let max_width = (self.geometry.content_width() - indent).max(size * 4.0);
+        let space = self.fonts.text_width(" ", FontStyle::Regular, size);
+        let mut anchors = anchors.to_vec();
+        let mut line: Vec<(String, FontStyle, f32)> = Vec::new();
+        let mut x = 0.0;
+
+        for token in tokenize(runs) {
+            let word = match token {
+                Token::Break => {
+                    self.emit_line(std::mem::take(&mut line), size, indent, &mut anchors);
+                    x = 0.0;
+                    continue;
+                }
+                Token::Word(word) => word,
+            };
+
+            let width = self.word_width(&word, size);
+            let words = if width > max_width {
+                self.split_word(word, size, max_width)
+            } else {
+                vec![word]
+            };
+
+            for mut word in words {
+                let width = self.word_width(&word, size);
+                if !line.is_empty() && x + space + width > max_width {
+                    // Fill the line with the first syllables when the profile hyphenates
+                    if let Some((head, tail)) = self.hyphenate(&word, size, max_width - x - space) {
+                        self.push_word(&mut line, &mut x, head, size, space);
+                        word = tail;
+                    }
+                    self.emit_line(std::mem::take(&mut line), size, indent, &mut anchors);
+                    x = 0.0;
+                }
+                self.push_word(&mut line, &mut x, word, size, space);
+            }
+        }
+        if !line.is_empty() {
+            self.emit_line(line, size, indent, &mut anchors);
+        }
+        self.y += size * LINE_HEIGHT * PARAGRAPH_SPACING;
+    }
+
+    fn add_image(&mut self, image: ImageXObject, anchors: &[String]) {
+        let (px_width, px_height) = (image.width.0 as f32, image.height.0 as f32);
+        if px_width == 0.0 || px_height == 0.0 {
+            return;
+        }
+
+        let mut width = px_width * CSS_PX_TO_PT;
+        let mut height = px_height * CSS_PX_TO_PT;
+        let scale = (self.geometry.content_width() / width)
+            .min(self.geometry.content_height() / height)
+            .min(1.0);
+        width *= scale;
+        height *= scale;
+
+        self.reserve(height);
+        self.mark(anchors);
+
+        let page = self.page_index();
+        self.pages[page].push(DrawOp::Image {
+            index: self.images.len(),
+            x: self.geometry.left + (self.geometry.content_width() - width) / 2.0,
+            y: self.geometry.height - self.geometry.top - self.y - height,
+            width,
+            height,
+        });
+        self.images.push(image);
+        self.y += height + self.typography.font_size * LINE_HEIGHT * PARAGRAPH_SPACING;
+    }
+
+    /// "Contents" pages listing TOC entries with their page numbers
+    fn add_contents(&mut self, entries: &[(String, usize, usize)]) {
+        self.add_text(
+            &[Run { text: "Contents".to_string(), style: FontStyle::Bold }],
+            Some(1),
+            0.0,
+            &[],
+        );
+
+        let size = self.typography.font_size;
+        for (title, level, page) in entries {
+            let indent = *level as f32 * INDENT_STEP;
+            let number = (page + 1).to_string();
+            let number_width = self.fonts.text_width(&number, FontStyle::Regular, size);
+            let max_title = self.geometry.content_width() - indent - number_width - size;
+
+            // One line per entry: truncate long titles
+            let mut title = title.clone();
+            while !title.is_empty() && self.fonts.text_width(&title, FontStyle::Regular, size) > max_title {
+                title.pop();
+            }
+
+            let number_x = self.geometry.content_width() - indent - number_width;
+            self.emit_line(
+                vec![(title, FontStyle::Regular, 0.0), (number, FontStyle::Regular, number_x)],
+                size,
+                indent,
+                &mut Vec::new(),
+            );
+        }
+    }
+}
+
+// ============================================================================
+// PDF Output
+// ============================================================================
+
+/// PDF text string, UTF-16BE with BOM so non-ASCII titles survive
+pub fn pdf_text(value: &str) -> Object {
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in value.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// Replace the document outline with a nested one built from (title, level, page) entries
+fn write_outline(doc: &mut lopdf::Document, entries: &[(String, usize, usize)]) -> Result<(), String> {
+    let pages: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+    if entries.is_empty() || pages.is_empty() {
+        return Ok(());
+    }
+
+    // Parent of each entry, derived from the TOC levels
+    let mut parents: Vec<Option<usize>> = Vec::with_capacity(entries.len());
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, (_, level, _)) in entries.iter().enumerate() {
+        while stack.len() > *level {
+            stack.pop();
+        }
+        parents.push(stack.last().copied());
+        stack.push(i);
+    }
+
+    let root_id = doc.new_object_id();
+    let ids: Vec<ObjectId> = entries.iter().map(|_| doc.new_object_id()).collect();
+    let children = |parent: Option<usize>| -> Vec<usize> {
+        (0..entries.len()).filter(|i| parents[*i] == parent).collect()
+    };
+
+    for (i, (title, _, page)) in entries.iter().enumerate() {
+        let siblings = children(parents[i]);
+        let position = siblings.iter().position(|s| *s == i).unwrap_or(0);
+        let page_id = pages[(*page).min(pages.len() - 1)];
+
+        let mut item = Dictionary::new();
+        item.set("Title", pdf_text(title));
+        item.set("Parent", parents[i].map_or(root_id, |p| ids[p]));
+        item.set(
+            "Dest",
+            vec![Object::Reference(page_id), Object::Name(b"Fit".to_vec())],
+        );
+        if position > 0 {
+            item.set("Prev", ids[siblings[position - 1]]);
+        }
+        if let Some(next) = siblings.get(position + 1) {
+            item.set("Next", ids[*next]);
+        }
+        let kids = children(Some(i));
+        if let (Some(first), Some(last)) = (kids.first(), kids.last()) {
+            item.set("First", ids[*first]);
+            item.set("Last", ids[*last]);
+            // Negative count: entries start collapsed
+            item.set("Count", -(kids.len() as i64));
+        }
+        doc.objects.insert(ids[i], Object::Dictionary(item));
+    }
+
+    let top = children(None);
+    let mut root = Dictionary::new();
+    root.set("Type", Object::Name(b"Outlines".to_vec()));
+    root.set("First", ids[top[0]]);
+    root.set("Last", ids[*top.last().unwrap_or(&top[0])]);
+    root.set("Count", top.len() as i64);
+    doc.objects.insert(root_id, Object::Dictionary(root));
+
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| format!("Failed to read PDF catalog: {}", e))?;
+    catalog.set("Outlines", root_id);
+    catalog.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+    Ok(())
+}
+
+fn write_pdf(
+    pdf_path: &Path,
+    title: &str,
+    layout: Layout,
+    outline: &[(String, usize, usize)],
+    compress: bool,
+) -> Result<(), String> {
+    let g = layout.geometry;
+    let to_mm = |pt: f32| Mm(pt * 25.4 / 72.0);
+
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(title, to_mm(g.width), to_mm(g.height), "Content");
+    let doc = doc.with_conformance(PdfConformance::Custom(CustomPdfConformance {
+        allows_default_fonts: true,
+        allows_pdf_layers: false,
+        ..CustomPdfConformance::default()
+    }));
+
+    // One PDF font per distinct face
+    let mut fonts: Vec<IndirectFontRef> = Vec::new();
+    let builtin = [
+        BuiltinFont::TimesRoman,
+        BuiltinFont::TimesBold,
+        BuiltinFont::TimesItalic,
+        BuiltinFont::TimesBoldItalic,
+    ];
+    for (i, face) in layout.fonts.faces.iter().enumerate() {
+        let font = match &face.data {
+            Some(data) => doc.add_external_font(Cursor::new(data.clone())),
+            None => doc.add_builtin_font(builtin[i]),
+        };
+        fonts.push(font.map_err(|e| format!("Failed to embed font: {}", e))?);
+    }
+
+    for (index, ops) in layout.pages.into_iter().enumerate() {
+        let shift = g.gutter_offset(index);
+        let (page, layer) = if index == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(to_mm(g.width), to_mm(g.height), "Content")
+        };
+        let layer = doc.get_page(page).get_layer(layer);
+
+        for op in ops {
+            match op {
+                DrawOp::Text { x, y, size, style, text } => {
+                    let font = &fonts[layout.fonts.styles[style.index()]];
+                    layer.use_text(text, size, to_mm(x + shift), to_mm(y), font);
+                }
+                DrawOp::Image { index, x, y, width, height } => {
+                    let image = layout.images[index].clone();
+                    let (px_width, px_height) = (image.width.0 as f32, image.height.0 as f32);
+                    // At 72 dpi one pixel is one point
+                    Image::from(image).add_to_layer(
+                        layer.clone(),
+                        ImageTransform {
+                            translate_x: Some(to_mm(x + shift)),
+                            translate_y: Some(to_mm(y)),
+                            scale_x: Some(width / px_width),
+                            scale_y: Some(height / px_height),
+                            dpi: Some(72.0),
+                            ..ImageTransform::default()
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    let bytes = doc
+        .save_to_bytes()
+        .map_err(|e| format!("Failed to write PDF: {}", e))?;
+    let mut pdf = lopdf::Document::load_mem(&bytes)
+        .map_err(|e| format!("Failed to reload PDF: {}", e))?;
+    write_outline(&mut pdf, outline)?;
+    pdf.prune_objects();
+    if compress {
+        pdf.compress();
+    } else {
+        pdf.decompress();
+    }
+    pdf.save(pdf_path)
+        .map_err(|e| format!("Failed to save PDF: {}", e))?;
+    Ok(())
+}
+
+// ============================================================================
+// Conversion
+// ============================================================================
+
+/// Render an EPUB to PDF without external tools; `is_cancelled` is polled between chapters.
+/// Returns what was rendered differently from the book, such as a fallback font.
+pub fn render_epub_to_pdf(
+    epub_path: &Path,
+    pdf_path: &Path,
+    settings: &EpubToPdfSettings,
+    is_cancelled: &dyn Fn() -> bool,
+) -> Result<Vec<String>, String> {
+    let mut package = EpubPackage::open(epub_path)?;
+    let geometry = PageGeometry::from_settings(settings);
+    let mut typography = Typography::from_settings(settings, package.metadata.language.as_deref());
+    let style = CustomStyle::from_settings(settings)?;
+    if let Some(size) = style.as_ref().and_then(CustomStyle::body_font_size) {
+        typography.font_size = size;
+    }
+
+    let mut fonts = style
+        .as_ref()
+        .and_then(custom_fonts)
+        .or_else(|| embedded_body_fonts(&mut package))
+        .or_else(load_system_fonts)
+        .unwrap_or_else(FontSet::builtin);
+    let mut warnings = Vec::new();
+    if fonts.is_builtin() {
+        warnings.push("No TrueType font found, using builtin Times (Latin-1 only)".to_string());
+    }
+
+    let mut layout = Layout::new(&mut fonts, geometry, typography);
+    let spine: Vec<String> = package
+        .spine
+        .iter()
+        .filter(|s| s.linear)
+        .filter_map(|s| s.href.clone())
+        .collect();
+
+    for href in spine {
+        if is_cancelled() {
+            return Err("Conversion cancelled".to_string());
+        }
+        let xhtml = prepare_xml(&package.read_resource(&href)?);
+        let blocks = parse_document(&href, &xhtml, settings.preserve_images)?;
+
+        // Every spine document starts a new page, as in the source book
+        layout.new_page();
+        layout.mark(std::slice::from_ref(&href));
+
+        for block in blocks {
+            match block.content {
+                BlockContent::Text { heading, runs, indent } => {
+                    layout.add_text(&runs, heading, indent, &block.anchors)
+                }
+                BlockContent::Image { href } => {
+                    let image = package.read_resource(&href).ok().and_then(|b| prepare_image(&b));
+                    match image {
+                        Some(image) => layout.add_image(image, &block.anchors),
+                        None => layout.mark(&block.anchors),
+                    }
+                }
+            }
+        }
+    }
+
+    // TOC entries resolved to body pages
+    let mut outline: Vec<(String, usize, usize)> = package
+        .toc
+        .iter()
+        .filter_map(|entry| {
+            let page = layout
+                .anchors
+                .get(&entry.href)
+                .or_else(|| layout.anchors.get(strip_fragment(&entry.href)))?;
+            Some((entry.title.clone(), entry.level, *page))
+        })
+        .collect();
+
+    if settings.include_table_of_contents && !outline.is_empty() {
+        // Lay out once to count the contents pages, then again with final numbers
+        let count_pages = |fonts: &mut FontSet, offset: usize| {
+            let shifted: Vec<_> = outline.iter().map(|(t, l, p)| (t.clone(), *l, p + offset)).collect();
+            let mut contents = Layout::new(fonts, geometry, typography);
+            contents.add_contents(&shifted);
+            contents.pages
+        };
+        let offset = count_pages(layout.fonts, 0).len();
+        let contents_pages = count_pages(layout.fonts, offset);
+
+        for entry in &mut outline {
+            entry.2 += offset;
+        }
+        outline.insert(0, ("Contents".to_string(), 0, 0));
+        layout.pages.splice(0..0, contents_pages);
+    }
+
+    let title = package.metadata.title.clone().unwrap_or_else(|| {
+        epub_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    write_pdf(pdf_path, &title, layout, &outline, settings.compression_level != "none")?;
+    Ok(warnings)
+}
+
+/// Render plain paragraphs, given as (heading level, text), with the page layout and
+/// fonts of the settings; used for the notes appendix of annotated exports
+pub fn render_paragraphs_pdf(
+    pdf_path: &Path,
+    title: &str,
+    paragraphs: &[(Option<u8>, String)],
+    settings: &EpubToPdfSettings,
+) -> Result<(), String> {
+    let style = CustomStyle::from_settings(settings)?;
+    let mut fonts = style
+        .as_ref()
+        .and_then(custom_fonts)
+        .or_else(load_system_fonts)
+        .unwrap_or_else(FontSet::builtin);
+    let typography = Typography::from_settings(settings, None);
+    let mut layout = Layout::new(&mut fonts, PageGeometry::from_settings(settings), typography);
+    for (heading, text) in paragraphs {
+        let runs = [Run { text: text.clone(), style: FontStyle::Regular }];
+        layout.add_text(&runs, *heading, 0.0, &[]);
+    }
+    write_pdf(pdf_path, title, layout, &[], settings.compression_level != "none")
+}
+
+/// Write the text of an EPUB, one paragraph per line with blank lines between them
+pub fn write_epub_text(epub_path: &Path, txt_path: &Path, is_cancelled: &dyn Fn() -> bool) -> Result<(), String> {
+    let mut package = EpubPackage::open(epub_path)?;
+    let spine: Vec<String> = package
+        .spine
+        .iter()
+        .filter(|s| s.linear)
+        .filter_map(|s| s.href.clone())
+        .collect();
+
+    let mut paragraphs = Vec::new();
+    for href in spine {
+        if is_cancelled() {
+            return Err("Conversion cancelled".to_string());
+        }
+        let xhtml = prepare_xml(&package.read_resource(&href)?);
+        for block in parse_document(&href, &xhtml, false)? {
+            if let BlockContent::Text { runs, .. } = block.content {
+                let text: String = runs.iter().map(|r| r.text.as_str()).collect();
+                let lines: Vec<String> = text
+                    .lines()
+                    .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .collect();
+                paragraphs.push(lines.join("\n").trim().to_string());
+            }
+        }
+    }
+
+    let mut text = paragraphs.join("\n\n");
+    text.push('\n');
+    std::fs::write(txt_path, text).map_err(|e| format!("Failed to write text file: {}", e))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::epub_package::write_test_epub;
+
+    fn text_of(block: &Block) -> String {
+        match &block.content {
+            BlockContent::Text { runs, .. } => runs.iter().map(|r| r.text.as_str()).collect(),
+            BlockContent::Image { href } => format!("[{}]", href),
+        }
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let xhtml = prepare_xml(
+            br#"<?xml version="1.0"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>x</title><style>p{}</style></head>
+<body><h1 id="c1">Chapter&nbsp;1</h1>
+<p>Arrakis is a <b>desert</b> planet.<br/>Next line</p>
+<ol><li>First</li><li>Second</li></ol>
+<p id="fig"><img src="../Images/map.png"/></p></body></html>"#,
+        );
+        let blocks = parse_document("OEBPS/Text/ch1.xhtml", &xhtml, true).unwrap();
+
+        assert_eq!(blocks.len(), 5);
+        assert_eq!(text_of(&blocks[0]), "Chapter\u{a0}1");
+        assert_eq!(blocks[0].anchors, vec!["OEBPS/Text/ch1.xhtml#c1"]);
+        assert!(matches!(blocks[0].content, BlockContent::Text { heading: Some(1), .. }));
+        assert_eq!(text_of(&blocks[1]), "Arrakis is a desert planet.\nNext line");
+        assert_eq!(text_of(&blocks[3]), "2. Second");
+        assert_eq!(text_of(&blocks[4]), "[OEBPS/Images/map.png]");
+        assert_eq!(blocks[4].anchors, vec!["OEBPS/Text/ch1.xhtml#fig"]);
+
+        let without_images = parse_document("OEBPS/Text/ch1.xhtml", &xhtml, false).unwrap();
+        assert_eq!(without_images.len(), 4);
+    }
+
+    #[test]
+    fn test_line_breaking() {
+        let mut fonts = FontSet::builtin();
+        let geometry = PageGeometry::from_settings(&EpubToPdfSettings::default());
+        let typography = Typography { font_size: BASE_FONT_SIZE, hyphenation: None };
+        let mut layout = Layout::new(&mut fonts, geometry, typography);
+        let runs = vec![Run { text: "word ".repeat(2000), style: FontStyle::Regular }];
+        layout.add_text(&runs, None, 0.0, &["a#b".to_string()]);
+
+        let lines = layout.pages.iter().flatten().count();
+        assert!(lines > 10);
+        assert!(layout.pages.len() > 1);
+        assert_eq!(layout.anchors.get("a#b"), Some(&0));
+        for op in layout.pages.iter().flatten() {
+            if let DrawOp::Text { y, .. } = op {
+                assert!(*y >= geometry.bottom);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hyphenation() {
+        let mut fonts = FontSet::builtin();
+        let geometry = PageGeometry {
+            width: 160.0,
+            left: 10.0,
+            right: 10.0,
+            gutter: 12.0,
+            ..PageGeometry::from_settings(&EpubToPdfSettings::default())
+        };
+        let typography = Typography { font_size: BASE_FONT_SIZE, hyphenation: Some(hypher::Lang::English) };
+        let mut layout = Layout::new(&mut fonts, geometry, typography);
+        let runs = vec![Run { text: "extraordinarily comprehensive ".repeat(8), style: FontStyle::Regular }];
+        layout.add_text(&runs, None, 0.0, &[]);
+
+        let lines: Vec<&str> = layout
+            .pages
+            .iter()
+            .flatten()
+            .filter_map(|op| match op {
+                DrawOp::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(lines.iter().any(|l| l.ends_with('-')));
+        let text: String = lines.iter().map(|l| l.strip_suffix('-').map_or(format!("{} ", l), str::to_string)).collect();
+        assert_eq!(text.trim_end(), "extraordinarily comprehensive ".repeat(8).trim_end());
+
+        // The gutter is on the inner side: left of odd pages, right of even ones
+        assert_eq!(geometry.gutter_offset(0), 12.0);
+        assert_eq!(geometry.gutter_offset(1), 0.0);
+    }
+
+    #[test]
+    fn test_parse_css_rules() {
+        let css = r#"/* body */ @font-face { font-family: "Body Serif"; src: url(../Fonts/body.ttf); }
+@media print { p { color: black } }
+body, .text { font-family: 'Body Serif', serif; margin: 0 }"#;
+        let rules = parse_css_rules(css);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].0, "@font-face");
+        assert_eq!(rules[1].1[0], ("font-family".to_string(), "'Body Serif', serif".to_string()));
+        assert_eq!(first_font_family(&rules[1].1[0].1), "body serif");
+    }
+
+    /// Minimal EPUB 3 with two chapters and a nav document
+    fn write_sample_epub(path: &Path) {
+        let package = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Dune</dc:title></metadata>
+<manifest>
+  <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+  <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+  <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>
+</manifest>
+<spine><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#;
+        write_test_epub(
+            path,
+            package,
+            &[
+                (
+                    "OEBPS/nav.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+<nav epub:type="toc"><ol><li><a href="c1.xhtml">Arrakis</a></li><li><a href="c2.xhtml#s">Caladan</a></li></ol></nav></body></html>"#,
+                ),
+                (
+                    "OEBPS/c1.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><h1>Arrakis</h1><p>A beginning is the time&hellip;</p></body></html>"#,
+                ),
+                (
+                    "OEBPS/c2.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><h1 id="s">Caladan</h1><p><i>Fear</i> is the mind-killer.</p></body></html>"#,
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_render_epub_to_pdf() {
+        let dir = std::env::temp_dir().join(format!("stomy-native-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let epub = dir.join("dune.epub");
+        let pdf = dir.join("dune.pdf");
+        write_sample_epub(&epub);
+
+        let settings = EpubToPdfSettings {
+            include_table_of_contents: true,
+            ..EpubToPdfSettings::default()
+        };
+        render_epub_to_pdf(&epub, &pdf, &settings, &|| false).unwrap();
+        assert!(render_epub_to_pdf(&epub, &dir.join("cancelled.pdf"), &settings, &|| true).is_err());
+
+        let doc = lopdf::Document::load(&pdf).unwrap();
+        // Contents page + one page per chapter
+        assert_eq!(doc.get_pages().len(), 3);
+        let outlines = doc.catalog().unwrap().get(b"Outlines").unwrap().as_reference().unwrap();
+        let count = doc.get_dictionary(outlines).unwrap().get(b"Count").unwrap().as_i64().unwrap();
+        assert_eq!(count, 3);
+
+        let txt = dir.join("dune.txt");
+        write_epub_text(&epub, &txt, &|| false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&txt).unwrap(),
+            "Arrakis\n\nA beginning is the time\u{2026}\n\nCaladan\n\nFear is the mind-killer.\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_jpeg_components() {
+        let header = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x10,
+            0x00, 0x10, 0x03, 0x01,
+        ];
+        assert_eq!(jpeg_components(&header), Some(3));
+        assert_eq!(jpeg_components(b"\x89PNG"), None);
+    }
+}
@@ -0,0 +1,647 @@
+/// EPUB Package Reader
+/// Reads the container, OPF package (metadata, manifest, spine) and the
+/// table of contents (EPUB 3 nav or EPUB 2 NCX) of an EPUB file.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/epub_package.rs
+/// 2. Add `mod epub_package;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+const NS_OPS: &str = "http://www.idpf.org/2007/ops";
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub identifier: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub date: Option<String>,
+    pub subjects: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<String>,
+    pub cover_id: Option<String>, // Manifest id from <meta name="cover">
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestItem {
+    pub id: String,
+    pub href: String, // Full path inside the archive
+    pub media_type: String,
+    pub properties: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SpineItem {
+    pub idref: String,
+    pub href: Option<String>, // None when the idref is not in the manifest
+    pub linear: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TocEntry {
+    pub title: String,
+    pub href: String, // Full path inside the archive, fragment included
+    pub level: usize, // 0 for top-level entries
+}
+
+/// An opened EPUB with its package document parsed
+pub struct EpubPackage {
+    archive: ZipArchive<File>,
+    pub opf_path: String,
+    pub version: String,
+    pub metadata: EpubMetadata,
+    pub manifest: Vec<ManifestItem>,
+    pub spine: Vec<SpineItem>,
+    pub toc: Vec<TocEntry>,
+}
+
+// ============================================================================
+// Paths & XML Helpers
+// ============================================================================
+
+/// Folder part of an archive path ("OEBPS/Text/ch1.xhtml" -> "OEBPS/Text/")
+fn base_dir(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(i) => &path[..=i],
+        None => "",
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Resolve an href found in `from` to a normalised archive path (fragment kept)
+pub fn resolve_href(from: &str, href: &str) -> String {
+    let (path, fragment) = match href.split_once('#') {
+        Some((p, f)) => (p, Some(f)),
+        None => (href, None),
+    };
+    if path.is_empty() {
+        let from = from.split('#').next().unwrap_or(from);
+        return match fragment {
+            Some(f) => format!("{}#{}", from, f),
+            None => from.to_string(),
+        };
+    }
+
+    let joined = format!("{}{}", base_dir(from), percent_decode(path));
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+
+    let resolved = parts.join("/");
+    match fragment {
+        Some(f) => format!("{}#{}", resolved, f),
+        None => resolved,
+    }
+}
+
+/// Archive path without its fragment
+pub fn strip_fragment(href: &str) -> &str {
+    href.split('#').next().unwrap_or(href)
+}
+
+fn html_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "ensp" => '\u{2002}',
+        "emsp" => '\u{2003}',
+        "thinsp" => '\u{2009}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "sbquo" => '‚',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bdquo" => '„',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "sect" => '§',
+        "para" => '¶',
+        "times" => '×',
+        "euro" => '€',
+        "pound" => '£',
+        "dagger" => '†',
+        "Dagger" => '‡',
+        "iexcl" => '¡',
+        "iquest" => '¿',
+        "agrave" => 'à',
+        "aacute" => 'á',
+        "acirc" => 'â',
+        "auml" => 'ä',
+        "ccedil" => 'ç',
+        "egrave" => 'è',
+        "eacute" => 'é',
+        "ecirc" => 'ê',
+        "euml" => 'ë',
+        "icirc" => 'î',
+        "iuml" => 'ï',
+        "ocirc" => 'ô',
+        "ouml" => 'ö',
+        "ugrave" => 'ù',
+        "ucirc" => 'û',
+        "uuml" => 'ü',
+        "szlig" => 'ß',
+        "oelig" => 'œ',
+        "Eacute" => 'É',
+        "Agrave" => 'À',
+        "Ccedil" => 'Ç',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Replace HTML named entities, which XML parsers reject, by numeric references
+fn replace_html_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let name_end = rest[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map(|i| i + 1);
+        match name_end {
+            Some(end) if end > 1 && rest[end..].starts_with(';') => {
+                let name = &rest[1..end];
+                if matches!(name, "amp" | "lt" | "gt" | "quot" | "apos") {
+                    out.push_str(&rest[..=end]);
+                } else if let Some(c) = html_entity(name) {
+                    out.push_str(&format!("&#{};", c as u32));
+                }
+                // Unknown entities are dropped rather than failing the whole document
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parse an XML or XHTML document as found in EPUBs (DOCTYPE and HTML entities allowed)
+pub fn parse_xml(text: &str) -> Result<roxmltree::Document<'_>, String> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    roxmltree::Document::parse_with_options(text, options).map_err(|e| e.to_string())
+}
+
+/// Decode an XHTML resource and make it parseable by `parse_xml`
+pub fn prepare_xml(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_start_matches('\u{feff}');
+    replace_html_entities(text)
+}
+
+/// Concatenated text content of an element, whitespace collapsed
+pub fn node_text(node: roxmltree::Node) -> String {
+    let text: String = node
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+// ============================================================================
+// Package Parsing
+// ============================================================================
+
+fn parse_container(xml: &str) -> Result<String, String> {
+    let doc = parse_xml(xml).map_err(|e| format!("Failed to parse container.xml: {}", e))?;
+    doc.descendants()
+        .find(|n| {
+            n.tag_name().name() == "rootfile"
+                && n.attribute("media-type").unwrap_or("application/oebps-package+xml")
+                    == "application/oebps-package+xml"
+        })
+        .and_then(|n| n.attribute("full-path"))
+        .map(percent_decode)
+        .ok_or_else(|| "container.xml does not reference a package document".to_string())
+}
+
+fn parse_metadata(metadata: roxmltree::Node) -> EpubMetadata {
+    let mut result = EpubMetadata::default();
+
+    for node in metadata.children().filter(|n| n.is_element()) {
+        let text = node_text(node);
+        match node.tag_name().name() {
+            "title" if result.title.is_none() => result.title = non_empty(text),
+            "creator" if !text.is_empty() => result.creators.push(text),
+            "language" if result.language.is_none() => result.language = non_empty(text),
+            "identifier" if result.identifier.is_none() => result.identifier = non_empty(text),
+            "publisher" if result.publisher.is_none() => result.publisher = non_empty(text),
+            "description" if result.description.is_none() => result.description = non_empty(text),
+            "date" if result.date.is_none() => result.date = non_empty(text),
+            "subject" if !text.is_empty() => result.subjects.push(text),
+            "meta" => {
+                // EPUB 2 style <meta name content> and EPUB 3 style <meta property>text</meta>
+                let name = node.attribute("name").or_else(|| node.attribute("property"));
+                let value = node
+                    .attribute("content")
+                    .map(|c| c.trim().to_string())
+                    .unwrap_or(text);
+                match name {
+                    Some("cover") => result.cover_id = non_empty(value),
+                    Some("calibre:series") | Some("belongs-to-collection") if result.series.is_none() => {
+                        result.series = non_empty(value)
+                    }
+                    Some("calibre:series_index") | Some("group-position") if result.series_index.is_none() => {
+                        result.series_index = non_empty(value)
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Parsed OPF: version, metadata, manifest, spine and the NCX id referenced by the spine
+type PackageDocument = (String, EpubMetadata, Vec<ManifestItem>, Vec<SpineItem>, Option<String>);
+
+fn parse_package(opf_path: &str, xml: &str) -> Result<PackageDocument, String> {
+    let doc = parse_xml(xml).map_err(|e| format!("Failed to parse package document: {}", e))?;
+    let package = doc.root_element();
+    if package.tag_name().name() != "package" {
+        return Err("Package document has no <package> root".to_string());
+    }
+
+    let version = package.attribute("version").unwrap_or("2.0").to_string();
+    let metadata = child(package, "metadata").map(parse_metadata).unwrap_or_default();
+
+    let manifest: Vec<ManifestItem> = child(package, "manifest")
+        .map(|m| {
+            m.children()
+                .filter(|n| n.tag_name().name() == "item")
+                .filter_map(|n| {
+                    Some(ManifestItem {
+                        id: n.attribute("id")?.to_string(),
+                        href: resolve_href(opf_path, n.attribute("href")?),
+                        media_type: n.attribute("media-type").unwrap_or_default().to_string(),
+                        properties: n
+                            .attribute("properties")
+                            .unwrap_or_default()
+                            .split_whitespace()
+                            .map(|p| p.to_string())
+                            .collect(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let spine_node = child(package, "spine");
+    let ncx_id = spine_node.and_then(|s| s.attribute("toc")).map(|s| s.to_string());
+    let spine: Vec<SpineItem> = spine_node
+        .map(|s| {
+            s.children()
+                .filter(|n| n.tag_name().name() == "itemref")
+                .filter_map(|n| {
+                    let idref = n.attribute("idref")?.to_string();
+                    Some(SpineItem {
+                        href: manifest.iter().find(|i| i.id == idref).map(|i| i.href.clone()),
+                        linear: n.attribute("linear") != Some("no"),
+                        idref,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((version, metadata, manifest, spine, ncx_id))
+}
+
+/// Entries of an EPUB 3 navigation document (`<nav epub:type="toc">`)
+fn parse_nav(nav_path: &str, xml: &str) -> Result<Vec<TocEntry>, String> {
+    let doc = parse_xml(xml).map_err(|e| format!("Failed to parse navigation document: {}", e))?;
+    let Some(nav) = doc.descendants().find(|n| {
+        n.tag_name().name() == "nav"
+            && n.attribute((NS_OPS, "type"))
+                .or_else(|| n.attribute("type"))
+                .is_some_and(|t| t.split_whitespace().any(|t| t == "toc"))
+    }) else {
+        return Ok(Vec::new());
+    };
+
+    fn walk_list(list: roxmltree::Node, nav_path: &str, level: usize, entries: &mut Vec<TocEntry>) {
+        for li in list.children().filter(|n| n.tag_name().name() == "li") {
+            let label = li
+                .children()
+                .find(|n| matches!(n.tag_name().name(), "a" | "span"));
+            if let Some(label) = label {
+                if let Some(href) = label.attribute("href") {
+                    entries.push(TocEntry {
+                        title: node_text(label),
+                        href: resolve_href(nav_path, href),
+                        level,
+                    });
+                }
+            }
+            if let Some(sub) = child(li, "ol") {
+                // Entries without a link only group their children
+                let next = if label.and_then(|l| l.attribute("href")).is_some() { level + 1 } else { level };
+                walk_list(sub, nav_path, next, entries);
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    if let Some(list) = child(nav, "ol") {
+        walk_list(list, nav_path, 0, &mut entries);
+    }
+    Ok(entries)
+}
+
+/// Entries of an EPUB 2 NCX navigation map
+fn parse_ncx(ncx_path: &str, xml: &str) -> Result<Vec<TocEntry>, String> {
+    let doc = parse_xml(xml).map_err(|e| format!("Failed to parse NCX: {}", e))?;
+    let Some(nav_map) = doc.descendants().find(|n| n.tag_name().name() == "navMap") else {
+        return Ok(Vec::new());
+    };
+
+    fn walk_points(parent: roxmltree::Node, ncx_path: &str, level: usize, entries: &mut Vec<TocEntry>) {
+        for point in parent.children().filter(|n| n.tag_name().name() == "navPoint") {
+            let title = child(point, "navLabel").map(node_text).unwrap_or_default();
+            if let Some(src) = child(point, "content").and_then(|c| c.attribute("src")) {
+                entries.push(TocEntry {
+                    title,
+                    href: resolve_href(ncx_path, src),
+                    level,
+                });
+            }
+            walk_points(point, ncx_path, level + 1, entries);
+        }
+    }
+
+    let mut entries = Vec::new();
+    walk_points(nav_map, ncx_path, 0, &mut entries);
+    Ok(entries)
+}
+
+// ============================================================================
+// Package Access
+// ============================================================================
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    let mut buffer = Vec::with_capacity(entry.size() as usize);
+    entry
+        .read_to_end(&mut buffer)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    Ok(buffer)
+}
+
+impl EpubPackage {
+    /// Open an EPUB and parse its package document and table of contents
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| format!("Failed to read EPUB archive: {}", e))?;
+
+        let container = read_entry(&mut archive, CONTAINER_PATH)?;
+        let opf_path = parse_container(&String::from_utf8_lossy(&container))?;
+        let opf = read_entry(&mut archive, &opf_path)?;
+        let (version, metadata, manifest, spine, ncx_id) =
+            parse_package(&opf_path, &prepare_xml(&opf))?;
+
+        let mut package = EpubPackage {
+            archive,
+            opf_path,
+            version,
+            metadata,
+            manifest,
+            spine,
+            toc: Vec::new(),
+        };
+
+        // Prefer the EPUB 3 nav document, fall back to the NCX
+        let nav_href = package
+            .manifest
+            .iter()
+            .find(|i| i.properties.iter().any(|p| p == "nav"))
+            .map(|i| i.href.clone());
+        if let Some(href) = nav_href {
+            let xml = prepare_xml(&package.read_resource(&href)?);
+            package.toc = parse_nav(&href, &xml)?;
+        }
+        if package.toc.is_empty() {
+            let ncx_href = package
+                .manifest
+                .iter()
+                .find(|i| Some(&i.id) == ncx_id.as_ref() || i.media_type == "application/x-dtbncx+xml")
+                .map(|i| i.href.clone());
+            if let Some(href) = ncx_href {
+                let xml = prepare_xml(&package.read_resource(&href)?);
+                package.toc = parse_ncx(&href, &xml)?;
+            }
+        }
+
+        Ok(package)
+    }
+
+    /// Read a file from the archive by its full path (a fragment is ignored)
+    pub fn read_resource(&mut self, href: &str) -> Result<Vec<u8>, String> {
+        read_entry(&mut self.archive, strip_fragment(href))
+    }
+
+    pub fn has_resource(&self, href: &str) -> bool {
+        self.archive.index_for_name(strip_fragment(href)).is_some()
+    }
+
+    pub fn manifest_item(&self, href: &str) -> Option<&ManifestItem> {
+        let href = strip_fragment(href);
+        self.manifest.iter().find(|i| i.href == href)
+    }
+
+    /// Archive path of the cover image, if the package declares one
+    pub fn cover_href(&self) -> Option<String> {
+        self.manifest
+            .iter()
+            .find(|i| i.properties.iter().any(|p| p == "cover-image"))
+            .or_else(|| {
+                let id = self.metadata.cover_id.as_ref()?;
+                self.manifest.iter().find(|i| &i.id == id)
+            })
+            .filter(|i| i.media_type.starts_with("image/"))
+            .map(|i| i.href.clone())
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+/// Write an EPUB for the tests of the modules reading books: the package document
+/// goes to OEBPS/content.opf and `files` to their archive paths
+#[cfg(test)]
+pub(crate) fn write_test_epub<C: AsRef<[u8]>>(path: &Path, opf: &str, files: &[(&str, C)]) {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let container = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+<rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#;
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("mimetype", stored).unwrap();
+    zip.write_all(b"application/epub+zip").unwrap();
+    let package = [(CONTAINER_PATH, container.as_bytes()), ("OEBPS/content.opf", opf.as_bytes())];
+    for (name, content) in package.into_iter().chain(files.iter().map(|(name, content)| (*name, content.as_ref()))) {
+        zip.start_file(name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPF: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>Dune</dc:title>
+    <dc:creator opf:role="aut">Frank Herbert</dc:creator>
+    <dc:language>en</dc:language>
+    <dc:identifier id="id">urn:isbn:9780441013593</dc:identifier>
+    <dc:subject>Science Fiction</dc:subject>
+    <meta name="calibre:series" content="Dune"/>
+    <meta name="calibre:series_index" content="1"/>
+    <meta name="cover" content="cover-img"/>
+  </metadata>
+  <manifest>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="cover-img" href="Images/cover.jpg" media-type="image/jpeg"/>
+    <item id="ch1" href="Text/Chapter%201.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine toc="ncx">
+    <itemref idref="ch1"/>
+    <itemref idref="missing" linear="no"/>
+  </spine>
+</package>"#;
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(resolve_href("OEBPS/content.opf", "Text/ch1.xhtml"), "OEBPS/Text/ch1.xhtml");
+        assert_eq!(resolve_href("OEBPS/Text/ch1.xhtml", "../Images/a%20b.png"), "OEBPS/Images/a b.png");
+        assert_eq!(resolve_href("OEBPS/Text/ch1.xhtml", "#note"), "OEBPS/Text/ch1.xhtml#note");
+        assert_eq!(resolve_href("content.opf", "./ch2.xhtml#s1"), "ch2.xhtml#s1");
+    }
+
+    #[test]
+    fn test_parse_package() {
+        let (version, metadata, manifest, spine, ncx_id) = parse_package("OEBPS/content.opf", OPF).unwrap();
+        assert_eq!(version, "2.0");
+        assert_eq!(metadata.title.as_deref(), Some("Dune"));
+        assert_eq!(metadata.creators, vec!["Frank Herbert"]);
+        assert_eq!(metadata.series_index.as_deref(), Some("1"));
+        assert_eq!(metadata.cover_id.as_deref(), Some("cover-img"));
+        assert_eq!(manifest[2].href, "OEBPS/Text/Chapter 1.xhtml");
+        assert_eq!(spine[0].href.as_deref(), Some("OEBPS/Text/Chapter 1.xhtml"));
+        assert_eq!(spine[1].href, None);
+        assert!(!spine[1].linear);
+        assert_eq!(ncx_id.as_deref(), Some("ncx"));
+    }
+
+    #[test]
+    fn test_parse_toc() {
+        let nav = r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<body><nav epub:type="toc"><ol>
+  <li><a href="ch1.xhtml">Book One&nbsp;— Dune</a>
+    <ol><li><a href="ch1.xhtml#s2">Part Two</a></li></ol></li>
+  <li><a href="../ch2.xhtml">Appendix</a></li>
+</ol></nav></body></html>"#;
+        let entries = parse_nav("OEBPS/Text/nav.xhtml", &replace_html_entities(nav)).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].title, "Book One — Dune");
+        assert_eq!(entries[1].href, "OEBPS/Text/ch1.xhtml#s2");
+        assert_eq!(entries[1].level, 1);
+        assert_eq!(entries[2].href, "OEBPS/ch2.xhtml");
+
+        let ncx = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+  <navPoint id="p1"><navLabel><text>Chapter 1</text></navLabel><content src="Text/ch1.xhtml"/>
+    <navPoint id="p2"><navLabel><text>Section</text></navLabel><content src="Text/ch1.xhtml#a"/></navPoint>
+  </navPoint>
+</navMap></ncx>"#;
+        let entries = parse_ncx("OEBPS/toc.ncx", ncx).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Chapter 1");
+        assert_eq!(entries[1].level, 1);
+    }
+
+    #[test]
+    fn test_replace_html_entities() {
+        assert_eq!(replace_html_entities("a&nbsp;b &amp; c"), "a&#160;b &amp; c");
+        assert_eq!(replace_html_entities("R&D &unknown; x"), "R&D  x");
+    }
+}
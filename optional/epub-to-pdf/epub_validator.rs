@@ -0,0 +1,289 @@
+/// EPUB Pre-flight Validation
+/// Checks an EPUB before any converter runs: ZIP container, mimetype,
+/// container.xml, package document, spine, missing resources and DRM.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/epub_validator.rs (needs epub_package.rs)
+/// 2. Add `mod epub_validator;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tauri::command;
+use zip::{CompressionMethod, ZipArchive};
+
+use super::epub_package::{parse_xml, prepare_xml, EpubPackage};
+
+const MIMETYPE: &str = "application/epub+zip";
+
+/// Encryption algorithms used for font obfuscation, which is not DRM
+const FONT_OBFUSCATION: [&str; 2] = [
+    "http://www.idpf.org/2008/embedding",
+    "http://ns.adobe.com/pdf/enc#RC",
+];
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    pub code: String, // "drm-protected", "missing-resource", ...
+    pub message: String,
+    pub path: Option<String>, // Archive path the issue is about
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EpubValidation {
+    pub valid: bool, // No errors; warnings do not prevent conversion
+    pub drm_protected: bool,
+    pub errors: Vec<ValidationIssue>,
+    pub warnings: Vec<ValidationIssue>,
+}
+
+impl EpubValidation {
+    fn error(&mut self, code: &str, message: String, path: Option<&str>) {
+        self.errors.push(ValidationIssue {
+            code: code.to_string(),
+            message,
+            path: path.map(str::to_string),
+        });
+    }
+
+    fn warning(&mut self, code: &str, message: String, path: Option<&str>) {
+        self.warnings.push(ValidationIssue {
+            code: code.to_string(),
+            message,
+            path: path.map(str::to_string),
+        });
+    }
+}
+
+// ============================================================================
+// Checks
+// ============================================================================
+
+fn read_text(archive: &mut ZipArchive<File>, name: &str) -> Option<String> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes).ok()?;
+    Some(prepare_xml(&bytes))
+}
+
+fn check_mimetype(archive: &mut ZipArchive<File>, report: &mut EpubValidation) {
+    let Some(content) = read_text(archive, "mimetype") else {
+        report.warning("missing-mimetype", "The mimetype file is missing".to_string(), Some("mimetype"));
+        return;
+    };
+    if content.trim() != MIMETYPE {
+        report.error(
+            "invalid-mimetype",
+            format!("Not an EPUB: the mimetype is \"{}\"", content.trim()),
+            Some("mimetype"),
+        );
+        return;
+    }
+
+    // Readers accept it anyway, but the spec wants it first and uncompressed
+    let stored_first = archive
+        .by_index_raw(0)
+        .is_ok_and(|e| e.name() == "mimetype" && e.compression() == CompressionMethod::Stored);
+    if !stored_first {
+        report.warning(
+            "mimetype-not-first",
+            "The mimetype file should be the first, uncompressed entry".to_string(),
+            Some("mimetype"),
+        );
+    }
+}
+
+/// DRM scheme protecting the book, if any
+fn detect_drm(archive: &mut ZipArchive<File>) -> Option<&'static str> {
+    if archive.index_for_name("META-INF/license.lcpl").is_some() {
+        return Some("Readium LCP");
+    }
+    if archive.index_for_name("META-INF/sinf.xml").is_some() {
+        return Some("Apple FairPlay");
+    }
+    if let Some(rights) = read_text(archive, "META-INF/rights.xml") {
+        if rights.contains("adobe") {
+            return Some("Adobe ADEPT");
+        }
+    }
+
+    let encryption = read_text(archive, "META-INF/encryption.xml")?;
+    let doc = parse_xml(&encryption).ok()?;
+    let encrypted = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "EncryptionMethod")
+        .filter_map(|n| n.attribute("Algorithm"))
+        .any(|algorithm| !FONT_OBFUSCATION.contains(&algorithm));
+    encrypted.then_some("encrypted content")
+}
+
+fn check_package(package: &mut EpubPackage, report: &mut EpubValidation) {
+    let linear = package.spine.iter().filter(|s| s.linear).count();
+    if package.spine.is_empty() || linear == 0 {
+        report.error("empty-spine", "The book has no readable content (empty spine)".to_string(), None);
+    }
+
+    for item in &package.spine {
+        if item.href.is_none() {
+            report.error(
+                "broken-spine-reference",
+                format!("The spine references an unknown manifest item: {}", item.idref),
+                Some(&package.opf_path),
+            );
+        }
+    }
+
+    let spine_hrefs: Vec<String> = package.spine.iter().filter_map(|s| s.href.clone()).collect();
+    for item in &package.manifest {
+        if package.has_resource(&item.href) {
+            continue;
+        }
+        if spine_hrefs.contains(&item.href) {
+            report.error(
+                "missing-document",
+                format!("A chapter is missing from the archive: {}", item.href),
+                Some(&item.href),
+            );
+        } else {
+            report.warning(
+                "missing-resource",
+                format!("A resource is missing from the archive: {}", item.href),
+                Some(&item.href),
+            );
+        }
+    }
+
+    // External tools tolerate tag soup, the native renderer does not
+    for href in spine_hrefs {
+        let Ok(bytes) = package.read_resource(&href) else {
+            continue;
+        };
+        if let Err(e) = parse_xml(&prepare_xml(&bytes)) {
+            report.warning("malformed-document", format!("{} is not well-formed XHTML: {}", href, e), Some(&href));
+        }
+    }
+
+    if package.toc.is_empty() {
+        report.warning("missing-toc", "The book has no table of contents".to_string(), None);
+    }
+}
+
+/// Validate an EPUB; stops at the first error that prevents further checks
+pub fn validate(epub_path: &Path) -> EpubValidation {
+    let mut report = EpubValidation::default();
+
+    let archive = File::open(epub_path)
+        .map_err(|e| format!("Failed to open EPUB: {}", e))
+        .and_then(|file| ZipArchive::new(file).map_err(|e| format!("Not an EPUB: the file is not a ZIP archive ({})", e)));
+    let mut archive = match archive {
+        Ok(archive) => archive,
+        Err(e) => {
+            report.error("invalid-archive", e, None);
+            return report;
+        }
+    };
+
+    check_mimetype(&mut archive, &mut report);
+    if let Some(scheme) = detect_drm(&mut archive) {
+        report.drm_protected = true;
+        report.error(
+            "drm-protected",
+            format!("This book is DRM-protected ({}) and cannot be converted", scheme),
+            None,
+        );
+    }
+    if archive.index_for_name("META-INF/container.xml").is_none() {
+        report.error(
+            "missing-container",
+            "Not a valid EPUB: META-INF/container.xml is missing".to_string(),
+            Some("META-INF/container.xml"),
+        );
+    }
+    if !report.errors.is_empty() {
+        return report;
+    }
+
+    match EpubPackage::open(epub_path) {
+        Ok(mut package) => check_package(&mut package, &mut report),
+        Err(e) => report.error("invalid-package", format!("The package document cannot be read: {}", e), None),
+    }
+
+    report.valid = report.errors.is_empty();
+    report
+}
+
+/// Check an EPUB before converting it
+#[command]
+pub fn validate_epub(epub_path: String) -> EpubValidation {
+    validate(Path::new(&epub_path))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::epub_package::write_test_epub;
+    use std::io::Write;
+
+    const PACKAGE: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata/>
+<manifest>
+  <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+  <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>
+  <item id="img" href="map.png" media-type="image/png"/>
+</manifest>
+<spine><itemref idref="c1"/><itemref idref="c2"/><itemref idref="c3"/></spine></package>"#;
+
+    fn codes(issues: &[ValidationIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.code.as_str()).collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = std::env::temp_dir().join(format!("stomy-validator-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let broken = dir.join("Broken.EPUB");
+        write_test_epub(&broken, PACKAGE, &[("OEBPS/c1.xhtml", "<html><body><p>Unclosed</body></html>")]);
+        let report = validate(&broken);
+        assert!(!report.valid);
+        assert_eq!(codes(&report.errors), vec!["broken-spine-reference", "missing-document"]);
+        assert_eq!(report.errors[1].path.as_deref(), Some("OEBPS/c2.xhtml"));
+        assert_eq!(codes(&report.warnings), vec!["missing-resource", "malformed-document", "missing-toc"]);
+
+        // A compressed mimetype, as written by zip tools that are not EPUB-aware
+        let zipped = dir.join("zipped.epub");
+        let mut zip = zip::ZipWriter::new(File::create(&zipped).unwrap());
+        zip.start_file("mimetype", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(MIMETYPE.as_bytes()).unwrap();
+        zip.finish().unwrap();
+        assert_eq!(codes(&validate(&zipped).warnings), vec!["mimetype-not-first"]);
+
+        let protected = dir.join("protected.epub");
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+<enc:EncryptedData><enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/></enc:EncryptedData></encryption>"#;
+        write_test_epub(&protected, PACKAGE, &[("META-INF/encryption.xml", encryption)]);
+        let report = validate(&protected);
+        assert!(report.drm_protected);
+        assert_eq!(report.errors[0].message, "This book is DRM-protected (encrypted content) and cannot be converted");
+
+        let fonts = encryption.replace("http://www.w3.org/2001/04/xmlenc#aes128-cbc", FONT_OBFUSCATION[0]);
+        write_test_epub(&protected, PACKAGE, &[("META-INF/encryption.xml", &fonts)]);
+        assert!(!validate(&protected).drm_protected);
+
+        let not_zip = dir.join("text.epub");
+        std::fs::write(&not_zip, "plain text").unwrap();
+        assert_eq!(codes(&validate(&not_zip).errors), vec!["invalid-archive"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+/// Print Layout Profiles
+/// Named page layouts for print and e-ink output: page size, margins, body font
+/// size, hyphenation, binding gutter and imposition. Builtin profiles cover the
+/// common book trims and e-reader screens; users add their own, stored as JSON.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/layout_profiles.rs
+/// 2. Add `mod layout_profiles;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::command;
+
+use super::epub_converter::{EpubToPdfSettings, PdfMargins};
+
+/// Smallest and largest page side accepted for a profile, in points
+const MIN_PAGE_SIDE_PT: f64 = 144.0;
+const MAX_PAGE_SIDE_PT: f64 = 2000.0;
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+/// How pages are placed on the printed sheets
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Imposition {
+    #[default]
+    None,
+    Booklet, // Two pages side by side per sheet side, in saddle-stitch order
+}
+
+/// All sizes in points
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutProfile {
+    pub id: String,
+    pub name: String,
+    pub width: f64,
+    pub height: f64,
+    pub margins: PdfMargins,
+    #[serde(default)]
+    pub gutter: f64, // Added to the inner margin for the binding
+    pub font_size: f64,
+    #[serde(default)]
+    pub hyphenation: bool,
+    #[serde(default)]
+    pub imposition: Imposition,
+    #[serde(default)]
+    pub builtin: bool, // Set on the profiles shipped with the plugin
+}
+
+// ============================================================================
+// Profiles
+// ============================================================================
+
+fn profile(
+    id: &str,
+    name: &str,
+    (width, height): (f64, f64),
+    (top, bottom, inner, outer): (f64, f64, f64, f64),
+    gutter: f64,
+    font_size: f64,
+    hyphenation: bool,
+) -> LayoutProfile {
+    LayoutProfile {
+        id: id.to_string(),
+        name: name.to_string(),
+        width,
+        height,
+        margins: PdfMargins { top, bottom, left: inner, right: outer },
+        gutter,
+        font_size,
+        hyphenation,
+        imposition: Imposition::None,
+        builtin: true,
+    }
+}
+
+/// Profiles shipped with the plugin; e-ink sizes are the 4:3 screen areas of each diagonal
+pub fn builtin_profiles() -> Vec<LayoutProfile> {
+    vec![
+        profile("a5", "A5 paperback", (419.53, 595.28), (50.0, 54.0, 45.0, 45.0), 14.0, 10.0, true),
+        profile("trade-6x9", "6×9 in trade paperback", (432.0, 648.0), (54.0, 63.0, 54.0, 54.0), 18.0, 11.0, true),
+        // Large print guidelines ask for unbroken words
+        profile("large-print", "Large print (Letter)", (612.0, 792.0), (72.0, 72.0, 72.0, 72.0), 18.0, 18.0, false),
+        profile("eink-6", "E-ink 6 in", (259.2, 345.6), (14.0, 14.0, 14.0, 14.0), 0.0, 9.0, true),
+        profile("eink-7.8", "E-ink 7.8 in", (336.96, 449.28), (18.0, 18.0, 18.0, 18.0), 0.0, 10.0, true),
+        profile("eink-10.3", "E-ink 10.3 in", (444.96, 593.28), (24.0, 24.0, 24.0, 24.0), 0.0, 11.0, true),
+        LayoutProfile {
+            imposition: Imposition::Booklet,
+            ..profile("booklet-a5", "A5 booklet on A4 sheets", (419.53, 595.28), (40.0, 40.0, 40.0, 40.0), 14.0, 10.0, true)
+        },
+    ]
+}
+
+/// Check a user profile before it is saved or used
+fn validate_profile(profile: &LayoutProfile) -> Result<(), String> {
+    let valid_id = !profile.id.is_empty()
+        && profile
+            .id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'));
+    if !valid_id {
+        return Err(format!(
+            "Invalid layout profile id '{}': use lowercase letters, digits, '-', '_' or '.'",
+            profile.id
+        ));
+    }
+
+    let sides = MIN_PAGE_SIDE_PT..=MAX_PAGE_SIDE_PT;
+    if !sides.contains(&profile.width) || !sides.contains(&profile.height) {
+        return Err(format!(
+            "Page size of '{}' must be between {} and {} points",
+            profile.id, MIN_PAGE_SIDE_PT, MAX_PAGE_SIDE_PT
+        ));
+    }
+    let m = &profile.margins;
+    if [m.top, m.bottom, m.left, m.right, profile.gutter]
+        .iter()
+        .any(|v| !v.is_finite() || *v < 0.0)
+    {
+        return Err(format!("Margins and gutter of '{}' must be positive numbers", profile.id));
+    }
+    if m.left + m.right + profile.gutter >= profile.width * 0.8 || m.top + m.bottom >= profile.height * 0.8 {
+        return Err(format!("Margins of '{}' leave no room for the page content", profile.id));
+    }
+    if !(4.0..=72.0).contains(&profile.font_size) {
+        return Err(format!("Font size of '{}' must be between 4 and 72 points", profile.id));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// User Profiles
+// ============================================================================
+
+/// JSON file holding the user profiles, in the platform config folder
+pub fn profiles_path() -> PathBuf {
+    let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        var("APPDATA").map(|p| p.join("Stomy"))
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|p| p.join("Library/Application Support/Stomy"))
+    } else {
+        var("XDG_CONFIG_HOME")
+            .or_else(|| var("HOME").map(|p| p.join(".config")))
+            .map(|p| p.join("stomy"))
+    };
+    base.unwrap_or_else(std::env::temp_dir).join("layout-profiles.json")
+}
+
+fn read_profiles(path: &Path) -> Result<Vec<LayoutProfile>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read layout profiles: {}", e))?;
+    let mut profiles: Vec<LayoutProfile> =
+        serde_json::from_str(&json).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    for profile in &mut profiles {
+        profile.builtin = false;
+    }
+    Ok(profiles)
+}
+
+fn write_profiles(path: &Path, profiles: &[LayoutProfile]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create config folder: {}", e))?;
+    }
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| format!("Failed to save layout profiles: {}", e))?;
+    std::fs::write(path, json).map_err(|e| format!("Failed to save layout profiles: {}", e))
+}
+
+/// Builtin profiles followed by the user profiles stored in `path`
+fn all_profiles(path: &Path) -> Result<Vec<LayoutProfile>, String> {
+    let mut profiles = builtin_profiles();
+    profiles.extend(read_profiles(path)?);
+    Ok(profiles)
+}
+
+fn find_profile(path: &Path, id: &str) -> Result<LayoutProfile, String> {
+    all_profiles(path)?
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| format!("Unknown layout profile: {}", id))
+}
+
+/// Profile chosen in the settings, None for the plain page size and margins
+pub fn selected_profile(settings: &EpubToPdfSettings) -> Result<Option<LayoutProfile>, String> {
+    if settings.layout_profile.is_empty() {
+        return Ok(None);
+    }
+    // Hand-edited profiles are checked like saved ones
+    let profile = find_profile(&profiles_path(), &settings.layout_profile)?;
+    validate_profile(&profile)?;
+    Ok(Some(profile))
+}
+
+fn save_profile(path: &Path, mut profile: LayoutProfile) -> Result<Vec<LayoutProfile>, String> {
+    validate_profile(&profile)?;
+    if builtin_profiles().iter().any(|p| p.id == profile.id) {
+        return Err(format!("'{}' is a builtin profile: choose another id", profile.id));
+    }
+    profile.builtin = false;
+
+    let mut profiles = read_profiles(path)?;
+    match profiles.iter_mut().find(|p| p.id == profile.id) {
+        Some(existing) => *existing = profile,
+        None => profiles.push(profile),
+    }
+    write_profiles(path, &profiles)?;
+    all_profiles(path)
+}
+
+fn delete_profile(path: &Path, id: &str) -> Result<Vec<LayoutProfile>, String> {
+    let mut profiles = read_profiles(path)?;
+    let count = profiles.len();
+    profiles.retain(|p| p.id != id);
+    if profiles.len() == count {
+        return Err(format!("Unknown user layout profile: {}", id));
+    }
+    write_profiles(path, &profiles)?;
+    all_profiles(path)
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// List the builtin and user layout profiles
+#[command]
+pub fn list_layout_profiles() -> Result<Vec<LayoutProfile>, String> {
+    all_profiles(&profiles_path())
+}
+
+/// Add or replace a user profile; returns every profile
+#[command]
+pub fn save_layout_profile(profile: LayoutProfile) -> Result<Vec<LayoutProfile>, String> {
+    save_profile(&profiles_path(), profile)
+}
+
+/// Remove a user profile; returns every profile
+#[command]
+pub fn delete_layout_profile(id: String) -> Result<Vec<LayoutProfile>, String> {
+    delete_profile(&profiles_path(), &id)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_profiles() {
+        for profile in builtin_profiles() {
+            assert_eq!(validate_profile(&profile), Ok(()), "{}", profile.id);
+        }
+        let booklet = builtin_profiles().into_iter().find(|p| p.id == "booklet-a5").unwrap();
+        assert_eq!(booklet.imposition, Imposition::Booklet);
+    }
+
+    #[test]
+    fn test_user_profiles() {
+        let dir = std::env::temp_dir().join(format!("stomy-profiles-{}", std::process::id()));
+        let path = dir.join("layout-profiles.json");
+        let pocket = LayoutProfile {
+            builtin: true,
+            ..profile("pocket", "Pocket", (312.0, 504.0), (36.0, 36.0, 30.0, 30.0), 10.0, 9.5, true)
+        };
+
+        let profiles = save_profile(&path, pocket.clone()).unwrap();
+        assert_eq!(profiles.len(), builtin_profiles().len() + 1);
+        let saved = find_profile(&path, "pocket").unwrap();
+        assert!(!saved.builtin);
+        assert_eq!(saved.font_size, 9.5);
+
+        // Builtin ids and impossible layouts are refused
+        assert!(save_profile(&path, LayoutProfile { id: "a5".to_string(), ..pocket.clone() }).is_err());
+        assert!(save_profile(&path, LayoutProfile { gutter: 300.0, ..pocket.clone() }).is_err());
+        assert!(save_profile(&path, LayoutProfile { id: "Pocket Book".to_string(), ..pocket.clone() }).is_err());
+
+        // Hand-written JSON only needs the sizes
+        std::fs::write(
+            &path,
+            r#"[{"id": "zine", "name": "Zine", "width": 396, "height": 612,
+                "margins": {"top": 36, "bottom": 36, "left": 36, "right": 36}, "fontSize": 10}]"#,
+        )
+        .unwrap();
+        let zine = find_profile(&path, "zine").unwrap();
+        assert_eq!(zine.imposition, Imposition::None);
+        assert!(!zine.hyphenation);
+        assert!(find_profile(&path, "pocket").is_err());
+
+        assert_eq!(delete_profile(&path, "zine").unwrap().len(), builtin_profiles().len());
+        assert!(delete_profile(&path, "a5").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,385 @@
+/// Output Naming
+/// Builds output paths from a naming template filled with the EPUB metadata
+/// (`{author}/{series}/{series_index} - {title}.pdf`) and applies the
+/// collision policy when the file already exists.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/output_naming.rs (needs epub_package.rs)
+/// 2. Add `mod output_naming;` to src-tauri/src/main.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::command;
+
+use super::ebook_formats::EbookFormat;
+use super::epub_package::{EpubMetadata, EpubPackage};
+
+const PLACEHOLDERS: [&str; 9] = [
+    "title",
+    "author",
+    "authors",
+    "series",
+    "series_index",
+    "publisher",
+    "language",
+    "year",
+    "filename",
+];
+
+/// Longest file name most file systems accept, in bytes
+const MAX_COMPONENT_LENGTH: usize = 255;
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+/// Where batch outputs go and what happens when a file already exists
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputNaming {
+    pub output_dir: String,
+    pub template: String,
+    pub collision: String, // "overwrite", "skip" or "suffix"
+}
+
+impl Default for OutputNaming {
+    fn default() -> Self {
+        OutputNaming {
+            output_dir: String::new(),
+            template: "{title}.pdf".to_string(),
+            collision: "suffix".to_string(),
+        }
+    }
+}
+
+/// The output chosen for one source file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedOutput {
+    pub source_path: String,
+    pub target_path: String,
+    pub action: String, // "create", "overwrite" or "skip"
+}
+
+// ============================================================================
+// Templates
+// ============================================================================
+
+/// Make a file or folder name valid. Characters invalid on Windows are always
+/// replaced, as books often end up on FAT-formatted readers
+pub fn sanitize_component(name: &str, windows: bool) -> String {
+    let mut clean: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Leading dots hide files on Unix, trailing dots and spaces are dropped by Windows
+    clean = clean.trim().trim_start_matches('.').trim_end_matches(['.', ' ']).to_string();
+
+    if windows {
+        let stem = clean.split('.').next().unwrap_or_default().to_uppercase();
+        let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+            || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+                && stem.len() == 4
+                && stem[3..].chars().all(|c| c.is_ascii_digit()));
+        if reserved {
+            clean.insert(0, '_');
+        }
+    }
+
+    if clean.len() > MAX_COMPONENT_LENGTH {
+        // Keep the extension, cut the name on a character boundary
+        let extension = clean.rfind('.').map(|i| clean[i..].to_string()).unwrap_or_default();
+        let mut cut = MAX_COMPONENT_LENGTH.saturating_sub(extension.len());
+        while !clean.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        clean = format!("{}{}", clean[..cut].trim_end(), extension);
+    }
+    clean
+}
+
+/// "1.0" → "1", "2.5" stays
+fn format_series_index(index: &str) -> String {
+    match index.trim().parse::<f64>() {
+        Ok(value) if value.fract() == 0.0 && value >= 0.0 => format!("{}", value as u64),
+        _ => index.trim().to_string(),
+    }
+}
+
+fn placeholder_value(name: &str, metadata: &EpubMetadata, source: &Path) -> String {
+    let file_name = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let value = match name {
+        "title" => metadata.title.clone().unwrap_or_else(|| file_name.clone()),
+        "author" => metadata.creators.first().cloned().unwrap_or_default(),
+        "authors" => metadata.creators.join(" & "),
+        "series" => metadata.series.clone().unwrap_or_default(),
+        "series_index" => metadata.series_index.as_deref().map(format_series_index).unwrap_or_default(),
+        "publisher" => metadata.publisher.clone().unwrap_or_default(),
+        "language" => metadata.language.clone().unwrap_or_default(),
+        "year" => metadata
+            .date
+            .as_deref()
+            .and_then(|d| d.get(..4))
+            .filter(|y| y.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or_default()
+            .to_string(),
+        _ => file_name,
+    };
+    // A value never creates folders
+    value.replace(['/', '\\'], "_")
+}
+
+/// Check the template syntax before any file is looked at
+pub fn check_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("The output template is empty".to_string());
+    }
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| "Unclosed placeholder in output template".to_string())?;
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "Unknown placeholder {{{}}} in output template (use {})",
+                name,
+                PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// Relative output path for one book. Empty values drop their folder level and
+/// the separators around them ("{series_index} - {title}" without a series is "{title}")
+pub fn render_template(
+    template: &str,
+    metadata: &EpubMetadata,
+    source: &Path,
+    default_extension: &str,
+    windows: bool,
+) -> Result<PathBuf, String> {
+    check_template(template)?;
+
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}').unwrap_or(0);
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&placeholder_value(&rest[start + 1..end], metadata, source));
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let separators: &[char] = &[' ', '-', '_', ',', '–', '—'];
+    let mut path = PathBuf::new();
+    for component in rendered.split(['/', '\\']) {
+        let trimmed = component.trim_matches(separators);
+        let clean = sanitize_component(trimmed, windows);
+        if !clean.is_empty() && clean != ".." {
+            path.push(clean);
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        return Err(format!("The output template gives an empty name for {}", source.display()));
+    }
+    if EbookFormat::from_path(&path).is_none() {
+        let name = format!("{}.{}", path.file_name().unwrap_or_default().to_string_lossy(), default_extension);
+        path.set_file_name(name);
+    }
+    Ok(path)
+}
+
+// ============================================================================
+// Collisions
+// ============================================================================
+
+/// "Dune.pdf" → "Dune (2).pdf", keeping double extensions such as ".kepub.epub"
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let extension_len = EbookFormat::from_path(path)
+        .map(|f| f.extension().len() + 1)
+        .filter(|len| *len < name.len())
+        .or_else(|| name.rfind('.').map(|i| name.len() - i))
+        .unwrap_or(0);
+    let (stem, extension) = name.split_at(name.len() - extension_len);
+    path.with_file_name(format!("{} ({}){}", stem, number, extension))
+}
+
+/// Pick the target for each (source, explicit target) pair. Without an explicit
+/// target the template is used. Paths already planned in the same batch count
+/// as existing, so two books never write the same file
+pub fn resolve_outputs(
+    requests: &[(String, Option<String>)],
+    naming: Option<&OutputNaming>,
+) -> Result<Vec<ResolvedOutput>, String> {
+    let windows = cfg!(target_os = "windows");
+    // Without naming options, explicit paths keep the historical overwrite behaviour
+    let collision = naming.map(|n| n.collision.as_str()).unwrap_or("overwrite");
+    if !matches!(collision, "overwrite" | "skip" | "suffix") {
+        return Err(format!("Unknown collision policy: {}", collision));
+    }
+    if let Some(naming) = naming {
+        check_template(&naming.template)?;
+    }
+
+    let mut planned: HashSet<PathBuf> = HashSet::new();
+    let mut resolved = Vec::new();
+    for (source, target) in requests {
+        let target = match (target, naming) {
+            (Some(target), _) => PathBuf::from(target),
+            (None, Some(naming)) => {
+                if naming.output_dir.trim().is_empty() {
+                    return Err("No output folder given".to_string());
+                }
+                let source_path = Path::new(source);
+                // Non-EPUB sources or unreadable packages fall back to the file name
+                let metadata = EpubPackage::open(source_path).map(|p| p.metadata).unwrap_or_default();
+                let relative = render_template(&naming.template, &metadata, source_path, "pdf", windows)?;
+                Path::new(&naming.output_dir).join(relative)
+            }
+            (None, None) => return Err(format!("No output path or naming template for {}", source)),
+        };
+
+        let exists = target.exists();
+        let taken = planned.contains(&target);
+        let (target, action) = match collision {
+            _ if !exists && !taken => (target, "create"),
+            "overwrite" if !taken => (target, "overwrite"),
+            "skip" => (target, "skip"),
+            _ => {
+                let mut number = 2;
+                while numbered_path(&target, number).exists() || planned.contains(&numbered_path(&target, number)) {
+                    number += 1;
+                }
+                (numbered_path(&target, number), "create")
+            }
+        };
+
+        if action != "skip" {
+            planned.insert(target.clone());
+        }
+        resolved.push(ResolvedOutput {
+            source_path: source.clone(),
+            target_path: target.to_string_lossy().to_string(),
+            action: action.to_string(),
+        });
+    }
+    Ok(resolved)
+}
+
+/// Preview the output paths a batch would use, without converting anything
+#[command]
+pub fn resolve_output_paths(epub_paths: Vec<String>, naming: OutputNaming) -> Result<Vec<ResolvedOutput>, String> {
+    let requests: Vec<(String, Option<String>)> = epub_paths.into_iter().map(|p| (p, None)).collect();
+    resolve_outputs(&requests, Some(&naming))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dune() -> EpubMetadata {
+        EpubMetadata {
+            title: Some("Dune: Messiah?".to_string()),
+            creators: vec!["Frank Herbert".to_string(), "Brian Herbert".to_string()],
+            series: Some("Dune".to_string()),
+            series_index: Some("2.0".to_string()),
+            date: Some("1969-10-15".to_string()),
+            ..EpubMetadata::default()
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let source = Path::new("/books/messiah.epub");
+        let template = "{author}/{series}/{series_index} - {title}.pdf";
+        assert_eq!(
+            render_template(template, &dune(), source, "pdf", false).unwrap(),
+            PathBuf::from("Frank Herbert/Dune/2 - Dune_ Messiah_.pdf")
+        );
+
+        let standalone = EpubMetadata { series: None, series_index: None, ..dune() };
+        assert_eq!(
+            render_template(template, &standalone, source, "pdf", false).unwrap(),
+            PathBuf::from("Frank Herbert/Dune_ Messiah_.pdf")
+        );
+        assert_eq!(
+            render_template("{year}/{filename}", &EpubMetadata::default(), source, "pdf", false).unwrap(),
+            PathBuf::from("messiah.pdf")
+        );
+        assert_eq!(
+            render_template("{authors} - {title}.kepub.epub", &dune(), source, "pdf", false).unwrap(),
+            PathBuf::from("Frank Herbert & Brian Herbert - Dune_ Messiah_.kepub.epub")
+        );
+
+        assert!(check_template("{isbn}.pdf").unwrap_err().starts_with("Unknown placeholder {isbn}"));
+        assert!(check_template("{title.pdf").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_component() {
+        assert_eq!(sanitize_component("..hidden. ", false), "hidden");
+        assert_eq!(sanitize_component("a/b\\c\u{7}", false), "a_b_c_");
+        assert_eq!(sanitize_component("CON.pdf", true), "_CON.pdf");
+        assert_eq!(sanitize_component("COM1", true), "_COM1");
+        assert_eq!(sanitize_component("Company.pdf", true), "Company.pdf");
+
+        let long = format!("{}.pdf", "é".repeat(200));
+        let clean = sanitize_component(&long, false);
+        assert!(clean.len() <= MAX_COMPONENT_LENGTH);
+        assert!(clean.ends_with("é.pdf"));
+    }
+
+    #[test]
+    fn test_collision_policies() {
+        let dir = std::env::temp_dir().join(format!("stomy-naming-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Dune.pdf"), b"existing").unwrap();
+
+        let naming = |collision: &str| OutputNaming {
+            output_dir: dir.to_string_lossy().to_string(),
+            template: "{filename}.pdf".to_string(),
+            collision: collision.to_string(),
+        };
+        let requests = vec![
+            ("/books/Dune.epub".to_string(), None),
+            ("/other/Dune.epub".to_string(), None),
+        ];
+        let targets = |collision: &str| -> Vec<(String, String)> {
+            resolve_outputs(&requests, Some(&naming(collision)))
+                .unwrap()
+                .into_iter()
+                .map(|r| (Path::new(&r.target_path).file_name().unwrap().to_string_lossy().to_string(), r.action))
+                .collect()
+        };
+
+        let pair = |name: &str, action: &str| (name.to_string(), action.to_string());
+        assert_eq!(targets("suffix"), vec![pair("Dune (2).pdf", "create"), pair("Dune (3).pdf", "create")]);
+        assert_eq!(targets("skip"), vec![pair("Dune.pdf", "skip"), pair("Dune.pdf", "skip")]);
+        assert_eq!(targets("overwrite"), vec![pair("Dune.pdf", "overwrite"), pair("Dune (2).pdf", "create")]);
+        assert!(resolve_outputs(&requests, Some(&naming("rename"))).is_err());
+
+        assert_eq!(
+            numbered_path(Path::new("/k/Dune.kepub.epub"), 2),
+            PathBuf::from("/k/Dune (2).kepub.epub")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
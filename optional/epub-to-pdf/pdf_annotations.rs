@@ -0,0 +1,992 @@
+/// PDF Highlight Annotations
+/// Marks reading highlights (made in Stomy or on a Kobo) in a converted PDF. The
+/// highlighted text is searched in the page text, so the output of every converter
+/// works, and covered by a Highlight annotation with the note in its popup. The
+/// highlights and notes can also be appended to the PDF as a last section.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/pdf_annotations.rs (needs pdf_postprocess.rs and epub_native.rs)
+/// 2. Add `mod pdf_annotations;` to src-tauri/src/main.rs
+
+use printpdf::lopdf::content::Content;
+use printpdf::lopdf::{self, Dictionary, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::epub_converter::EpubToPdfSettings;
+use super::epub_native::{pdf_text, render_paragraphs_pdf};
+use super::pdf_postprocess::{concat, page_size};
+
+const IDENTITY: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Highlighter yellow, as RGB fractions
+const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 0.88, 0.2];
+
+/// Glyph box below and above the baseline, in font size units
+const DESCENT: f32 = 0.22;
+const ASCENT: f32 = 0.78;
+
+/// Nested form XObjects followed when reading page text
+const MAX_FORM_DEPTH: usize = 4;
+
+static APPENDIX_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Highlight {
+    pub text: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub location: Option<String>, // Chapter or reader position, shown in the appendix
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfAnnotations {
+    pub highlights: Vec<Highlight>,
+    #[serde(default)]
+    pub notes_appendix: bool, // Append the highlights and notes after the last page
+}
+
+/// A shown character and its box in page space
+#[derive(Debug, Clone)]
+struct Glyph {
+    text: String,
+    x0: f32,
+    x1: f32,
+    bottom: f32,
+    top: f32,
+}
+
+/// Where a highlight was found: its line boxes (x0, bottom, x1, top) on each page
+type Placement = Vec<(usize, Vec<[f32; 4]>)>;
+
+// ============================================================================
+// Fonts
+// ============================================================================
+
+/// What is needed to turn shown strings into text and positions
+#[derive(Debug, Default)]
+struct PdfFont {
+    two_byte: bool, // Type0 fonts, in practice Identity-H
+    to_unicode: HashMap<u32, String>,
+    encoding: Option<String>,
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+    scale: f32, // Glyph space to text space
+}
+
+fn stream_bytes(stream: &Stream) -> Vec<u8> {
+    stream.decompressed_content().unwrap_or_else(|_| stream.content.clone())
+}
+
+fn utf16_text(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn code_of(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |code, b| (code << 8) | u32::from(*b))
+}
+
+#[derive(Debug, PartialEq)]
+enum CmapToken {
+    Hex(Vec<u8>),
+    Open,
+    Close,
+    Word(String),
+}
+
+fn cmap_tokens(text: &str) -> Vec<CmapToken> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' if chars.peek() == Some(&'<') => {
+                chars.next();
+                tokens.push(CmapToken::Word("<<".to_string()));
+            }
+            '<' => {
+                let digits: String = chars.by_ref().take_while(|c| *c != '>').filter(|c| c.is_ascii_hexdigit()).collect();
+                let bytes = (0..digits.len() / 2)
+                    .filter_map(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).ok())
+                    .collect();
+                tokens.push(CmapToken::Hex(bytes));
+            }
+            '[' => tokens.push(CmapToken::Open),
+            ']' => tokens.push(CmapToken::Close),
+            '%' => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "<>[]%".contains(next) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(CmapToken::Word(word));
+            }
+        }
+    }
+    tokens
+}
+
+/// Character codes to text from a ToUnicode CMap (bfchar and bfrange sections)
+fn parse_cmap(text: &str) -> HashMap<u32, String> {
+    let tokens = cmap_tokens(text);
+    let mut map = HashMap::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            CmapToken::Word(w) if w == "beginbfchar" => {
+                i += 1;
+                while let (Some(CmapToken::Hex(src)), Some(CmapToken::Hex(dst))) = (tokens.get(i), tokens.get(i + 1)) {
+                    map.insert(code_of(src), utf16_text(dst));
+                    i += 2;
+                }
+            }
+            CmapToken::Word(w) if w == "beginbfrange" => {
+                i += 1;
+                while let (Some(CmapToken::Hex(lo)), Some(CmapToken::Hex(hi))) = (tokens.get(i), tokens.get(i + 1)) {
+                    let (lo, hi) = (code_of(lo), code_of(hi));
+                    if hi < lo || hi - lo > 0xFFFF {
+                        break;
+                    }
+                    match tokens.get(i + 2) {
+                        Some(CmapToken::Hex(dst)) => {
+                            // The last UTF-16 unit counts up through the range
+                            let mut units: Vec<u16> =
+                                dst.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+                            for code in lo..=hi {
+                                map.insert(code, String::from_utf16_lossy(&units));
+                                if let Some(last) = units.last_mut() {
+                                    *last = last.wrapping_add(1);
+                                }
+                            }
+                            i += 3;
+                        }
+                        Some(CmapToken::Open) => {
+                            i += 3;
+                            let mut code = lo;
+                            while let Some(CmapToken::Hex(dst)) = tokens.get(i) {
+                                map.insert(code, utf16_text(dst));
+                                code += 1;
+                                i += 1;
+                            }
+                            i += 1; // Closing bracket
+                        }
+                        _ => break,
+                    }
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    map
+}
+
+impl PdfFont {
+    fn load(doc: &lopdf::Document, dict: &Dictionary) -> Self {
+        let get = |dict: &Dictionary, key: &[u8]| -> Option<Object> {
+            dict.get(key).ok().and_then(|o| doc.dereference(o).ok()).map(|(_, o)| o.clone())
+        };
+        let number = |object: &Object| doc.dereference(object).ok().and_then(|(_, o)| o.as_float().ok());
+
+        let subtype = dict.get(b"Subtype").and_then(Object::as_name).unwrap_or_default();
+        let mut font = PdfFont {
+            two_byte: subtype == b"Type0",
+            default_width: 500.0, // Standard fonts list no widths
+            scale: 0.001,
+            ..PdfFont::default()
+        };
+        if let Some(Object::Stream(cmap)) = get(dict, b"ToUnicode") {
+            font.to_unicode = parse_cmap(&String::from_utf8_lossy(&stream_bytes(&cmap)));
+        }
+        font.encoding = match get(dict, b"Encoding") {
+            Some(Object::Name(name)) => Some(String::from_utf8_lossy(&name).to_string()),
+            Some(Object::Dictionary(encoding)) => encoding
+                .get(b"BaseEncoding")
+                .and_then(Object::as_name)
+                .ok()
+                .map(|n| String::from_utf8_lossy(n).to_string()),
+            _ => None,
+        };
+
+        if font.two_byte {
+            let descendant = get(dict, b"DescendantFonts")
+                .and_then(|o| o.as_array().ok().and_then(|a| a.first().cloned()))
+                .and_then(|o| doc.dereference(&o).ok().and_then(|(_, o)| o.as_dict().ok().cloned()));
+            if let Some(descendant) = descendant {
+                font.default_width = get(&descendant, b"DW").and_then(|o| o.as_float().ok()).unwrap_or(1000.0);
+                // W lists "first [w1 w2 ...]" and "first last w" groups
+                let w = get(&descendant, b"W").and_then(|o| o.as_array().ok().cloned()).unwrap_or_default();
+                let mut i = 0;
+                while i + 1 < w.len() {
+                    let Ok(first) = w[i].as_i64() else {
+                        break;
+                    };
+                    let first = first as u32;
+                    match &w[i + 1] {
+                        Object::Array(list) => {
+                            for (j, width) in list.iter().enumerate() {
+                                if let Some(width) = number(width) {
+                                    font.widths.insert(first + j as u32, width);
+                                }
+                            }
+                            i += 2;
+                        }
+                        last => {
+                            let (Ok(last), Some(width)) = (last.as_i64(), w.get(i + 2).and_then(number)) else {
+                                break;
+                            };
+                            for code in first..=(last as u32).min(first + 0xFFFF) {
+                                font.widths.insert(code, width);
+                            }
+                            i += 3;
+                        }
+                    }
+                }
+            }
+        } else {
+            let first = get(dict, b"FirstChar").and_then(|o| o.as_i64().ok()).unwrap_or(0) as u32;
+            if let Some(Object::Array(widths)) = get(dict, b"Widths") {
+                for (i, width) in widths.iter().enumerate() {
+                    if let Some(width) = number(width) {
+                        font.widths.insert(first + i as u32, width);
+                    }
+                }
+            }
+            if let Some(missing) = get(dict, b"FontDescriptor")
+                .and_then(|d| d.as_dict().ok().and_then(|d| get(d, b"MissingWidth")))
+                .and_then(|o| o.as_float().ok())
+                .filter(|w| *w > 0.0)
+            {
+                font.default_width = missing;
+            }
+            // Type 3 glyphs are measured in their own units
+            if subtype == b"Type3" {
+                if let Some(matrix) = get(dict, b"FontMatrix").and_then(|o| o.as_array().ok().cloned()) {
+                    font.scale = matrix.first().and_then(number).unwrap_or(0.001);
+                }
+            }
+        }
+        font
+    }
+
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.two_byte {
+            bytes.chunks(2).map(code_of).collect()
+        } else {
+            bytes.iter().map(|b| u32::from(*b)).collect()
+        }
+    }
+
+    fn text(&self, code: u32) -> String {
+        if let Some(text) = self.to_unicode.get(&code) {
+            return text.clone();
+        }
+        if self.two_byte {
+            return String::new();
+        }
+        let encoding = self.encoding.as_deref().unwrap_or("WinAnsiEncoding");
+        lopdf::Document::decode_text(Some(encoding), &[code as u8])
+    }
+
+    /// Advance of a glyph in text space, per unit of font size
+    fn width(&self, code: u32) -> f32 {
+        self.widths.get(&code).copied().unwrap_or(self.default_width) * self.scale
+    }
+}
+
+// ============================================================================
+// Page Text
+// ============================================================================
+
+#[derive(Debug, Clone)]
+struct TextState {
+    ctm: [f32; 6],
+    font: Option<Vec<u8>>,
+    size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+fn dictionary<'a>(doc: &'a lopdf::Document, parent: &'a Dictionary, key: &[u8]) -> Option<&'a Dictionary> {
+    parent
+        .get(key)
+        .and_then(|o| doc.dereference(o))
+        .and_then(|(_, o)| o.as_dict())
+        .ok()
+}
+
+fn load_fonts(doc: &lopdf::Document, resources: Option<&Dictionary>) -> HashMap<Vec<u8>, PdfFont> {
+    resources
+        .and_then(|r| dictionary(doc, r, b"Font"))
+        .map(|fonts| {
+            fonts
+                .iter()
+                .filter_map(|(name, object)| {
+                    let (_, font) = doc.dereference(object).ok()?;
+                    Some((name.clone(), PdfFont::load(doc, font.as_dict().ok()?)))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Show a string: one glyph per character code, advancing the text matrix
+fn show_text(bytes: &[u8], font: &PdfFont, state: &TextState, tm: &mut [f32; 6], glyphs: &mut Vec<Glyph>) {
+    for code in font.codes(bytes) {
+        let width = font.width(code) * state.size * state.horizontal_scale;
+        let m = concat(*tm, state.ctm);
+        let point = |x: f32, y: f32| (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5]);
+        let (ax, ay) = point(0.0, state.rise - DESCENT * state.size);
+        let (bx, by) = point(width, state.rise + ASCENT * state.size);
+
+        let text = font.text(code);
+        if !text.trim().is_empty() {
+            glyphs.push(Glyph {
+                text,
+                x0: ax.min(bx),
+                x1: ax.max(bx),
+                bottom: ay.min(by),
+                top: ay.max(by),
+            });
+        }
+
+        let spacing = state.char_spacing + if !font.two_byte && code == 32 { state.word_spacing } else { 0.0 };
+        let advance = width + spacing * state.horizontal_scale;
+        *tm = concat([1.0, 0.0, 0.0, 1.0, advance, 0.0], *tm);
+    }
+}
+
+/// Glyphs drawn by a content stream, following form XObjects
+fn content_glyphs(
+    doc: &lopdf::Document,
+    content: &[u8],
+    resources: Option<&Dictionary>,
+    ctm: [f32; 6],
+    depth: usize,
+    glyphs: &mut Vec<Glyph>,
+) {
+    let Ok(content) = Content::decode(content) else {
+        return;
+    };
+    let fonts = load_fonts(doc, resources);
+    let mut state = TextState {
+        ctm,
+        font: None,
+        size: 0.0,
+        char_spacing: 0.0,
+        word_spacing: 0.0,
+        horizontal_scale: 1.0,
+        leading: 0.0,
+        rise: 0.0,
+    };
+    let mut saved = Vec::new();
+    let (mut tm, mut tlm) = (IDENTITY, IDENTITY);
+    let next_line = |tm: &mut [f32; 6], tlm: &mut [f32; 6], tx: f32, ty: f32| {
+        *tlm = concat([1.0, 0.0, 0.0, 1.0, tx, ty], *tlm);
+        *tm = *tlm;
+    };
+
+    for operation in content.operations {
+        let n: Vec<f32> = operation.operands.iter().filter_map(|o| o.as_float().ok()).collect();
+        let font = state.font.as_ref().and_then(|name| fonts.get(name));
+
+        match (operation.operator.as_str(), &n[..]) {
+            ("q", _) => saved.push(state.clone()),
+            ("Q", _) => state = saved.pop().unwrap_or(state),
+            ("cm", &[a, b, c, d, e, f]) => state.ctm = concat([a, b, c, d, e, f], state.ctm),
+            ("BT", _) => (tm, tlm) = (IDENTITY, IDENTITY),
+            ("Tf", &[size]) => {
+                state.font = operation.operands.first().and_then(|o| o.as_name().ok()).map(<[u8]>::to_vec);
+                state.size = size;
+            }
+            ("Tc", &[value]) => state.char_spacing = value,
+            ("Tw", &[value]) => state.word_spacing = value,
+            ("Tz", &[value]) => state.horizontal_scale = value / 100.0,
+            ("TL", &[value]) => state.leading = value,
+            ("Ts", &[value]) => state.rise = value,
+            ("Td", &[tx, ty]) => next_line(&mut tm, &mut tlm, tx, ty),
+            ("TD", &[tx, ty]) => {
+                state.leading = -ty;
+                next_line(&mut tm, &mut tlm, tx, ty);
+            }
+            ("Tm", &[a, b, c, d, e, f]) => (tm, tlm) = ([a, b, c, d, e, f], [a, b, c, d, e, f]),
+            ("T*", _) => next_line(&mut tm, &mut tlm, 0.0, -state.leading),
+            ("Tj", _) | ("'", _) | ("\"", _) => {
+                if operation.operator != "Tj" {
+                    if let &[word, char] = &n[..] {
+                        (state.word_spacing, state.char_spacing) = (word, char);
+                    }
+                    next_line(&mut tm, &mut tlm, 0.0, -state.leading);
+                }
+                if let (Some(font), Some(Ok(bytes))) = (font, operation.operands.last().map(Object::as_str)) {
+                    show_text(bytes, font, &state, &mut tm, glyphs);
+                }
+            }
+            ("TJ", _) => {
+                let (Some(font), Some(Ok(items))) = (font, operation.operands.first().map(Object::as_array)) else {
+                    continue;
+                };
+                for item in items {
+                    match item {
+                        Object::String(bytes, _) => show_text(bytes, font, &state, &mut tm, glyphs),
+                        other => {
+                            let offset = other.as_float().unwrap_or(0.0);
+                            let tx = -offset / 1000.0 * state.size * state.horizontal_scale;
+                            tm = concat([1.0, 0.0, 0.0, 1.0, tx, 0.0], tm);
+                        }
+                    }
+                }
+            }
+            ("Do", _) if depth < MAX_FORM_DEPTH => {
+                let form = operation
+                    .operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .zip(resources.and_then(|r| dictionary(doc, r, b"XObject")))
+                    .and_then(|(name, xobjects)| xobjects.get(name).and_then(|o| doc.dereference(o)).ok())
+                    .and_then(|(_, o)| o.as_stream().ok())
+                    .filter(|s| s.dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"Form"));
+                if let Some(form) = form {
+                    let matrix: Vec<f32> = form
+                        .dict
+                        .get(b"Matrix")
+                        .and_then(Object::as_array)
+                        .map(|m| m.iter().filter_map(|v| v.as_float().ok()).collect())
+                        .unwrap_or_default();
+                    let matrix = <[f32; 6]>::try_from(matrix).unwrap_or(IDENTITY);
+                    let form_resources = dictionary(doc, &form.dict, b"Resources").or(resources);
+                    let ctm = concat(matrix, state.ctm);
+                    content_glyphs(doc, &stream_bytes(form), form_resources, ctm, depth + 1, glyphs);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn page_glyphs(doc: &lopdf::Document, page_id: ObjectId) -> Vec<Glyph> {
+    let Ok(content) = doc.get_page_content(page_id) else {
+        return Vec::new();
+    };
+    let resources = match doc.get_page_resources(page_id) {
+        (Some(dict), _) => Some(dict),
+        (None, ids) => ids.first().and_then(|id| doc.get_dictionary(*id).ok()),
+    };
+    let mut glyphs = Vec::new();
+    content_glyphs(doc, &content, resources, IDENTITY, 0, &mut glyphs);
+    glyphs
+}
+
+// ============================================================================
+// Search
+// ============================================================================
+
+/// Characters compared when searching: letters and digits, lowercased. Spaces,
+/// punctuation and end-of-line hyphens differ between the book and the PDF.
+fn search_chars(text: &str) -> Vec<char> {
+    let mut chars = Vec::new();
+    for c in text.chars() {
+        let ligature = match c {
+            '\u{fb00}' => "ff",
+            '\u{fb01}' => "fi",
+            '\u{fb02}' => "fl",
+            '\u{fb03}' => "ffi",
+            '\u{fb04}' => "ffl",
+            c if c.is_alphanumeric() => {
+                chars.extend(c.to_lowercase());
+                continue;
+            }
+            _ => continue,
+        };
+        chars.extend(ligature.chars());
+    }
+    chars
+}
+
+/// Searchable characters of the whole document, each with its page and glyph index
+struct TextIndex {
+    chars: Vec<char>,
+    positions: Vec<(usize, usize)>,
+}
+
+impl TextIndex {
+    fn new(pages: &[Vec<Glyph>]) -> Self {
+        let mut index = TextIndex { chars: Vec::new(), positions: Vec::new() };
+        for (page, glyphs) in pages.iter().enumerate() {
+            for (i, glyph) in glyphs.iter().enumerate() {
+                for c in search_chars(&glyph.text) {
+                    index.chars.push(c);
+                    index.positions.push((page, i));
+                }
+            }
+        }
+        index
+    }
+
+    /// First occurrence not already taken by an identical highlight
+    fn find(&self, needle: &[char], taken: &HashSet<usize>) -> Option<usize> {
+        if needle.is_empty() || needle.len() > self.chars.len() {
+            return None;
+        }
+        (0..=self.chars.len() - needle.len())
+            .find(|&start| !taken.contains(&start) && self.chars[start..start + needle.len()] == *needle)
+    }
+}
+
+/// One box per line for the glyphs of a match on one page
+fn line_boxes(glyphs: &[&Glyph]) -> Vec<[f32; 4]> {
+    let mut boxes: Vec<[f32; 4]> = Vec::new();
+    for glyph in glyphs {
+        match boxes.last_mut() {
+            Some(line) if (glyph.bottom - line[1]).abs() < (line[3] - line[1]) * 0.5 && glyph.x0 >= line[0] - 1.0 => {
+                line[0] = line[0].min(glyph.x0);
+                line[1] = line[1].min(glyph.bottom);
+                line[2] = line[2].max(glyph.x1);
+                line[3] = line[3].max(glyph.top);
+            }
+            _ => boxes.push([glyph.x0, glyph.bottom, glyph.x1, glyph.top]),
+        }
+    }
+    boxes
+}
+
+/// Line boxes of every highlight found in the pages, None for those that are not
+fn place_highlights(pages: &[Vec<Glyph>], highlights: &[Highlight]) -> Vec<Option<Placement>> {
+    let index = TextIndex::new(pages);
+    let mut taken = HashSet::new();
+    highlights
+        .iter()
+        .map(|highlight| {
+            let needle = search_chars(&highlight.text);
+            let start = index.find(&needle, &taken)?;
+            taken.insert(start);
+
+            let mut placement: Placement = Vec::new();
+            let mut matched: Vec<(usize, usize)> = index.positions[start..start + needle.len()].to_vec();
+            matched.dedup();
+            for (page, glyph) in matched {
+                match placement.last_mut() {
+                    Some((last, boxes)) if *last == page => boxes.push([glyph as f32, 0.0, 0.0, 0.0]),
+                    _ => placement.push((page, vec![[glyph as f32, 0.0, 0.0, 0.0]])),
+                }
+            }
+            // Glyph indexes collected per page, now turned into line boxes
+            for (page, boxes) in &mut placement {
+                let glyphs: Vec<&Glyph> = boxes.iter().map(|b| &pages[*page][b[0] as usize]).collect();
+                *boxes = line_boxes(&glyphs);
+            }
+            Some(placement)
+        })
+        .collect()
+}
+
+// ============================================================================
+// Annotations
+// ============================================================================
+
+fn numbers(values: &[f32]) -> Object {
+    Object::Array(values.iter().map(|v| Object::Real(*v)).collect())
+}
+
+fn bounds(boxes: &[[f32; 4]]) -> [f32; 4] {
+    boxes.iter().fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |r, b| {
+        [r[0].min(b[0]), r[1].min(b[1]), r[2].max(b[2]), r[3].max(b[3])]
+    })
+}
+
+/// Content that paints the boxes in multiply mode, so the text stays readable
+fn highlight_drawing(boxes: &[[f32; 4]], state: &str) -> String {
+    let [r, g, b] = HIGHLIGHT_COLOR;
+    let mut content = format!("q /{} gs {} {} {} rg\n", state, r, g, b);
+    for [x0, y0, x1, y1] in boxes {
+        content.push_str(&format!("{} {} {} {} re\n", x0, y0, x1 - x0, y1 - y0));
+    }
+    content.push_str("f Q\n");
+    content
+}
+
+fn multiply_state() -> Dictionary {
+    let mut state = Dictionary::new();
+    state.set("Type", Object::Name(b"ExtGState".to_vec()));
+    state.set("BM", Object::Name(b"Multiply".to_vec()));
+    state
+}
+
+fn add_annotation(doc: &mut lopdf::Document, page_id: ObjectId, annotation: ObjectId) -> Result<(), String> {
+    let existing = doc
+        .get_dictionary(page_id)
+        .map_err(|e| format!("Failed to read PDF page: {}", e))?
+        .get(b"Annots")
+        .ok()
+        .cloned();
+    match existing {
+        Some(Object::Reference(id)) => {
+            if let Ok(Object::Array(annots)) = doc.get_object_mut(id) {
+                annots.push(Object::Reference(annotation));
+                return Ok(());
+            }
+        }
+        Some(Object::Array(mut annots)) => {
+            annots.push(Object::Reference(annotation));
+            return doc
+                .get_dictionary_mut(page_id)
+                .map(|page| page.set("Annots", annots))
+                .map_err(|e| format!("Failed to read PDF page: {}", e));
+        }
+        _ => {}
+    }
+    doc.get_dictionary_mut(page_id)
+        .map(|page| page.set("Annots", vec![Object::Reference(annotation)]))
+        .map_err(|e| format!("Failed to read PDF page: {}", e))
+}
+
+/// Highlight annotation with an appearance stream, and a popup when there is a note
+fn annotate(
+    doc: &mut lopdf::Document,
+    page_id: ObjectId,
+    boxes: &[[f32; 4]],
+    highlight: &Highlight,
+    with_note: bool,
+) -> Result<(), String> {
+    let rect = bounds(boxes);
+    let quads: Vec<f32> = boxes
+        .iter()
+        .flat_map(|[x0, y0, x1, y1]| [*x0, *y1, *x1, *y1, *x0, *y0, *x1, *y0])
+        .collect();
+
+    let mut states = Dictionary::new();
+    states.set("GS0", multiply_state());
+    let mut resources = Dictionary::new();
+    resources.set("ExtGState", states);
+    let mut form = Dictionary::new();
+    form.set("Type", Object::Name(b"XObject".to_vec()));
+    form.set("Subtype", Object::Name(b"Form".to_vec()));
+    form.set("BBox", numbers(&rect));
+    form.set("Resources", resources);
+    let appearance = doc.add_object(Stream::new(form, highlight_drawing(boxes, "GS0").into_bytes()));
+    let mut appearances = Dictionary::new();
+    appearances.set("N", appearance);
+
+    let note = highlight.note.as_deref().filter(|n| with_note && !n.trim().is_empty());
+    let mut annotation = Dictionary::new();
+    annotation.set("Type", Object::Name(b"Annot".to_vec()));
+    annotation.set("Subtype", Object::Name(b"Highlight".to_vec()));
+    annotation.set("Rect", numbers(&rect));
+    annotation.set("QuadPoints", numbers(&quads));
+    annotation.set("C", numbers(&HIGHLIGHT_COLOR));
+    annotation.set("F", 4); // Print
+    annotation.set("P", page_id);
+    annotation.set("AP", appearances);
+    annotation.set("Contents", pdf_text(note.unwrap_or(&highlight.text)));
+    let annotation_id = doc.add_object(annotation);
+    add_annotation(doc, page_id, annotation_id)?;
+
+    if note.is_some() {
+        // Closed popup in the outer margin, next to the highlight
+        let (width, _) = page_size(doc, page_id);
+        let top = rect[3];
+        let mut popup = Dictionary::new();
+        popup.set("Type", Object::Name(b"Annot".to_vec()));
+        popup.set("Subtype", Object::Name(b"Popup".to_vec()));
+        popup.set("Rect", numbers(&[(width - 200.0).max(0.0), (top - 120.0).max(0.0), width, top]));
+        popup.set("Parent", annotation_id);
+        popup.set("Open", false);
+        let popup_id = doc.add_object(popup);
+        if let Ok(annotation) = doc.get_dictionary_mut(annotation_id) {
+            annotation.set("Popup", popup_id);
+        }
+        add_annotation(doc, page_id, popup_id)?;
+    }
+    Ok(())
+}
+
+/// Resource dictionary of a page, made its own when it was inherited
+fn page_resources_mut(doc: &mut lopdf::Document, page_id: ObjectId) -> Result<&mut Dictionary, String> {
+    let page = doc.get_dictionary(page_id).map_err(|e| format!("Failed to read PDF page: {}", e))?;
+    match page.get(b"Resources") {
+        Ok(Object::Reference(id)) => {
+            let id = *id;
+            doc.get_dictionary_mut(id).map_err(|e| format!("Failed to read page resources: {}", e))
+        }
+        Ok(Object::Dictionary(_)) => doc
+            .get_dictionary_mut(page_id)
+            .and_then(|p| p.get_mut(b"Resources"))
+            .and_then(Object::as_dict_mut)
+            .map_err(|e| format!("Failed to read page resources: {}", e)),
+        _ => {
+            let (_, ids) = doc.get_page_resources(page_id);
+            let inherited = ids.first().and_then(|id| doc.get_dictionary(*id).ok()).cloned().unwrap_or_default();
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| format!("Failed to read PDF page: {}", e))?;
+            page.set("Resources", inherited);
+            page.get_mut(b"Resources")
+                .and_then(Object::as_dict_mut)
+                .map_err(|e| format!("Failed to read page resources: {}", e))
+        }
+    }
+}
+
+/// Paint the boxes into the page itself; imposed booklets keep no annotations
+fn draw_highlight(doc: &mut lopdf::Document, page_id: ObjectId, boxes: &[[f32; 4]]) -> Result<(), String> {
+    let state_id = doc.add_object(multiply_state());
+    let resources = page_resources_mut(doc, page_id)?;
+    let states_ref = match resources.get(b"ExtGState") {
+        Ok(Object::Reference(id)) => Some(*id),
+        _ => None,
+    };
+    let name = format!("StomyHighlight{}", state_id.0);
+    match states_ref {
+        Some(id) => doc
+            .get_dictionary_mut(id)
+            .map_err(|e| format!("Failed to read page resources: {}", e))?
+            .set(name.as_bytes(), state_id),
+        None => match resources.get_mut(b"ExtGState").and_then(Object::as_dict_mut) {
+            Ok(states) => states.set(name.as_bytes(), state_id),
+            Err(_) => {
+                let mut states = Dictionary::new();
+                states.set(name.as_bytes(), state_id);
+                resources.set("ExtGState", states);
+            }
+        },
+    }
+    doc.add_page_contents(page_id, highlight_drawing(boxes, &name).into_bytes())
+        .map_err(|e| format!("Failed to write page content: {}", e))
+}
+
+// ============================================================================
+// Notes Appendix
+// ============================================================================
+
+/// Paragraphs of the appendix: where each highlight is, its text and its note
+fn appendix_paragraphs(highlights: &[Highlight], pages: &[Option<usize>]) -> Vec<(Option<u8>, String)> {
+    let mut paragraphs = vec![(Some(1), "Highlights and Notes".to_string())];
+    for (highlight, page) in highlights.iter().zip(pages) {
+        let page = match page {
+            Some(page) => format!("Page {}", page + 1),
+            None => "Not found in this PDF".to_string(),
+        };
+        let heading = match &highlight.location {
+            Some(location) if !location.trim().is_empty() => format!("{} · {}", location.trim(), page),
+            _ => page,
+        };
+        paragraphs.push((Some(4), heading));
+        paragraphs.push((None, format!("“{}”", highlight.text.trim())));
+        if let Some(note) = highlight.note.as_ref().filter(|n| !n.trim().is_empty()) {
+            paragraphs.push((None, note.trim().to_string()));
+        }
+    }
+    paragraphs
+}
+
+/// Move the pages of `other` to the end of `doc`
+fn append_pages(doc: &mut lopdf::Document, mut other: lopdf::Document) -> Result<(), String> {
+    other.renumber_objects_with(doc.max_id + 1);
+    let pages: Vec<ObjectId> = other.get_pages().into_values().collect();
+    // Inherited resources would be lost with the other page tree
+    for page_id in &pages {
+        if let (None, ids) = other.get_page_resources(*page_id) {
+            if let (Some(id), Ok(page)) = (ids.first().copied(), other.get_dictionary_mut(*page_id)) {
+                page.set("Resources", id);
+            }
+        }
+    }
+
+    let pages_id = doc
+        .catalog()
+        .and_then(|c| c.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to read PDF pages: {}", e))?;
+    doc.max_id = doc.max_id.max(other.max_id);
+    doc.objects.extend(other.objects);
+    for page_id in &pages {
+        if let Ok(page) = doc.get_dictionary_mut(*page_id) {
+            page.set("Parent", pages_id);
+        }
+    }
+
+    let root = doc
+        .get_dictionary_mut(pages_id)
+        .map_err(|e| format!("Failed to read PDF pages: {}", e))?;
+    let mut kids = root.get(b"Kids").and_then(Object::as_array).cloned().unwrap_or_default();
+    kids.extend(pages.iter().map(|id| Object::Reference(*id)));
+    let count = root.get(b"Count").and_then(Object::as_i64).unwrap_or(0) + pages.len() as i64;
+    root.set("Kids", kids);
+    root.set("Count", count);
+    Ok(())
+}
+
+fn append_notes(
+    doc: &mut lopdf::Document,
+    highlights: &[Highlight],
+    pages: &[Option<usize>],
+    settings: &EpubToPdfSettings,
+) -> Result<(), String> {
+    let path = std::env::temp_dir().join(format!(
+        "stomy-notes-{}-{}.pdf",
+        std::process::id(),
+        APPENDIX_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let rendered = render_paragraphs_pdf(&path, "Highlights and Notes", &appendix_paragraphs(highlights, pages), settings)
+        .and_then(|_| lopdf::Document::load(&path).map_err(|e| format!("Failed to read notes appendix: {}", e)));
+    let _ = std::fs::remove_file(&path);
+    append_pages(doc, rendered?)
+}
+
+// ============================================================================
+// Public API
+// ============================================================================
+
+/// Mark the highlights in the document and append the notes when asked. Booklets are
+/// printed, so `flatten` paints the highlights into the pages instead.
+/// Returns the number of highlights found in the text.
+pub fn annotate_pdf(
+    doc: &mut lopdf::Document,
+    annotations: &PdfAnnotations,
+    settings: &EpubToPdfSettings,
+    flatten: bool,
+) -> Result<usize, String> {
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let pages: Vec<Vec<Glyph>> = page_ids.iter().map(|id| page_glyphs(doc, *id)).collect();
+    let placements = place_highlights(&pages, &annotations.highlights);
+
+    for (highlight, placement) in annotations.highlights.iter().zip(&placements) {
+        let Some(placement) = placement else {
+            continue;
+        };
+        // The note goes with the first part of a highlight spanning pages
+        for (part, (page, boxes)) in placement.iter().enumerate() {
+            if flatten {
+                draw_highlight(doc, page_ids[*page], boxes)?;
+            } else {
+                annotate(doc, page_ids[*page], boxes, highlight, part == 0)?;
+            }
+        }
+    }
+
+    let first_pages: Vec<Option<usize>> = placements
+        .iter()
+        .map(|p| p.as_ref().and_then(|p| p.first()).map(|(page, _)| *page))
+        .collect();
+    let found = first_pages.iter().filter(|p| p.is_some()).count();
+    if annotations.notes_appendix && !annotations.highlights.is_empty() {
+        append_notes(doc, &annotations.highlights, &first_pages, settings)?;
+    }
+    Ok(found)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmap() {
+        let cmap = "/CIDInit /ProcSet findresource begin\n2 beginbfchar\n<0003> <0020>\n<0011> <FB01>\nendbfchar\n\
+                    1 beginbfrange\n<0024> <0026> <0041>\nendbfrange\n1 beginbfrange\n<0030> <0031> [<00E9> <0063006B>]\nendbfrange\nend";
+        let map = parse_cmap(cmap);
+        assert_eq!(map[&3], " ");
+        assert_eq!(map[&0x11], "\u{fb01}");
+        assert_eq!(map[&0x26], "C");
+        assert_eq!(map[&0x31], "ck");
+    }
+
+    #[test]
+    fn test_search_across_lines() {
+        let glyph = |text: &str, x: f32, y: f32| Glyph { text: text.to_string(), x0: x, x1: x + 5.0, bottom: y, top: y + 10.0 };
+        // "the spice must" / "ﬂow-" / "ing" over two lines and a page break
+        let mut first = Vec::new();
+        for (i, c) in "the spice must".chars().enumerate() {
+            first.push(glyph(&c.to_string(), 10.0 + i as f32 * 5.0, 700.0));
+        }
+        first.push(glyph("\u{fb02}", 10.0, 686.0));
+        first.push(glyph("o", 15.0, 686.0));
+        first.push(glyph("w", 20.0, 686.0));
+        first.push(glyph("-", 25.0, 686.0));
+        let second = vec![glyph("i", 10.0, 700.0), glyph("n", 15.0, 700.0), glyph("g", 20.0, 700.0)];
+
+        let highlights = vec![
+            Highlight { text: "Spice must flowing!".to_string(), note: None, location: None },
+            Highlight { text: "sandworm".to_string(), note: None, location: None },
+        ];
+        let placements = place_highlights(&[first, second], &highlights);
+        let placement = placements[0].as_ref().unwrap();
+        assert_eq!(placement.len(), 2);
+        assert_eq!(placement[0].0, 0);
+        assert_eq!(placement[0].1, vec![[30.0, 700.0, 80.0, 710.0], [10.0, 686.0, 25.0, 696.0]]);
+        assert_eq!(placement[1], (1, vec![[10.0, 700.0, 25.0, 710.0]]));
+        assert!(placements[1].is_none());
+    }
+
+    #[test]
+    fn test_annotate_pdf() {
+        let dir = std::env::temp_dir().join(format!("stomy-annotations-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pdf = dir.join("dune.pdf");
+        let settings = EpubToPdfSettings::default();
+        let paragraphs = vec![
+            (Some(1), "Dune".to_string()),
+            (None, "I must not fear. Fear is the mind-killer.".to_string()),
+        ];
+        render_paragraphs_pdf(&pdf, "Dune", &paragraphs, &settings).unwrap();
+
+        let mut doc = lopdf::Document::load(&pdf).unwrap();
+        let annotations = PdfAnnotations {
+            highlights: vec![
+                Highlight {
+                    text: "Fear is the mind-killer".to_string(),
+                    note: Some("Litany against fear".to_string()),
+                    location: Some("Chapter 1".to_string()),
+                },
+                Highlight { text: "The spice must flow".to_string(), note: None, location: None },
+            ],
+            notes_appendix: true,
+        };
+        assert_eq!(annotate_pdf(&mut doc, &annotations, &settings, false).unwrap(), 1);
+
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        assert_eq!(pages.len(), 2);
+        let annots = doc.get_page_annotations(pages[0]);
+        assert_eq!(annots.len(), 2);
+        let highlight = annots[0];
+        assert_eq!(highlight.get(b"Subtype").and_then(Object::as_name).unwrap(), b"Highlight");
+        assert_eq!(highlight.get(b"QuadPoints").and_then(Object::as_array).unwrap().len(), 8);
+        assert!(highlight.get(b"Popup").is_ok());
+        assert_eq!(annots[1].get(b"Subtype").and_then(Object::as_name).unwrap(), b"Popup");
+
+        // The appendix is searchable text as well
+        let appendix = page_glyphs(&doc, pages[1]);
+        let text: String = appendix.iter().map(|g| g.text.as_str()).collect();
+        assert!(text.contains("Chapter1·Page1"), "{}", text);
+        assert!(text.contains("NotfoundinthisPDF"));
+
+        // Flattened highlights draw into the page instead
+        let mut doc = lopdf::Document::load(&pdf).unwrap();
+        let plain = PdfAnnotations { notes_appendix: false, ..annotations };
+        annotate_pdf(&mut doc, &plain, &settings, true).unwrap();
+        let page = *doc.get_pages().values().next().unwrap();
+        assert!(doc.get_page_annotations(page).is_empty());
+        let content = String::from_utf8_lossy(&doc.get_page_content(page).unwrap()).to_string();
+        assert!(content.contains("/StomyHighlight"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
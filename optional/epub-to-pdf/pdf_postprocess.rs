@@ -0,0 +1,1201 @@
+/// PDF Post-processing
+/// Finishes the PDF written by any converter: copies the OPF metadata into the
+/// Info dictionary and an XMP packet, puts the EPUB cover on the first page,
+/// recompresses images for the compression level, merges duplicate streams and
+/// optionally marks highlights, imposes booklets, produces PDF/A-2b or a linearised
+/// (fast web view) file.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/pdf_postprocess.rs (needs epub_package.rs, epub_native.rs and pdf_annotations.rs)
+/// 2. Add `mod pdf_postprocess;` to src-tauri/src/main.rs
+/// 3. Linearisation runs qpdf, which must be installed separately
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use printpdf::lopdf::{self, Dictionary, Object, ObjectId, Stream, StringFormat};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::converter_registry::find_in_path;
+use super::epub_converter::EpubToPdfSettings;
+use super::epub_native::{jpeg_components, pdf_text};
+use super::epub_package::{EpubMetadata, EpubPackage};
+use super::layout_profiles::{self, Imposition};
+use super::pdf_annotations::{annotate_pdf, PdfAnnotations};
+
+/// A4 in points, for PDFs whose first page has no MediaBox
+const DEFAULT_PAGE_SIZE: (f32, f32) = (595.0, 842.0);
+
+/// Colour profile named by the PDF/A output intent
+const SRGB: &str = "sRGB IEC61966-2.1";
+
+// ============================================================================
+// Metadata
+// ============================================================================
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode a PDF text string (UTF-16BE with BOM, else PDFDocEncoding read as Latin-1)
+fn info_text(object: &Object) -> Option<String> {
+    let Object::String(bytes, _) = object else {
+        return None;
+    };
+    let text = match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|b| *b as char).collect(),
+    };
+    Some(text).filter(|t| !t.trim().is_empty())
+}
+
+/// XMP packet with the Dublin Core fields of the package; must agree with the Info dictionary
+fn xmp_packet(metadata: &EpubMetadata, producer: Option<&str>, date: &str, pdf_a: bool) -> String {
+    let list = |kind: &str, values: &[String]| -> String {
+        let items: String = values
+            .iter()
+            .map(|v| format!("<rdf:li>{}</rdf:li>", xml_escape(v)))
+            .collect();
+        format!("<rdf:{kind}>{items}</rdf:{kind}>")
+    };
+
+    let mut fields = vec!["<dc:format>application/pdf</dc:format>".to_string()];
+    if let Some(title) = &metadata.title {
+        fields.push(format!(
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+            xml_escape(title)
+        ));
+    }
+    if !metadata.creators.is_empty() {
+        fields.push(format!("<dc:creator>{}</dc:creator>", list("Seq", &metadata.creators)));
+    }
+    if let Some(language) = &metadata.language {
+        fields.push(format!("<dc:language>{}</dc:language>", list("Bag", std::slice::from_ref(language))));
+    }
+    if let Some(identifier) = &metadata.identifier {
+        fields.push(format!("<dc:identifier>{}</dc:identifier>", xml_escape(identifier)));
+    }
+    if let Some(publisher) = &metadata.publisher {
+        fields.push(format!("<dc:publisher>{}</dc:publisher>", list("Bag", std::slice::from_ref(publisher))));
+    }
+    if let Some(description) = &metadata.description {
+        fields.push(format!(
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+            xml_escape(description)
+        ));
+    }
+    if !metadata.subjects.is_empty() {
+        fields.push(format!("<dc:subject>{}</dc:subject>", list("Bag", &metadata.subjects)));
+        fields.push(format!("<pdf:Keywords>{}</pdf:Keywords>", xml_escape(&metadata.subjects.join(", "))));
+    }
+    if let Some(producer) = producer {
+        fields.push(format!("<pdf:Producer>{}</pdf:Producer>", xml_escape(producer)));
+    }
+    fields.push(format!("<xmp:CreateDate>{}</xmp:CreateDate>", date));
+    fields.push(format!("<xmp:ModifyDate>{}</xmp:ModifyDate>", date));
+    fields.push(format!("<xmp:MetadataDate>{}</xmp:MetadataDate>", date));
+    if pdf_a {
+        fields.push("<pdfaid:part>2</pdfaid:part>".to_string());
+        fields.push("<pdfaid:conformance>B</pdfaid:conformance>".to_string());
+    }
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" \
+xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n\
+{}\n\
+</rdf:Description>\n\
+</rdf:RDF>\n\
+</x:xmpmeta>\n\
+<?xpacket end=\"w\"?>",
+        fields.join("\n")
+    )
+}
+
+/// Write the metadata to the Info dictionary, the catalog language and an XMP stream.
+/// Fields the book lacks keep the value the converter wrote.
+fn write_metadata(doc: &mut lopdf::Document, metadata: &EpubMetadata, pdf_a: bool) -> Result<(), String> {
+    let info_id = match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => id,
+        Err(_) => {
+            let id = doc.add_object(Dictionary::new());
+            doc.trailer.set("Info", id);
+            id
+        }
+    };
+
+    let info = doc
+        .get_dictionary_mut(info_id)
+        .map_err(|e| format!("Failed to read PDF info: {}", e))?;
+    let existing = |key: &[u8]| info.get(key).ok().and_then(info_text);
+    let mut merged = metadata.clone();
+    if merged.title.is_none() {
+        merged.title = existing(b"Title");
+    }
+    if merged.creators.is_empty() {
+        merged.creators = existing(b"Author").into_iter().collect();
+    }
+    if merged.description.is_none() {
+        merged.description = existing(b"Subject");
+    }
+    if merged.subjects.is_empty() {
+        merged.subjects = existing(b"Keywords").into_iter().collect();
+    }
+    let producer = existing(b"Producer");
+
+    if let Some(title) = &merged.title {
+        info.set("Title", pdf_text(title));
+    }
+    if !merged.creators.is_empty() {
+        info.set("Author", pdf_text(&merged.creators.join(", ")));
+    }
+    if let Some(description) = &merged.description {
+        info.set("Subject", pdf_text(description));
+    }
+    if !merged.subjects.is_empty() {
+        info.set("Keywords", pdf_text(&merged.subjects.join(", ")));
+    }
+
+    // The PDF was just written, so creation and modification share one timestamp
+    let now = chrono::Local::now();
+    let offset = now.format("%z").to_string();
+    let date = format!("D:{}{}'{}'", now.format("%Y%m%d%H%M%S"), &offset[..3], &offset[3..]);
+    info.set("CreationDate", Object::String(date.clone().into_bytes(), StringFormat::Literal));
+    info.set("ModDate", Object::String(date.into_bytes(), StringFormat::Literal));
+
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"Metadata".to_vec()));
+    dict.set("Subtype", Object::Name(b"XML".to_vec()));
+    let xmp_date = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+    let packet = xmp_packet(&merged, producer.as_deref(), &xmp_date, pdf_a);
+    // Left uncompressed so tools that only scan for the packet still find it
+    let xmp_id = doc.add_object(Stream::new(dict, packet.into_bytes()).with_compression(false));
+
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| format!("Failed to read PDF catalog: {}", e))?;
+    catalog.set("Metadata", xmp_id);
+    if let Some(language) = &merged.language {
+        catalog.set("Lang", pdf_text(language));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Cover Page
+// ============================================================================
+
+/// Whether a resource dictionary draws an image, looking one level into forms
+fn draws_image(doc: &lopdf::Document, resources: &Dictionary, depth: usize) -> bool {
+    let Ok(xobjects) = resources
+        .get(b"XObject")
+        .and_then(|o| doc.dereference(o))
+        .and_then(|(_, o)| o.as_dict())
+    else {
+        return false;
+    };
+    xobjects.iter().any(|(_, object)| {
+        let Ok((_, Object::Stream(stream))) = doc.dereference(object) else {
+            return false;
+        };
+        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(b"Image") => true,
+            Ok(b"Form") if depth > 0 => stream
+                .dict
+                .get(b"Resources")
+                .and_then(|o| doc.dereference(o))
+                .and_then(|(_, o)| o.as_dict())
+                .is_ok_and(|r| draws_image(doc, r, depth - 1)),
+            _ => false,
+        }
+    })
+}
+
+/// Calibre and the native renderer already start with the cover when the
+/// book has a cover page; a first page with an image counts as one
+fn starts_with_image(doc: &lopdf::Document, page_id: ObjectId) -> bool {
+    let (own, inherited) = doc.get_page_resources(page_id);
+    own.into_iter()
+        .chain(inherited.into_iter().filter_map(|id| doc.get_dictionary(id).ok()))
+        .any(|resources| draws_image(doc, resources, 1))
+}
+
+/// Page size in points, following inherited MediaBox entries
+pub fn page_size(doc: &lopdf::Document, page_id: ObjectId) -> (f32, f32) {
+    let mut node = doc.get_dictionary(page_id).ok();
+    while let Some(dict) = node {
+        if let Ok(media_box) = dict
+            .get(b"MediaBox")
+            .and_then(|o| doc.dereference(o))
+            .and_then(|(_, o)| o.as_array())
+        {
+            let values: Vec<f32> = media_box.iter().filter_map(|v| v.as_float().ok()).collect();
+            if let [x0, y0, x1, y1] = values[..] {
+                return ((x1 - x0).abs(), (y1 - y0).abs());
+            }
+        }
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok();
+    }
+    DEFAULT_PAGE_SIZE
+}
+
+/// Image XObject for the cover; JPEGs are embedded as-is
+fn cover_image(bytes: &[u8]) -> Result<(Stream, u32, u32), String> {
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Image".to_vec()));
+    dict.set("BitsPerComponent", 8);
+
+    let components = bytes.starts_with(&[0xFF, 0xD8]).then(|| jpeg_components(bytes)).flatten();
+    let (width, height, mut stream) = match components {
+        Some(count @ (1 | 3)) => {
+            let (width, height) = image::ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .map_err(|e| format!("Failed to read cover: {}", e))?
+                .into_dimensions()
+                .map_err(|e| format!("Failed to read cover: {}", e))?;
+            let color_space: &[u8] = if count == 1 { b"DeviceGray" } else { b"DeviceRGB" };
+            dict.set("ColorSpace", Object::Name(color_space.to_vec()));
+            dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+            (width, height, Stream::new(dict, bytes.to_vec()).with_compression(false))
+        }
+        _ => {
+            let decoded = image::load_from_memory(bytes).map_err(|e| format!("Failed to decode cover: {}", e))?;
+            // Flatten transparency onto a white page
+            let rgba = decoded.to_rgba8();
+            let mut rgb = Vec::with_capacity(rgba.len() / 4 * 3);
+            for pixel in rgba.pixels() {
+                let alpha = pixel[3] as u32;
+                for channel in &pixel.0[..3] {
+                    rgb.push(((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8);
+                }
+            }
+            dict.set("ColorSpace", Object::Name(b"DeviceRGB".to_vec()));
+            let mut stream = Stream::new(dict, rgb);
+            stream
+                .compress()
+                .map_err(|e| format!("Failed to compress cover: {}", e))?;
+            (decoded.width(), decoded.height(), stream)
+        }
+    };
+    stream.dict.set("Width", width as i64);
+    stream.dict.set("Height", height as i64);
+    Ok((stream, width, height))
+}
+
+/// Insert a page showing the cover, scaled to fit and centred, before the first page
+fn prepend_cover(doc: &mut lopdf::Document, cover: &[u8]) -> Result<(), String> {
+    let Some(first_page) = doc.get_pages().values().next().copied() else {
+        return Ok(());
+    };
+    if starts_with_image(doc, first_page) {
+        return Ok(());
+    }
+
+    let (page_width, page_height) = page_size(doc, first_page);
+    let (image, width, height) = cover_image(cover)?;
+    let scale = (page_width / width as f32).min(page_height / height as f32);
+    let (draw_width, draw_height) = (width as f32 * scale, height as f32 * scale);
+    let content = format!(
+        "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Cover Do Q",
+        draw_width,
+        draw_height,
+        (page_width - draw_width) / 2.0,
+        (page_height - draw_height) / 2.0
+    );
+
+    let image_id = doc.add_object(image);
+    let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+    let pages_id = doc
+        .catalog()
+        .and_then(|c| c.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to read PDF page tree: {}", e))?;
+
+    let mut xobjects = Dictionary::new();
+    xobjects.set("Cover", image_id);
+    let mut resources = Dictionary::new();
+    resources.set("XObject", xobjects);
+    let mut page = Dictionary::new();
+    page.set("Type", Object::Name(b"Page".to_vec()));
+    page.set("Parent", pages_id);
+    page.set(
+        "MediaBox",
+        vec![0.into(), 0.into(), Object::Real(page_width), Object::Real(page_height)],
+    );
+    page.set("Resources", resources);
+    page.set("Contents", content_id);
+    let page_id = doc.add_object(page);
+
+    // Page trees may nest, but the root accepts pages next to intermediate nodes
+    let pages = doc
+        .get_dictionary_mut(pages_id)
+        .map_err(|e| format!("Failed to read PDF page tree: {}", e))?;
+    let count = pages.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+    pages.set("Count", count + 1);
+    let kids = pages
+        .get_mut(b"Kids")
+        .and_then(Object::as_array_mut)
+        .map_err(|e| format!("Failed to read PDF page tree: {}", e))?;
+    kids.insert(0, Object::Reference(page_id));
+    Ok(())
+}
+
+// ============================================================================
+// Images
+// ============================================================================
+
+/// Target resolution and JPEG quality of a compression level
+struct ImageQuality {
+    dpi: f32,
+    jpeg_quality: u8,
+}
+
+fn image_quality(level: &str) -> Option<ImageQuality> {
+    match level {
+        "low" => Some(ImageQuality { dpi: 300.0, jpeg_quality: 90 }),
+        "medium" => Some(ImageQuality { dpi: 200.0, jpeg_quality: 80 }),
+        "high" => Some(ImageQuality { dpi: 150.0, jpeg_quality: 65 }),
+        _ => None,
+    }
+}
+
+/// Matrix product `m × ctm`, as done by the `cm` operator
+pub fn concat(m: [f32; 6], ctm: [f32; 6]) -> [f32; 6] {
+    [
+        m[0] * ctm[0] + m[1] * ctm[2],
+        m[0] * ctm[1] + m[1] * ctm[3],
+        m[2] * ctm[0] + m[3] * ctm[2],
+        m[2] * ctm[1] + m[3] * ctm[3],
+        m[4] * ctm[0] + m[5] * ctm[2] + ctm[4],
+        m[4] * ctm[1] + m[5] * ctm[3] + ctm[5],
+    ]
+}
+
+/// Largest size in points each image is drawn at, from the page content streams.
+/// Images only drawn inside forms are not listed and keep their resolution.
+fn image_display_sizes(doc: &lopdf::Document) -> HashMap<ObjectId, (f32, f32)> {
+    let mut sizes: HashMap<ObjectId, (f32, f32)> = HashMap::new();
+    for page_id in doc.get_pages().into_values() {
+        let (own, inherited) = doc.get_page_resources(page_id);
+        let mut names: HashMap<Vec<u8>, ObjectId> = HashMap::new();
+        for resources in own
+            .into_iter()
+            .chain(inherited.iter().filter_map(|id| doc.get_dictionary(*id).ok()))
+        {
+            let Ok(xobjects) = resources
+                .get(b"XObject")
+                .and_then(|o| doc.dereference(o))
+                .and_then(|(_, o)| o.as_dict())
+            else {
+                continue;
+            };
+            for (name, object) in xobjects.iter() {
+                if let Object::Reference(id) = object {
+                    names.entry(name.clone()).or_insert(*id);
+                }
+            }
+        }
+
+        let Ok(content) = doc.get_and_decode_page_content(page_id) else {
+            continue;
+        };
+        let mut ctm = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let mut saved = Vec::new();
+        for operation in content.operations {
+            match operation.operator.as_str() {
+                "q" => saved.push(ctm),
+                "Q" => ctm = saved.pop().unwrap_or(ctm),
+                "cm" => {
+                    let m: Vec<f32> = operation.operands.iter().filter_map(|o| o.as_float().ok()).collect();
+                    if let [a, b, c, d, e, f] = m[..] {
+                        ctm = concat([a, b, c, d, e, f], ctm);
+                    }
+                }
+                "Do" => {
+                    let Some(id) = operation
+                        .operands
+                        .first()
+                        .and_then(|o| o.as_name().ok())
+                        .and_then(|name| names.get(name))
+                    else {
+                        continue;
+                    };
+                    // The image fills the unit square, so the matrix gives its size
+                    let (width, height) = (ctm[0].hypot(ctm[1]), ctm[2].hypot(ctm[3]));
+                    let size = sizes.entry(*id).or_insert((0.0, 0.0));
+                    *size = (size.0.max(width), size.1.max(height));
+                }
+                _ => {}
+            }
+        }
+    }
+    sizes
+}
+
+/// Downsample an image to the target resolution for its displayed size and
+/// re-encode JPEGs at the level's quality. None when nothing would be gained
+/// or the image uses features this does not handle (masks, indexed colours...).
+fn recompress_image(stream: &Stream, display: (f32, f32), quality: &ImageQuality) -> Option<Stream> {
+    let dict = &stream.dict;
+    if dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok() != Some(8)
+        || dict.has(b"Decode")
+        || dict.has(b"DecodeParms")
+        || dict.has(b"ImageMask")
+    {
+        return None;
+    }
+    let gray = match dict.get(b"ColorSpace").and_then(Object::as_name).ok()? {
+        b"DeviceGray" => true,
+        b"DeviceRGB" => false,
+        _ => return None,
+    };
+    let jpeg = match dict.get(b"Filter") {
+        Ok(Object::Name(filter)) if filter == b"DCTDecode" => true,
+        Ok(Object::Name(filter)) if filter == b"FlateDecode" => false,
+        Err(_) => false,
+        _ => return None,
+    };
+    let width = dict.get(b"Width").and_then(Object::as_i64).ok()? as u32;
+    let height = dict.get(b"Height").and_then(Object::as_i64).ok()? as u32;
+
+    let pixels = if jpeg {
+        image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg).ok()?
+    } else {
+        let data = if dict.has(b"Filter") {
+            // lopdf refuses to inflate image streams, so present it as plain data
+            let mut plain = stream.clone();
+            plain.dict.remove(b"Subtype");
+            plain.decompressed_content().ok()?
+        } else {
+            stream.content.clone()
+        };
+        if gray {
+            DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, data)?)
+        } else {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, data)?)
+        }
+    };
+    if (pixels.width(), pixels.height()) != (width, height) || pixels.color().has_alpha() {
+        return None;
+    }
+
+    // Pixels needed to print the displayed size at the target resolution
+    let scale = (display.0 / 72.0 * quality.dpi / width as f32)
+        .max(display.1 / 72.0 * quality.dpi / height as f32)
+        .min(1.0);
+    if !jpeg && scale > 0.9 {
+        return None;
+    }
+    let pixels = if scale > 0.9 {
+        pixels
+    } else {
+        let new_width = ((width as f32 * scale).round() as u32).max(1);
+        let new_height = ((height as f32 * scale).round() as u32).max(1);
+        pixels.resize_exact(new_width, new_height, FilterType::Triangle)
+    };
+
+    let mut new_dict = dict.clone();
+    new_dict.set("Width", pixels.width() as i64);
+    new_dict.set("Height", pixels.height() as i64);
+    new_dict.remove(b"Filter");
+    let recompressed = if jpeg {
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, quality.jpeg_quality)
+            .encode_image(&pixels)
+            .ok()?;
+        new_dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+        Stream::new(new_dict, encoded)
+    } else {
+        let mut flate = Stream::new(new_dict, pixels.into_bytes());
+        flate.compress().ok()?;
+        flate
+    };
+    (recompressed.content.len() < stream.content.len()).then_some(recompressed)
+}
+
+/// Recompress every image drawn on a page; returns how many were replaced
+fn recompress_images(doc: &mut lopdf::Document, quality: &ImageQuality) -> usize {
+    let mut replaced = 0;
+    for (id, display) in image_display_sizes(doc) {
+        let Some(Object::Stream(stream)) = doc.objects.get(&id) else {
+            continue;
+        };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Image".as_slice()) {
+            continue;
+        }
+        if let Some(recompressed) = recompress_image(stream, display, quality) {
+            doc.objects.insert(id, Object::Stream(recompressed));
+            replaced += 1;
+        }
+    }
+    replaced
+}
+
+// ============================================================================
+// Streams
+// ============================================================================
+
+/// Point references to identical streams (fonts and images embedded once per
+/// chapter) at a single copy; returns how many copies became unused
+fn dedupe_streams(doc: &mut lopdf::Document) -> usize {
+    let mut by_hash: HashMap<u64, Vec<ObjectId>> = HashMap::new();
+    let mut duplicates: HashMap<ObjectId, ObjectId> = HashMap::new();
+    for (id, object) in &doc.objects {
+        let Object::Stream(stream) = object else {
+            continue;
+        };
+        let dict = format!("{:?}", stream.dict);
+        let mut hasher = DefaultHasher::new();
+        dict.hash(&mut hasher);
+        stream.content.hash(&mut hasher);
+
+        let candidates = by_hash.entry(hasher.finish()).or_default();
+        let original = candidates.iter().find(|other| {
+            matches!(doc.objects.get(other), Some(Object::Stream(o)) if o.content == stream.content && format!("{:?}", o.dict) == dict)
+        });
+        match original {
+            Some(original) => {
+                duplicates.insert(*id, *original);
+            }
+            None => candidates.push(*id),
+        }
+    }
+
+    if !duplicates.is_empty() {
+        for object in doc.objects.values_mut() {
+            redirect(object, &duplicates);
+        }
+    }
+    duplicates.len()
+}
+
+fn redirect(object: &mut Object, targets: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(target) = targets.get(id) {
+                *id = *target;
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| redirect(item, targets)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| redirect(value, targets)),
+        Object::Stream(stream) => stream.dict.iter_mut().for_each(|(_, value)| redirect(value, targets)),
+        _ => {}
+    }
+}
+
+/// Flate-compress the streams the converter left uncompressed, except the XMP packet
+fn compress_streams(doc: &mut lopdf::Document) {
+    for object in doc.objects.values_mut() {
+        if let Object::Stream(stream) = object {
+            if stream.dict.get(b"Type").and_then(Object::as_name).ok() != Some(b"Metadata".as_slice()) {
+                let _ = stream.compress();
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Booklet Imposition
+// ============================================================================
+
+/// Page pairs for each sheet side of a saddle-stitched booklet of `count` pages
+/// (padded to a multiple of four); None is a blank page
+fn booklet_order(count: usize) -> Vec<(Option<usize>, Option<usize>)> {
+    let padded = count.div_ceil(4) * 4;
+    let page = |i: usize| (i < count).then_some(i);
+    (0..padded / 2)
+        .map(|side| {
+            let (outer, inner) = (padded - 1 - side, side);
+            // Sheet fronts carry the last page on the left, backs on the right
+            if side % 2 == 0 {
+                (page(outer), page(inner))
+            } else {
+                (page(inner), page(outer))
+            }
+        })
+        .collect()
+}
+
+/// Turn a page into a form XObject that a sheet can draw
+fn page_form(doc: &mut lopdf::Document, page_id: ObjectId) -> Result<ObjectId, String> {
+    let (width, height) = page_size(doc, page_id);
+    let content = doc
+        .get_page_content(page_id)
+        .map_err(|e| format!("Failed to read page content: {}", e))?;
+    let resources = match doc.get_page_resources(page_id) {
+        (Some(dict), _) => Object::Dictionary(dict.clone()),
+        (None, ids) => ids.first().map_or(Object::Dictionary(Dictionary::new()), |id| Object::Reference(*id)),
+    };
+
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Form".to_vec()));
+    dict.set("BBox", vec![0.into(), 0.into(), width.into(), height.into()]);
+    dict.set("Resources", resources);
+    Ok(doc.add_object(Stream::new(dict, content)))
+}
+
+/// Impose the pages two-up on sheets twice as wide, in booklet order. Bookmarks and
+/// links point at the original pages, so they are dropped.
+fn impose_booklet(doc: &mut lopdf::Document) -> Result<(), String> {
+    let pages: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+    let Some(first) = pages.first() else {
+        return Ok(());
+    };
+    let (width, height) = page_size(doc, *first);
+    let forms = pages
+        .iter()
+        .map(|page_id| page_form(doc, *page_id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let pages_id = doc
+        .catalog()
+        .and_then(|c| c.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Failed to read PDF pages: {}", e))?;
+
+    let mut sheets = Vec::new();
+    for (left, right) in booklet_order(forms.len()) {
+        let mut xobjects = Dictionary::new();
+        let mut content = String::new();
+        for (slot, page, x) in [("L", left, 0.0), ("R", right, width)] {
+            if let Some(page) = page {
+                xobjects.set(slot, forms[page]);
+                content.push_str(&format!("q 1 0 0 1 {} 0 cm /{} Do Q\n", x, slot));
+            }
+        }
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+
+        let mut resources = Dictionary::new();
+        resources.set("XObject", xobjects);
+        let mut sheet = Dictionary::new();
+        sheet.set("Type", Object::Name(b"Page".to_vec()));
+        sheet.set("Parent", pages_id);
+        sheet.set("MediaBox", vec![0.into(), 0.into(), (width * 2.0).into(), height.into()]);
+        sheet.set("Resources", resources);
+        sheet.set("Contents", content_id);
+        sheets.push(Object::Reference(doc.add_object(sheet)));
+    }
+
+    let count = sheets.len() as i64;
+    let root = doc
+        .get_dictionary_mut(pages_id)
+        .map_err(|e| format!("Failed to read PDF pages: {}", e))?;
+    root.set("Kids", sheets);
+    root.set("Count", count);
+    root.remove(b"MediaBox");
+
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| format!("Failed to read PDF catalog: {}", e))?;
+    for key in [b"Outlines".as_slice(), b"Dests", b"OpenAction", b"PageLabels"] {
+        catalog.remove(key);
+    }
+    if catalog.get(b"PageMode").and_then(Object::as_name).is_ok_and(|m| m == b"UseOutlines") {
+        catalog.remove(b"PageMode");
+    }
+    Ok(())
+}
+
+// ============================================================================
+// PDF/A and Linearisation
+// ============================================================================
+
+/// Minimal ICC v2 display profile for sRGB (D50-adapted primaries, gamma 2.2)
+fn srgb_icc_profile() -> Vec<u8> {
+    fn s15_fixed16(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for value in [x, y, z] {
+            tag.extend(s15_fixed16(value));
+        }
+        tag
+    }
+
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    let name = format!("{}\0", SRGB);
+    desc.extend((name.len() as u32).to_be_bytes());
+    desc.extend(name.as_bytes());
+    desc.extend([0u8; 8]); // No Unicode description
+    desc.extend([0u8; 70]); // No ScriptCode description
+    let copyright = b"text\0\0\0\0No copyright, use freely\0".to_vec();
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend(1u32.to_be_bytes());
+    curve.extend(0x0233u16.to_be_bytes()); // Gamma 2.2 as u8Fixed8
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", desc),
+        (b"cprt", copyright),
+        (b"wtpt", xyz(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut header = vec![0u8; 4]; // Size, filled in below
+    header.extend([0u8; 4]); // Preferred CMM
+    header.extend([2, 0x10, 0, 0]); // Version 2.1
+    header.extend(b"mntrRGB XYZ ");
+    for part in [2000u16, 1, 1, 0, 0, 0] {
+        header.extend(part.to_be_bytes());
+    }
+    header.extend(b"acsp");
+    header.extend([0u8; 28]); // Platform, flags, device and rendering intent
+    header.extend(xyz(0.9642, 1.0, 0.8249)[8..].to_vec()); // D50 illuminant
+    header.extend([0u8; 48]); // Creator, profile id and reserved bytes
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let data_start = header.len() + 4 + tags.len() * 12;
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend(((data_start + data.len()) as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+    }
+
+    let mut profile = [header, table, data].concat();
+    let size = (profile.len() as u32).to_be_bytes();
+    profile[..4].copy_from_slice(&size);
+    profile
+}
+
+/// Base name of the first font whose program is not embedded
+fn unembedded_font(doc: &lopdf::Document) -> Option<String> {
+    doc.objects.values().filter_map(|o| o.as_dict().ok()).find_map(|font| {
+        if font.get(b"Type").and_then(Object::as_name).ok() != Some(b"Font".as_slice()) {
+            return None;
+        }
+        // Composite and Type 3 fonts carry their glyphs elsewhere
+        if matches!(font.get(b"Subtype").and_then(Object::as_name).ok()?, b"Type0" | b"Type3") {
+            return None;
+        }
+        let embedded = font
+            .get(b"FontDescriptor")
+            .and_then(|o| doc.dereference(o))
+            .and_then(|(_, o)| o.as_dict())
+            .is_ok_and(|d| d.has(b"FontFile") || d.has(b"FontFile2") || d.has(b"FontFile3"));
+        let name = font.get(b"BaseFont").and_then(Object::as_name).unwrap_or(b"(unnamed)");
+        (!embedded).then(|| String::from_utf8_lossy(name).to_string())
+    })
+}
+
+/// Add what PDF/A-2b requires beyond the XMP identification: an sRGB output
+/// intent and a file identifier. Fonts must already be embedded.
+fn make_pdf_a(doc: &mut lopdf::Document) -> Result<(), String> {
+    if doc.is_encrypted() {
+        return Err("PDF/A does not allow encrypted PDFs".to_string());
+    }
+    if let Some(font) = unembedded_font(doc) {
+        return Err(format!(
+            "PDF/A needs embedded fonts, but {} is not embedded (install a TrueType font or use another converter)",
+            font
+        ));
+    }
+
+    let mut profile_dict = Dictionary::new();
+    profile_dict.set("N", 3);
+    let mut profile = Stream::new(profile_dict, srgb_icc_profile());
+    let _ = profile.compress();
+    let profile_id = doc.add_object(profile);
+
+    let mut intent = Dictionary::new();
+    intent.set("Type", Object::Name(b"OutputIntent".to_vec()));
+    intent.set("S", Object::Name(b"GTS_PDFA1".to_vec()));
+    intent.set("OutputConditionIdentifier", Object::string_literal(SRGB));
+    intent.set("Info", Object::string_literal(SRGB));
+    intent.set("DestOutputProfile", profile_id);
+    let intent_id = doc.add_object(intent);
+    doc.catalog_mut()
+        .map_err(|e| format!("Failed to read PDF catalog: {}", e))?
+        .set("OutputIntents", vec![Object::Reference(intent_id)]);
+
+    let mut hasher = DefaultHasher::new();
+    doc.objects.len().hash(&mut hasher);
+    std::time::SystemTime::now().hash(&mut hasher);
+    let first = hasher.finish();
+    first.hash(&mut hasher);
+    let id = [first.to_be_bytes(), hasher.finish().to_be_bytes()].concat();
+    doc.trailer.set(
+        "ID",
+        vec![
+            Object::String(id.clone(), StringFormat::Hexadecimal),
+            Object::String(id, StringFormat::Hexadecimal),
+        ],
+    );
+    Ok(())
+}
+
+/// qpdf binary used for linearisation, if installed
+pub fn qpdf_path() -> Option<PathBuf> {
+    find_in_path("qpdf")
+}
+
+/// Rewrite the PDF for fast web view; lopdf cannot write the hint tables itself
+fn linearize(pdf_path: &Path) -> Result<(), String> {
+    let qpdf = qpdf_path().ok_or("Fast web view needs qpdf, which was not found")?;
+    let linear = pdf_path.with_extension("linear.pdf");
+    let output = Command::new(qpdf)
+        .arg("--linearize")
+        .arg(pdf_path)
+        .arg(&linear)
+        .output()
+        .map_err(|e| format!("Failed to run qpdf: {}", e))?;
+
+    // Exit code 3 means the file was written with warnings
+    if !matches!(output.status.code(), Some(0 | 3)) {
+        let _ = std::fs::remove_file(&linear);
+        return Err(format!(
+            "qpdf failed to linearise the PDF: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    std::fs::rename(&linear, pdf_path).map_err(|e| format!("Failed to move linearised PDF into place: {}", e))
+}
+
+// ============================================================================
+// Public API
+// ============================================================================
+
+/// What post-processing did to a PDF
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostprocessReport {
+    pub size_before: u64, // Bytes
+    pub size_after: u64,
+    pub warnings: Vec<String>, // Steps skipped without failing, such as an undecodable cover
+}
+
+/// Post-process a PDF in place: EPUB metadata and cover when the source was an
+/// EPUB, the highlights when given, then the compression level, PDF/A and
+/// linearisation settings.
+pub fn postprocess_pdf(
+    pdf_path: &Path,
+    epub_path: Option<&Path>,
+    settings: &EpubToPdfSettings,
+    annotations: Option<&PdfAnnotations>,
+) -> Result<PostprocessReport, String> {
+    let file_size = |path: &Path| {
+        std::fs::metadata(path)
+            .map(|m| m.len())
+            .map_err(|e| format!("Failed to read PDF: {}", e))
+    };
+    let before = file_size(pdf_path)?;
+    let profile = layout_profiles::selected_profile(settings)?;
+    let mut doc = lopdf::Document::load(pdf_path).map_err(|e| format!("Failed to read PDF: {}", e))?;
+
+    let mut warnings = Vec::new();
+    let mut metadata = EpubMetadata::default();
+    if let Some(epub_path) = epub_path {
+        let mut package = EpubPackage::open(epub_path)?;
+        if let Some(href) = package.cover_href() {
+            // A missing or undecodable cover should not cost the whole conversion
+            if let Err(e) = package.read_resource(&href).and_then(|cover| prepend_cover(&mut doc, &cover)) {
+                warnings.push(format!("Skipped the cover: {}", e));
+            }
+        }
+        metadata = package.metadata;
+    }
+
+    if let Some(quality) = image_quality(&settings.compression_level) {
+        recompress_images(&mut doc, &quality);
+    }
+    dedupe_streams(&mut doc);
+    // Highlights are searched on the original pages; imposed sheets drop annotations,
+    // so booklets get them painted in
+    let booklet = profile.as_ref().is_some_and(|p| p.imposition == Imposition::Booklet);
+    if let Some(annotations) = annotations.filter(|a| !a.highlights.is_empty()) {
+        let found = annotate_pdf(&mut doc, annotations, settings, booklet)?;
+        let total = annotations.highlights.len();
+        if found < total {
+            warnings.push(format!("{} of {} highlights not found in the PDF text", total - found, total));
+        }
+    }
+    // Imposed last so images are still measured on the original pages
+    if booklet {
+        impose_booklet(&mut doc)?;
+    }
+    doc.prune_objects();
+    if settings.pdf_a {
+        make_pdf_a(&mut doc)?;
+    }
+    write_metadata(&mut doc, &metadata, settings.pdf_a)?;
+    if settings.compression_level != "none" {
+        compress_streams(&mut doc);
+    }
+    doc.save(pdf_path)
+        .map_err(|e| format!("Failed to save PDF: {}", e))?;
+
+    if settings.linearize {
+        linearize(pdf_path)?;
+    }
+    Ok(PostprocessReport {
+        size_before: before,
+        size_after: file_size(pdf_path)?,
+        warnings,
+    })
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::epub_package::write_test_epub;
+
+    const PACKAGE: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <dc:title>Dune &amp; Messiah</dc:title>
+  <dc:creator>Frank Herbert</dc:creator>
+  <dc:language>en</dc:language>
+  <dc:identifier id="id">urn:isbn:9780441013593</dc:identifier>
+  <dc:subject>Science Fiction</dc:subject>
+  <dc:subject>Classics</dc:subject>
+</metadata>
+<manifest>
+  <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+  <item id="cover" href="cover.png" media-type="image/png" properties="cover-image"/>
+</manifest>
+<spine><itemref idref="c1"/></spine></package>"#;
+
+    /// The cover is left out of the archive when `cover` is None, though the manifest lists it
+    fn write_epub(path: &Path, cover: Option<(u32, u32)>) {
+        let mut files = vec![("OEBPS/c1.xhtml", b"<html><body><p>Text</p></body></html>".to_vec())];
+        if let Some((width, height)) = cover {
+            let mut png = Vec::new();
+            RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 128]))
+                .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            files.push(("OEBPS/cover.png", png));
+        }
+        write_test_epub(path, PACKAGE, &files);
+    }
+
+    /// One text page, the shape of a Pandoc PDF without a cover
+    fn write_pdf(path: &Path, width: i64, height: i64) {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), b"BT ET".to_vec()));
+        let mut page = Dictionary::new();
+        page.set("Type", Object::Name(b"Page".to_vec()));
+        page.set("Parent", pages_id);
+        page.set("Contents", content_id);
+        let page_id = doc.add_object(page);
+
+        let mut pages = Dictionary::new();
+        pages.set("Type", Object::Name(b"Pages".to_vec()));
+        pages.set("Kids", vec![Object::Reference(page_id)]);
+        pages.set("Count", 1);
+        pages.set("MediaBox", vec![0.into(), 0.into(), width.into(), height.into()]);
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set("Pages", pages_id);
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path).unwrap();
+    }
+
+    fn settings(compression_level: &str, pdf_a: bool) -> EpubToPdfSettings {
+        EpubToPdfSettings {
+            compression_level: compression_level.to_string(),
+            pdf_a,
+            ..EpubToPdfSettings::default()
+        }
+    }
+
+    fn xmp_of(doc: &lopdf::Document) -> String {
+        let xmp_id = doc.catalog().unwrap().get(b"Metadata").unwrap().as_reference().unwrap();
+        String::from_utf8(doc.get_object(xmp_id).unwrap().as_stream().unwrap().content.clone()).unwrap()
+    }
+
+    fn cover_width(doc: &lopdf::Document) -> i64 {
+        let first_page = *doc.get_pages().values().next().unwrap();
+        let (resources, _) = doc.get_page_resources(first_page);
+        let cover = resources.unwrap().get(b"XObject").unwrap().as_dict().unwrap().get(b"Cover").unwrap();
+        let cover = doc.get_object(cover.as_reference().unwrap()).unwrap().as_stream().unwrap();
+        cover.dict.get(b"Width").unwrap().as_i64().unwrap()
+    }
+
+    #[test]
+    fn test_embed_epub_metadata() {
+        let dir = std::env::temp_dir().join(format!("stomy-postprocess-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let epub = dir.join("dune.epub");
+        let pdf = dir.join("dune.pdf");
+        write_epub(&epub, Some((20, 30)));
+        write_pdf(&pdf, 432, 648);
+
+        let report = postprocess_pdf(&pdf, Some(&epub), &settings("medium", false), None).unwrap();
+        assert!(report.size_before > 0 && report.size_after > report.size_before);
+        assert!(report.warnings.is_empty());
+        let doc = lopdf::Document::load(&pdf).unwrap();
+        let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = doc.get_dictionary(info_id).unwrap();
+        assert_eq!(info.get(b"Title").unwrap().as_str().unwrap(), pdf_text("Dune & Messiah").as_str().unwrap());
+        assert_eq!(info.get(b"Keywords").unwrap().as_str().unwrap(), pdf_text("Science Fiction, Classics").as_str().unwrap());
+        assert_eq!(info_text(info.get(b"Author").unwrap()).as_deref(), Some("Frank Herbert"));
+
+        let catalog = doc.catalog().unwrap();
+        assert_eq!(catalog.get(b"Lang").unwrap().as_str().unwrap(), pdf_text("en").as_str().unwrap());
+        let xmp = xmp_of(&doc);
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Dune &amp; Messiah</rdf:li>"));
+        assert!(xmp.contains("<dc:identifier>urn:isbn:9780441013593</dc:identifier>"));
+        assert!(!xmp.contains("pdfaid:part"));
+
+        // Cover first, at the size of the body pages, and only once
+        let pages: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+        assert_eq!(pages.len(), 2);
+        assert!(starts_with_image(&doc, pages[0]));
+        assert_eq!(page_size(&doc, pages[0]), (432.0, 648.0));
+        postprocess_pdf(&pdf, Some(&epub), &settings("medium", false), None).unwrap();
+        assert_eq!(lopdf::Document::load(&pdf).unwrap().get_pages().len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_cover_is_a_warning() {
+        let dir = std::env::temp_dir().join(format!("stomy-missing-cover-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let epub = dir.join("dune.epub");
+        let pdf = dir.join("dune.pdf");
+        write_epub(&epub, None);
+        write_pdf(&pdf, 432, 648);
+
+        let report = postprocess_pdf(&pdf, Some(&epub), &settings("medium", false), None).unwrap();
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("Skipped the cover: "));
+        // The metadata is still embedded
+        let doc = lopdf::Document::load(&pdf).unwrap();
+        assert_eq!(doc.get_pages().len(), 1);
+        assert!(doc.trailer.get(b"Info").is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recompress_images() {
+        let dir = std::env::temp_dir().join(format!("stomy-recompress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let epub = dir.join("dune.epub");
+        write_epub(&epub, Some((600, 900)));
+
+        // A 1x1.5 inch page needs 150x225 pixels at 150 dpi
+        let pdf = dir.join("high.pdf");
+        write_pdf(&pdf, 72, 108);
+        postprocess_pdf(&pdf, Some(&epub), &settings("high", false), None).unwrap();
+        assert_eq!(cover_width(&lopdf::Document::load(&pdf).unwrap()), 150);
+
+        let pdf = dir.join("none.pdf");
+        write_pdf(&pdf, 72, 108);
+        postprocess_pdf(&pdf, Some(&epub), &settings("none", false), None).unwrap();
+        assert_eq!(cover_width(&lopdf::Document::load(&pdf).unwrap()), 600);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dedupe_streams() {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let font = || Stream::new(Dictionary::new(), b"glyphs".to_vec());
+        let first = doc.add_object(font());
+        let second = doc.add_object(font());
+        let other = doc.add_object(Stream::new(Dictionary::new(), b"other".to_vec()));
+        let mut fonts = Dictionary::new();
+        fonts.set("F1", first);
+        fonts.set("F2", second);
+        fonts.set("F3", other);
+        let fonts_id = doc.add_object(fonts);
+
+        assert_eq!(dedupe_streams(&mut doc), 1);
+        let fonts = doc.get_dictionary(fonts_id).unwrap();
+        assert_eq!(fonts.get(b"F2").unwrap().as_reference().unwrap(), first);
+        assert_eq!(fonts.get(b"F3").unwrap().as_reference().unwrap(), other);
+    }
+
+    #[test]
+    fn test_pdf_a() {
+        let profile = srgb_icc_profile();
+        assert_eq!(&profile[36..40], b"acsp");
+        assert_eq!(u32::from_be_bytes(profile[..4].try_into().unwrap()) as usize, profile.len());
+
+        let dir = std::env::temp_dir().join(format!("stomy-pdfa-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pdf = dir.join("archive.pdf");
+        write_pdf(&pdf, 432, 648);
+        postprocess_pdf(&pdf, None, &settings("medium", true), None).unwrap();
+
+        let doc = lopdf::Document::load(&pdf).unwrap();
+        assert!(doc.catalog().unwrap().get(b"OutputIntents").is_ok());
+        assert_eq!(doc.trailer.get(b"ID").unwrap().as_array().unwrap().len(), 2);
+        let xmp = xmp_of(&doc);
+        assert!(xmp.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(xmp.contains("<pdfaid:conformance>B</pdfaid:conformance>"));
+
+        // Builtin fonts are not embedded, which PDF/A forbids
+        let mut doc = lopdf::Document::load(&pdf).unwrap();
+        let mut font = Dictionary::new();
+        font.set("Type", Object::Name(b"Font".to_vec()));
+        font.set("Subtype", Object::Name(b"Type1".to_vec()));
+        font.set("BaseFont", Object::Name(b"Times-Roman".to_vec()));
+        doc.add_object(font);
+        assert_eq!(unembedded_font(&doc).as_deref(), Some("Times-Roman"));
+        assert!(make_pdf_a(&mut doc).unwrap_err().contains("Times-Roman is not embedded"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_impose_booklet() {
+        assert_eq!(booklet_order(4), vec![(Some(3), Some(0)), (Some(1), Some(2))]);
+        assert_eq!(
+            booklet_order(6),
+            vec![(None, Some(0)), (Some(1), None), (Some(5), Some(2)), (Some(3), Some(4))]
+        );
+
+        let path = std::env::temp_dir().join(format!("stomy-booklet-{}.pdf", std::process::id()));
+        write_pdf(&path, 432, 648);
+        let mut doc = lopdf::Document::load(&path).unwrap();
+        impose_booklet(&mut doc).unwrap();
+        doc.prune_objects();
+
+        // One page becomes a sheet front and back, twice as wide
+        let sheets: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+        assert_eq!(sheets.len(), 2);
+        assert_eq!(page_size(&doc, sheets[0]), (864.0, 648.0));
+        assert_eq!(doc.get_page_content(sheets[0]).unwrap(), b"q 1 0 0 1 432 0 cm /R Do Q\n");
+        assert!(doc.get_page_content(sheets[1]).unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -6,6 +6,20 @@ export interface ConversionResult {
   success: boolean;
   outputPath?: string;
   error?: string;
+  sizeBefore?: number; // PDF size in bytes as the converter wrote it
+  sizeAfter?: number; // PDF size in bytes after post-processing
+  cached?: boolean; // Copied from the conversion cache instead of converted
+  diagnostic?: ConversionDiagnostic; // Error code and suggested fix of a failure
+  warnings?: string[]; // What went wrong without failing the conversion (fallback font, ...)
+}
+
+export interface ConversionDiagnostic {
+  // 'latex-engine-missing', 'latex-package-missing', 'font-missing', 'drm-protected', 'malformed-xhtml', 'disk-full', ...
+  code: string;
+  message: string;
+  suggestion: string;
+  detail?: string; // Log line the diagnosis is based on
+  logPath?: string; // Full converter output, read it with getConversionLog()
 }
 
 export interface EpubToPdfSettings {
@@ -22,6 +36,65 @@ export interface EpubToPdfSettings {
   };
   includeTableOfContents: boolean;
   preserveImages: boolean;
+  timeoutSeconds: number; // 0 = no limit
+  linearize: boolean; // Fast web view, needs qpdf
+  pdfA: boolean; // PDF/A-2b for archival
+  parallelJobs: number; // Conversions at once, 0 = one per CPU
+  maxLatexJobs: number; // LaTeX runs at once, 0 = no separate limit
+  cacheEnabled: boolean; // Reuse earlier conversions of the same file and settings
+  cacheMaxMb: number; // Cache size before the least recently used entries are evicted
+  layoutProfile: string; // Layout profile id, '' = page size and margins above
+  customCss: string; // Stylesheet path applied on top of the book's, '' = none
+  customFonts: string[]; // TrueType/OpenType font paths; the body font comes from them
+  pandocTemplate: string; // Pandoc LaTeX template path, '' = default
+  pandocPdfEngine: '' | PdfEngineName; // '' = best installed engine
+  sandboxConverters: boolean; // Run Calibre and Pandoc without network, in a private folder
+  requireNetworkIsolation: boolean; // Refuse to run them where the network cannot be cut off
+  maxCpuSeconds: number; // CPU time of a sandboxed converter, 0 = no limit
+  maxOutputMb: number; // Size of any file it writes, 0 = no limit
+  converterPaths?: ConverterPaths;
+  watchFolders?: WatchFolder[]; // Watched again whenever the plugin is enabled
+  outputTemplate: string; // e.g. '{author}/{series}/{series_index} - {title}.pdf'
+  collisionPolicy: 'overwrite' | 'skip' | 'suffix';
+}
+
+export interface WatchFolder {
+  sourceDir: string;
+  outputDir: string; // PDFs mirror the subfolders of sourceDir here
+  converter?: 'calibre' | 'pandoc' | 'native'; // Preferred installed one when absent
+  layoutProfile?: string; // Overrides the layoutProfile setting
+}
+
+export interface WatchedFile {
+  status: 'queued' | 'converted' | 'failed';
+  size: number;
+  modified: number; // Milliseconds since the epoch
+  outputPath: string;
+  error?: string;
+  updatedAt: string;
+}
+
+export interface WatchFolderStatus {
+  folder: WatchFolder;
+  files: Record<string, WatchedFile>; // Keyed by path relative to sourceDir
+  warning?: string; // Last error of the watcher or the state file
+}
+
+export interface OutputNaming {
+  outputDir: string;
+  template: string; // {title} {author} {authors} {series} {series_index} {publisher} {language} {year} {filename}
+  collision: 'overwrite' | 'skip' | 'suffix';
+}
+
+export interface ResolvedOutput {
+  sourcePath: string;
+  targetPath: string;
+  action: 'create' | 'overwrite' | 'skip';
+}
+
+export interface ConverterPaths {
+  calibre?: string; // Path to ebook-convert
+  pandoc?: string;
 }
 
 export interface ConversionJob {
@@ -29,24 +102,154 @@ export interface ConversionJob {
   bookId?: string;
   sourcePath: string;
   targetPath: string;
-  status: 'pending' | 'converting' | 'completed' | 'failed';
-  progress: number;
+  converter: 'calibre' | 'pandoc' | 'native';
+  status: 'pending' | 'converting' | 'completed' | 'failed' | 'cancelled';
+  progress: number; // 0-100
+  outputPath?: string;
   error?: string;
-  startedAt: string;
+  diagnostic?: ConversionDiagnostic;
+  warnings?: string[]; // From the conversion result of a completed job
+  queuedAt: string;
+  startedAt?: string;
   completedAt?: string;
 }
 
+export type ConversionEvent =
+  | 'epub-conversion-queued'
+  | 'epub-conversion-started'
+  | 'epub-conversion-progress'
+  | 'epub-conversion-completed'
+  | 'epub-conversion-failed';
+
+export interface ValidationIssue {
+  code: string; // 'drm-protected', 'missing-resource', ...
+  message: string;
+  path?: string; // Archive path the issue is about
+}
+
+export interface EpubValidation {
+  valid: boolean; // No errors; warnings do not prevent conversion
+  drmProtected: boolean;
+  errors: ValidationIssue[];
+  warnings: ValidationIssue[];
+}
+
 export interface BatchConversionResult {
   success: boolean;
   converted: number;
   failed: number;
   errors: Array<{ file: string; error: string }>;
   outputPaths: string[];
+  skipped?: number; // Existing files left alone by the 'skip' policy
+  resolved?: ResolvedOutput[]; // Target chosen for every job, in input order
+  jobs?: BatchJobResult[]; // Outcome of every job, in input order
+}
+
+export interface BatchJobResult {
+  sourcePath: string;
+  targetPath: string;
+  status: 'converted' | 'failed' | 'skipped';
+  error?: string;
+  diagnostic?: ConversionDiagnostic;
+  durationMs: number;
+}
+
+export type EbookFormat =
+  | 'epub'
+  | 'pdf'
+  | 'mobi'
+  | 'azw3'
+  | 'kepub'
+  | 'txt'
+  | 'html'
+  | 'docx'
+  | 'cbz';
+
+export interface ConversionPath {
+  from: EbookFormat;
+  to: EbookFormat;
+  converters: Array<'calibre' | 'pandoc' | 'native'>; // Preferred first
 }
 
 export interface ConverterInfo {
   available: boolean;
-  converter: 'calibre' | 'pandoc' | 'none';
+  converter: 'calibre' | 'pandoc' | 'native' | 'none';
   version?: string;
   path?: string;
+  conversions?: ConversionPath[];
+  pdfEngines?: PdfEngine[]; // Pandoc only: installed PDF engines, best first
+  error?: string; // Why the converter cannot be used
+}
+
+export interface SandboxStatus {
+  networkIsolation: 'namespaces' | 'seccomp' | 'sandbox-exec' | 'none';
+  resourceLimits: boolean; // CPU time and file size limits (Unix only)
+  warning?: string; // What is not protected on this system
+}
+
+export type PdfEngineName = 'xelatex' | 'lualatex' | 'tectonic' | 'pdflatex' | 'weasyprint' | 'wkhtmltopdf';
+
+export interface PdfEngine {
+  name: PdfEngineName;
+  path: string;
+}
+
+export interface SpineChapter {
+  index: number;
+  idref: string;
+  href: string;
+  title?: string; // First table of contents entry pointing into the document
+  linear: boolean;
+}
+
+export interface TocChapter {
+  index: number;
+  title: string;
+  href: string;
+  level: number; // 0 for top-level entries
+  spineIndex?: number; // Document the entry starts in, absent outside the reading order
+}
+
+export interface ChapterList {
+  spine: SpineChapter[];
+  toc: TocChapter[];
+}
+
+export interface ChapterSelection {
+  spine?: string[]; // Spine idrefs
+  toc?: number[]; // Table of contents indexes; each covers its sub-entries
+}
+
+export interface Highlight {
+  text: string; // Highlighted passage, searched in the PDF text
+  note?: string; // Shown in the annotation popup
+  location?: string; // Chapter or reader position, shown in the notes appendix
+}
+
+export interface PdfAnnotations {
+  highlights: Highlight[];
+  notesAppendix?: boolean; // Append the highlights and notes after the last page
+}
+
+export interface CacheStats {
+  entries: number;
+  bytes: number;
+}
+
+export interface LayoutProfile {
+  id: string;
+  name: string;
+  width: number; // Points
+  height: number;
+  margins: {
+    top: number;
+    bottom: number;
+    left: number; // Inner margin
+    right: number; // Outer margin
+  };
+  gutter?: number; // Extra inner margin for the binding
+  fontSize: number;
+  hyphenation?: boolean;
+  imposition?: 'none' | 'booklet';
+  builtin?: boolean;
 }
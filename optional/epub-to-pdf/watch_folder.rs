@@ -0,0 +1,509 @@
+/// Watch Folders
+/// Converts the EPUBs dropped into watched folders. File events are debounced,
+/// new or modified books go through the job queue with the folder's layout
+/// profile, PDFs mirror the folder structure in the output folder, and a state
+/// file there keeps a restart from converting everything again.
+///
+/// Instructions:
+/// 1. Copy this file to src-tauri/src/watch_folder.rs (needs epub_converter.rs)
+/// 2. Add `mod watch_folder;` to src-tauri/src/main.rs
+/// 3. Add `notify-debouncer-mini = "0.6"` to src-tauri/Cargo.toml
+/// 4. Register the commands in the invoke_handler
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, SystemTime};
+use tauri::{command, AppHandle};
+
+use super::ebook_formats::EbookFormat;
+use super::epub_converter::{self, ConversionJob, EpubToPdfSettings, JobHook, QueuedConversion};
+
+/// Quiet time after the last write before a book is picked up
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// Kept in the output folder, so each mirror has its own record
+const STATE_FILE: &str = ".stomy-watch.json";
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchFolder {
+    pub source_dir: String,
+    pub output_dir: String, // PDFs mirror the subfolders of source_dir here
+    #[serde(default)]
+    pub converter: Option<String>, // None for the preferred installed one
+    #[serde(default)]
+    pub layout_profile: Option<String>, // Overrides the profile of the settings
+}
+
+/// Last known state of a book, keyed by its path relative to the source folder
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedFile {
+    pub status: String, // "queued", "converted" or "failed"
+    pub size: u64,
+    pub modified: u64, // Milliseconds since the epoch
+    pub output_path: String,
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+struct WatchState {
+    files: BTreeMap<String, WatchedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchFolderStatus {
+    pub folder: WatchFolder,
+    pub files: BTreeMap<String, WatchedFile>,
+    #[serde(default)]
+    pub warning: Option<String>, // Last error of the watcher or the state file
+}
+
+/// What a running watch shares with its event handler and the queue hooks
+struct WatchContext {
+    folder: WatchFolder,
+    source: PathBuf, // Canonical, to match event paths
+    settings: EpubToPdfSettings,
+    app: Option<AppHandle>,
+    state: Mutex<WatchState>,
+    converting: Mutex<BTreeSet<String>>, // Keys with a job in the queue; locked after `state`
+    warning: Mutex<Option<String>>,
+}
+
+struct ActiveWatch {
+    context: Arc<WatchContext>,
+    _debouncer: Debouncer<RecommendedWatcher>, // Watching stops when dropped
+}
+
+fn watches() -> MutexGuard<'static, Vec<ActiveWatch>> {
+    static WATCHES: OnceLock<Mutex<Vec<ActiveWatch>>> = OnceLock::new();
+    WATCHES
+        .get_or_init(|| Mutex::new(Vec::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+// ============================================================================
+// State File
+// ============================================================================
+
+fn load_state(output_dir: &Path) -> WatchState {
+    let mut state: WatchState = std::fs::read_to_string(output_dir.join(STATE_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    // Queued jobs did not survive the restart, so those books are converted again
+    state.files.retain(|_, file| file.status != "queued");
+    state
+}
+
+/// Written to a temporary file first so a crash cannot leave half a state file
+fn save_state(output_dir: &Path, state: &WatchState) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(state).map_err(|e| format!("Failed to save watch state: {}", e))?;
+    let temp = output_dir.join(format!("{}.tmp", STATE_FILE));
+    std::fs::write(&temp, json)
+        .and_then(|_| std::fs::rename(&temp, output_dir.join(STATE_FILE)))
+        .map_err(|e| format!("Failed to save watch state: {}", e))
+}
+
+// ============================================================================
+// Scanning
+// ============================================================================
+
+/// Size and modification time, which tell a modified book from a converted one
+type Stamp = (u64, u64);
+
+fn file_stamp(path: &Path) -> Option<Stamp> {
+    let metadata = path.metadata().ok()?;
+    let modified = metadata.modified().ok()?.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_millis() as u64))
+}
+
+/// EPUB or KEPUB, skipping hidden files such as the converters' partial outputs
+fn is_book(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    !hidden && matches!(EbookFormat::from_path(path), Some(EbookFormat::Epub | EbookFormat::Kepub))
+}
+
+/// Every book under `dir`, hidden folders excluded
+fn scan(dir: &Path, books: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            scan(&path, books);
+        } else if is_book(&path) {
+            books.push(path);
+        }
+    }
+}
+
+/// Source path relative to the watched folder, with `/` separators for the state file
+fn relative_key(source: &Path, book: &Path) -> Option<String> {
+    let relative = book.strip_prefix(source).ok()?;
+    let parts: Vec<String> = relative.iter().map(|p| p.to_string_lossy().to_string()).collect();
+    Some(parts.join("/"))
+}
+
+/// Output path mirroring the book's place in the watched folder
+fn mirror_target(output_dir: &Path, key: &str) -> PathBuf {
+    let mut target = output_dir.to_path_buf();
+    target.extend(key.split('/'));
+    target.with_extension("pdf")
+}
+
+// ============================================================================
+// Watching
+// ============================================================================
+
+impl WatchContext {
+    /// Queue the books among `paths` (files or folders) that are new or modified
+    fn handle_paths(self: &Arc<Self>, paths: Vec<PathBuf>) {
+        let mut books = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                scan(&path, &mut books);
+            } else if is_book(&path) {
+                books.push(path);
+            }
+        }
+        books.sort();
+        books.dedup();
+
+        let output_dir = Path::new(&self.folder.output_dir);
+        let mut ready = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let mut converting = self.converting.lock().unwrap_or_else(|e| e.into_inner());
+            let mut changed = false;
+            for book in books {
+                let (Some(key), Some((size, modified))) = (relative_key(&self.source, &book), file_stamp(&book))
+                else {
+                    continue;
+                };
+                // Also skips books already queued: editors save in several writes
+                if state.files.get(&key).is_some_and(|f| f.size == size && f.modified == modified) {
+                    continue;
+                }
+                let target = mirror_target(output_dir, &key);
+                state.files.insert(
+                    key.clone(),
+                    WatchedFile {
+                        status: "queued".to_string(),
+                        size,
+                        modified,
+                        output_path: target.to_string_lossy().to_string(),
+                        error: None,
+                        updated_at: chrono::Utc::now().to_rfc3339(),
+                    },
+                );
+                changed = true;
+                // A book modified during its conversion is queued again once that one finishes,
+                // so two jobs never write the same PDF
+                if converting.insert(key.clone()) {
+                    ready.push((key, book, (size, modified)));
+                }
+            }
+            if changed {
+                self.save(&state);
+            }
+        }
+        for (key, book, stamp) in ready {
+            self.queue(key, book, stamp);
+        }
+    }
+
+    /// Queue one book; its outcome is recorded against the stamp it was queued with
+    fn queue(self: &Arc<Self>, key: String, book: PathBuf, stamp: Stamp) {
+        let epub_path = book.to_string_lossy().to_string();
+        let converter = match &self.folder.converter {
+            Some(converter) => Ok(converter.clone()),
+            None => epub_converter::preferred_converter_for(&epub_path, "book.pdf").map(str::to_string),
+        };
+        let job = ConversionJob {
+            epub_path,
+            pdf_path: Some(mirror_target(Path::new(&self.folder.output_dir), &key).to_string_lossy().to_string()),
+            book_id: None,
+            layout_profile: self.folder.layout_profile.clone(),
+        };
+        let context = Arc::clone(self);
+        let hook_key = key.clone();
+        let hook: JobHook = Arc::new(move |job: &QueuedConversion| {
+            context.record(&hook_key, stamp, &job.status, job.error.clone())
+        });
+        let queued = converter.and_then(|converter| {
+            epub_converter::enqueue(self.app.clone(), vec![job], converter, self.settings.clone(), None, Some(hook))
+        });
+        if let Err(e) = queued {
+            self.record(&key, stamp, "failed", Some(format!("Failed to queue conversion: {}", e)));
+        }
+    }
+
+    /// Record a finished job. Outcomes for an older version of the book are dropped and the
+    /// current one is queued instead; cancelled books are forgotten so the next scan queues them again.
+    fn record(self: &Arc<Self>, key: &str, stamp: Stamp, status: &str, error: Option<String>) {
+        let requeue = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let mut converting = self.converting.lock().unwrap_or_else(|e| e.into_inner());
+            converting.remove(key);
+            let current = state.files.get(key).map(|f| (f.size, f.modified));
+            if current.is_some_and(|current| current != stamp) {
+                converting.insert(key.to_string());
+                current
+            } else {
+                if status == "cancelled" {
+                    state.files.remove(key);
+                } else if let Some(file) = state.files.get_mut(key) {
+                    file.status = if status == "completed" { "converted" } else { "failed" }.to_string();
+                    file.error = error;
+                    file.updated_at = chrono::Utc::now().to_rfc3339();
+                }
+                self.save(&state);
+                None
+            }
+        };
+        if let Some(stamp) = requeue {
+            let book = self.source.join(key.split('/').collect::<PathBuf>());
+            self.queue(key.to_string(), book, stamp);
+        }
+    }
+
+    fn save(&self, state: &WatchState) {
+        if let Err(e) = save_state(Path::new(&self.folder.output_dir), state) {
+            self.warn(e);
+        }
+    }
+
+    /// Keep an error for list_watch_folders; the watch goes on
+    fn warn(&self, warning: String) {
+        *self.warning.lock().unwrap_or_else(|e| e.into_inner()) = Some(warning);
+    }
+
+    fn status(&self) -> WatchFolderStatus {
+        WatchFolderStatus {
+            folder: self.folder.clone(),
+            files: self.state.lock().unwrap_or_else(|e| e.into_inner()).files.clone(),
+            warning: self.warning.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        }
+    }
+}
+
+fn start(app: Option<AppHandle>, folder: WatchFolder, settings: EpubToPdfSettings) -> Result<WatchFolderStatus, String> {
+    let source = Path::new(&folder.source_dir)
+        .canonicalize()
+        .map_err(|e| format!("Failed to open watch folder {}: {}", folder.source_dir, e))?;
+    if !source.is_dir() {
+        return Err(format!("Not a folder: {}", folder.source_dir));
+    }
+    let output_dir = Path::new(&folder.output_dir);
+    std::fs::create_dir_all(output_dir).map_err(|e| format!("Failed to create output folder: {}", e))?;
+
+    // Watching the same folder again replaces the earlier watch and its settings
+    stop(&folder.source_dir);
+
+    let context = Arc::new(WatchContext {
+        state: Mutex::new(load_state(output_dir)),
+        converting: Mutex::new(BTreeSet::new()),
+        warning: Mutex::new(None),
+        folder,
+        source: source.clone(),
+        settings,
+        app,
+    });
+    let handler_context = Arc::clone(&context);
+    let mut debouncer = new_debouncer(DEBOUNCE, move |events: DebounceEventResult| match events {
+        Ok(events) => handler_context.handle_paths(events.into_iter().map(|e| e.path).collect()),
+        Err(e) => handler_context.warn(format!("Watch error: {}", e)),
+    })
+    .map_err(|e| format!("Failed to watch {}: {}", source.display(), e))?;
+    debouncer
+        .watcher()
+        .watch(&source, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch {}: {}", source.display(), e))?;
+
+    // Books added while nothing was watching
+    context.handle_paths(vec![source]);
+    let status = context.status();
+    watches().push(ActiveWatch {
+        context,
+        _debouncer: debouncer,
+    });
+    Ok(status)
+}
+
+/// Stop watching a folder; false when it was not watched
+fn stop(source_dir: &str) -> bool {
+    let mut watches = watches();
+    let before = watches.len();
+    watches.retain(|w| w.context.folder.source_dir != source_dir);
+    watches.len() != before
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Watch a folder and convert the books in it now and whenever they are added or modified
+#[command]
+pub fn start_watch_folder(
+    app: AppHandle,
+    folder: WatchFolder,
+    settings: Option<EpubToPdfSettings>,
+) -> Result<WatchFolderStatus, String> {
+    start(Some(app), folder, settings.unwrap_or_default())
+}
+
+/// Stop watching a folder; queued conversions still run
+#[command]
+pub fn stop_watch_folder(source_dir: String) -> Result<(), String> {
+    if stop(&source_dir) {
+        Ok(())
+    } else {
+        Err(format!("Folder is not watched: {}", source_dir))
+    }
+}
+
+/// Watched folders with the state of their books
+#[command]
+pub fn list_watch_folders() -> Vec<WatchFolderStatus> {
+    watches().iter().map(|w| w.context.status()).collect()
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_and_mirror() {
+        let root = std::env::temp_dir().join(format!("stomy-watch-scan-{}", std::process::id()));
+        for path in ["a.epub", "Dune/b.kepub", "Dune/.b.partial.epub", ".trash/c.epub", "notes.txt"] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x").unwrap();
+        }
+
+        let mut books = Vec::new();
+        scan(&root, &mut books);
+        books.sort();
+        let keys: Vec<String> = books.iter().filter_map(|b| relative_key(&root, b)).collect();
+        assert_eq!(keys, vec!["Dune/b.kepub", "a.epub"]);
+        assert_eq!(mirror_target(Path::new("/pdf"), "Dune/b.kepub"), Path::new("/pdf/Dune/b.pdf"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watch_state() {
+        let root = std::env::temp_dir().join(format!("stomy-watch-state-{}", std::process::id()));
+        let (source, output) = (root.join("in"), root.join("out"));
+        std::fs::create_dir_all(source.join("Dune")).unwrap();
+        // Not a valid book, so the queued conversion fails quickly
+        std::fs::write(source.join("Dune/dune.epub"), "not a zip").unwrap();
+
+        let folder = WatchFolder {
+            source_dir: source.to_string_lossy().to_string(),
+            output_dir: output.to_string_lossy().to_string(),
+            converter: Some("native".to_string()),
+            layout_profile: None,
+        };
+        // Already "failed" when the queue is quicker than this thread
+        let status = start(None, folder.clone(), EpubToPdfSettings::default()).unwrap();
+        assert!(status.files.contains_key("Dune/dune.epub"));
+
+        let wait_for_record = |key: &str| {
+            for _ in 0..600 {
+                if let Some(file) = load_state(&output).files.get(key) {
+                    return file.clone();
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            panic!("{} was not recorded", key);
+        };
+        let recorded = wait_for_record("Dune/dune.epub");
+        assert_eq!(recorded.status, "failed");
+        assert_eq!(recorded.output_path, output.join("Dune/dune.pdf").to_string_lossy());
+
+        // After a restart the recorded book is not queued again
+        assert!(stop(&folder.source_dir));
+        let status = start(None, folder.clone(), EpubToPdfSettings::default()).unwrap();
+        assert_eq!(status.files["Dune/dune.epub"].status, "failed");
+
+        // A book dropped in later is picked up once the writes settle
+        std::fs::write(source.join("messiah.epub"), "not a zip").unwrap();
+        assert_eq!(wait_for_record("messiah.epub").status, "failed");
+        assert!(stop(&folder.source_dir));
+        assert!(!stop(&folder.source_dir));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_outdated_outcome() {
+        let root = std::env::temp_dir().join(format!("stomy-watch-outdated-{}", std::process::id()));
+        let (source, output) = (root.join("in"), root.join("out"));
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(&output).unwrap();
+        std::fs::write(source.join("dune.epub"), "not a zip").unwrap();
+        let (size, modified) = file_stamp(&source.join("dune.epub")).unwrap();
+
+        // The book was modified while its first conversion was running
+        let mut state = WatchState::default();
+        state.files.insert(
+            "dune.epub".to_string(),
+            WatchedFile {
+                status: "queued".to_string(),
+                size,
+                modified,
+                output_path: output.join("dune.pdf").to_string_lossy().to_string(),
+                error: None,
+                updated_at: chrono::Utc::now().to_rfc3339(),
+            },
+        );
+        let context = Arc::new(WatchContext {
+            folder: WatchFolder {
+                source_dir: source.to_string_lossy().to_string(),
+                output_dir: output.to_string_lossy().to_string(),
+                converter: Some("native".to_string()),
+                layout_profile: None,
+            },
+            source: source.clone(),
+            settings: EpubToPdfSettings::default(),
+            app: None,
+            state: Mutex::new(state),
+            converting: Mutex::new(BTreeSet::from(["dune.epub".to_string()])),
+            warning: Mutex::new(None),
+        });
+
+        // The first job succeeding does not mark the modified book converted: it is queued again
+        context.record("dune.epub", (size, modified.saturating_sub(1000)), "completed", None);
+        assert!(context.converting.lock().unwrap().contains("dune.epub"));
+        for _ in 0..600 {
+            if !context.converting.lock().unwrap().contains("dune.epub") {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let file = context.status().files["dune.epub"].clone();
+        assert_eq!(file.status, "failed");
+        assert_eq!((file.size, file.modified), (size, modified));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
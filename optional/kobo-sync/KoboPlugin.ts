@@ -19,6 +19,16 @@ import type {
   KoboVocabulary,
   KoboLibraryData,
   KoboSyncStats,
+  KoboFontValidation,
+  KoboInstalledFont,
+  KoboDictionaryValidation,
+  KoboInstalledDictionary,
+  KoboCoverResult,
+  KoboImagePurgeResult,
+  KoboScreenshot,
+  StomyProgress,
+  ProgressMergeOptions,
+  ProgressMergePlan,
 } from './types';
 
 export const koboPlugin: Plugin = {
@@ -362,6 +372,148 @@ export async function getBookProgress(
   }
 }
 
+/**
+ * Build a progress resolution plan (no data is written)
+ */
+export async function planKoboProgressMerge(
+  devicePath: string,
+  stomyProgress: StomyProgress[],
+  options: ProgressMergeOptions
+): Promise<ProgressMergePlan> {
+  return await invoke<ProgressMergePlan>('plan_kobo_progress_merge', {
+    devicePath,
+    stomyProgress,
+    options,
+  });
+}
+
+// ============================================================================
+// Fonts & Dictionaries
+// ============================================================================
+
+/**
+ * Validate a font family (Regular, Bold, Italic, BoldItalic) before install
+ */
+export async function validateKoboFontFamily(fontPaths: string[]): Promise<KoboFontValidation> {
+  return await invoke<KoboFontValidation>('validate_kobo_font_family', { fontPaths });
+}
+
+/**
+ * Install a font family into the Kobo `fonts` folder
+ */
+export async function installKoboFontFamily(
+  devicePath: string,
+  fontPaths: string[]
+): Promise<string[]> {
+  return await invoke<string[]>('install_kobo_font_family', { devicePath, fontPaths });
+}
+
+/**
+ * List font families installed on the Kobo
+ */
+export async function listKoboFonts(devicePath: string): Promise<KoboInstalledFont[]> {
+  try {
+    return await invoke<KoboInstalledFont[]>('list_kobo_fonts', { devicePath });
+  } catch (error) {
+    console.error('[KoboPlugin] Failed to list fonts:', error);
+    return [];
+  }
+}
+
+/**
+ * Remove a font family from the Kobo
+ */
+export async function removeKoboFontFamily(devicePath: string, family: string): Promise<number> {
+  return await invoke<number>('remove_kobo_font_family', { devicePath, family });
+}
+
+/**
+ * Validate a `dicthtml-*.zip` dictionary before install
+ */
+export async function validateKoboDictionary(
+  dictionaryPath: string
+): Promise<KoboDictionaryValidation> {
+  return await invoke<KoboDictionaryValidation>('validate_kobo_dictionary', { dictionaryPath });
+}
+
+/**
+ * Install a dictionary into the Kobo `.kobo/dict` folder
+ */
+export async function installKoboDictionary(
+  devicePath: string,
+  dictionaryPath: string
+): Promise<string> {
+  return await invoke<string>('install_kobo_dictionary', { devicePath, dictionaryPath });
+}
+
+/**
+ * List dictionaries installed on the Kobo
+ */
+export async function listKoboDictionaries(devicePath: string): Promise<KoboInstalledDictionary[]> {
+  try {
+    return await invoke<KoboInstalledDictionary[]>('list_kobo_dictionaries', { devicePath });
+  } catch (error) {
+    console.error('[KoboPlugin] Failed to list dictionaries:', error);
+    return [];
+  }
+}
+
+/**
+ * Remove a dictionary from the Kobo
+ */
+export async function removeKoboDictionary(devicePath: string, fileName: string): Promise<void> {
+  await invoke('remove_kobo_dictionary', { devicePath, fileName });
+}
+
+// ============================================================================
+// Image Cache
+// ============================================================================
+
+/**
+ * Regenerate Kobo cover thumbnails from a Stomy cover image
+ */
+export async function regenerateKoboCover(
+  devicePath: string,
+  contentId: string,
+  coverPath: string,
+  screenWidth?: number,
+  screenHeight?: number
+): Promise<KoboCoverResult> {
+  return await invoke<KoboCoverResult>('regenerate_kobo_cover', {
+    devicePath,
+    contentId,
+    coverPath,
+    screenWidth: screenWidth ?? null,
+    screenHeight: screenHeight ?? null,
+  });
+}
+
+/**
+ * Remove cached thumbnails of books no longer on the device
+ */
+export async function purgeKoboOrphanImages(devicePath: string): Promise<KoboImagePurgeResult> {
+  return await invoke<KoboImagePurgeResult>('purge_kobo_orphan_images', { devicePath });
+}
+
+/**
+ * List screenshots taken on the Kobo
+ */
+export async function listKoboScreenshots(devicePath: string): Promise<KoboScreenshot[]> {
+  try {
+    return await invoke<KoboScreenshot[]>('list_kobo_screenshots', { devicePath });
+  } catch (error) {
+    console.error('[KoboPlugin] Failed to list screenshots:', error);
+    return [];
+  }
+}
+
+/**
+ * Delete all screenshots from the Kobo
+ */
+export async function deleteKoboScreenshots(devicePath: string): Promise<number> {
+  return await invoke<number>('delete_kobo_screenshots', { devicePath });
+}
+
 // ============================================================================
 // Book Matching Functions
 // ============================================================================
@@ -86,11 +86,15 @@ pub struct KoboLibraryData {
 // Database Connection
 // ============================================================================
 
+pub(crate) fn get_kobo_dir(device_path: &str) -> PathBuf {
+    PathBuf::from(device_path).join(".kobo")
+}
+
 fn get_kobo_db_path(device_path: &str) -> PathBuf {
-    PathBuf::from(device_path).join(".kobo").join("KoboReader.sqlite")
+    get_kobo_dir(device_path).join("KoboReader.sqlite")
 }
 
-fn open_kobo_db(device_path: &str) -> SqlResult<Connection> {
+pub(crate) fn open_kobo_db(device_path: &str) -> SqlResult<Connection> {
     let db_path = get_kobo_db_path(device_path);
     Connection::open(db_path)
 }
@@ -118,10 +122,24 @@ fn parse_kobo_book(row: &Row) -> SqlResult<KoboBook> {
     })
 }
 
-fn query_books(conn: &Connection) -> SqlResult<Vec<KoboBook>> {
-    let mut stmt = conn.prepare(
-        "SELECT
-            ContentID,
+/// Check whether a column exists, as older firmware ships fewer columns
+fn has_column(conn: &Connection, table: &str, column: &str) -> bool {
+    conn.prepare(&format!("SELECT * FROM {} LIMIT 0", table))
+        .map(|stmt| stmt.column_names().iter().any(|c| c.eq_ignore_ascii_case(column)))
+        .unwrap_or(false)
+}
+
+/// Book columns in the order expected by `parse_kobo_book`
+fn book_columns(conn: &Connection) -> String {
+    // TimeSpentReading only exists on firmware 4.20 and later
+    let time_spent = if has_column(conn, "content", "TimeSpentReading") {
+        "TimeSpentReading"
+    } else {
+        "0"
+    };
+
+    format!(
+        "ContentID,
             ISBN,
             Title,
             Attribution,
@@ -130,15 +148,24 @@ fn query_books(conn: &Connection) -> SqlResult<Vec<KoboBook>> {
             Language,
             ___PercentRead,
             ReadStatus,
-            TimeSpentReading,
+            {},
             DateLastRead,
             MimeType,
             ContentType,
-            ___UserID
+            ___UserID",
+        time_spent
+    )
+}
+
+pub(crate) fn query_books(conn: &Connection) -> SqlResult<Vec<KoboBook>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT
+            {}
         FROM content
         WHERE ContentType = 6 OR ContentType = 9
-        ORDER BY DateLastRead DESC"
-    )?;
+        ORDER BY DateLastRead DESC",
+        book_columns(conn)
+    ))?;
 
     let books = stmt.query_map([], parse_kobo_book)?
         .filter_map(|r| r.ok())
@@ -147,13 +174,13 @@ fn query_books(conn: &Connection) -> SqlResult<Vec<KoboBook>> {
     Ok(books)
 }
 
-fn query_events(conn: &Connection) -> SqlResult<Vec<KoboEvent>> {
+pub(crate) fn query_events(conn: &Connection) -> SqlResult<Vec<KoboEvent>> {
     let mut stmt = conn.prepare(
         "SELECT
-            Id,
+            rowid,
             ContentID,
-            Type,
-            Count,
+            EventType,
+            EventCount,
             LastOccurrence,
             ExtraData
         FROM Event
@@ -177,8 +204,19 @@ fn query_events(conn: &Connection) -> SqlResult<Vec<KoboEvent>> {
     Ok(events)
 }
 
-fn query_bookmarks(conn: &Connection) -> SqlResult<Vec<KoboBookmark>> {
-    let mut stmt = conn.prepare(
+pub(crate) fn query_bookmarks(conn: &Connection) -> SqlResult<Vec<KoboBookmark>> {
+    // Firmware 3.x has no Type column: derive it from the bookmark content
+    let bookmark_type = if has_column(conn, "Bookmark", "Type") {
+        "Type"
+    } else {
+        "CASE
+                WHEN Annotation IS NOT NULL AND Annotation != '' THEN 'annotation'
+                WHEN Text IS NOT NULL AND Text != '' THEN 'highlight'
+                ELSE 'dogear'
+            END"
+    };
+
+    let mut stmt = conn.prepare(&format!(
         "SELECT
             BookmarkID,
             VolumeID,
@@ -192,18 +230,19 @@ fn query_bookmarks(conn: &Connection) -> SqlResult<Vec<KoboBookmark>> {
             EndOffset,
             DateCreated,
             DateModified,
-            Type
+            {}
         FROM Bookmark
         WHERE Hidden = 'false'
-        ORDER BY DateCreated DESC"
-    )?;
+        ORDER BY DateCreated DESC",
+        bookmark_type
+    ))?;
 
     let bookmarks = stmt.query_map([], |row| {
         Ok(KoboBookmark {
             bookmark_id: row.get(0)?,
             volume_id: row.get(1)?,
             content_id: row.get(2)?,
-            text: row.get(3)?,
+            text: row.get::<_, Option<String>>(3)?.unwrap_or_default(), // Empty for dogears
             annotation: row.get(4).ok(),
             chapter_progress: row.get::<_, Option<f64>>(5).unwrap_or(Some(0.0)).unwrap_or(0.0),
             start_container_path: row.get(6).ok(),
@@ -221,7 +260,7 @@ fn query_bookmarks(conn: &Connection) -> SqlResult<Vec<KoboBookmark>> {
     Ok(bookmarks)
 }
 
-fn query_vocabulary(conn: &Connection) -> SqlResult<Vec<KoboVocabulary>> {
+pub(crate) fn query_vocabulary(conn: &Connection) -> SqlResult<Vec<KoboVocabulary>> {
     let mut stmt = conn.prepare(
         "SELECT
             Text,
@@ -316,7 +355,9 @@ pub fn get_kobo_library_data(device_path: String) -> Result<KoboLibraryData, Str
     })
 }
 
-/// Get reading progress for a specific book by ISBN or title
+/// Get reading progress for a specific book by ISBN or title. Only books
+/// (ContentType 6) match, not their chapters; both values are bound as SQL
+/// parameters, never spliced into the query.
 #[command]
 pub fn get_book_progress(
     device_path: String,
@@ -326,10 +367,17 @@ pub fn get_book_progress(
     let conn = open_kobo_db(&device_path)
         .map_err(|e| format!("Failed to open Kobo database: {}", e))?;
 
-    let query = if let Some(isbn_val) = isbn {
-        format!("SELECT * FROM content WHERE ISBN = '{}' LIMIT 1", isbn_val)
+    let columns = book_columns(&conn);
+    let (query, value) = if let Some(isbn_val) = isbn {
+        (
+            format!("SELECT {} FROM content WHERE ContentType = 6 AND ISBN = ?1 LIMIT 1", columns),
+            isbn_val,
+        )
     } else if let Some(title_val) = title {
-        format!("SELECT * FROM content WHERE Title LIKE '%{}%' LIMIT 1", title_val)
+        (
+            format!("SELECT {} FROM content WHERE ContentType = 6 AND Title LIKE ?1 LIMIT 1", columns),
+            format!("%{}%", title_val),
+        )
     } else {
         return Err("Either ISBN or title must be provided".to_string());
     };
@@ -337,7 +385,7 @@ pub fn get_book_progress(
     let mut stmt = conn.prepare(&query)
         .map_err(|e| format!("Failed to prepare query: {}", e))?;
 
-    let book = stmt.query_row([], parse_kobo_book).ok();
+    let book = stmt.query_row([value], parse_kobo_book).ok();
 
     Ok(book)
 }
@@ -348,8 +396,16 @@ pub fn get_book_progress(
 
 #[cfg(test)]
 mod tests {
+    use super::super::kobo_fixtures::{KoboFixture, KoboSchemaVersion};
     use super::*;
 
+    fn sample_fixture(schema: KoboSchemaVersion) -> KoboFixture {
+        KoboFixture::builder(schema)
+            .sample_library()
+            .build()
+            .expect("fixture database")
+    }
+
     #[test]
     fn test_db_path() {
         let path = get_kobo_db_path("/Volumes/KOBOeReader");
@@ -358,4 +414,133 @@ mod tests {
             "/Volumes/KOBOeReader/.kobo/KoboReader.sqlite"
         );
     }
+
+    #[test]
+    fn test_get_kobo_books() {
+        for schema in KoboSchemaVersion::ALL {
+            let fixture = sample_fixture(schema);
+            let books = get_kobo_books(fixture.device_path()).unwrap();
+
+            // Chapters (ContentType 899) are not books
+            assert_eq!(books.len(), 3, "{:?}", schema);
+
+            let dune = books.iter().find(|b| b.title == "Dune").unwrap();
+            assert_eq!(dune.attribution.as_deref(), Some("Frank Herbert"));
+            assert_eq!(dune.isbn.as_deref(), Some("9780441013593"));
+            assert_eq!(dune.percent_read, 100.0);
+            assert_eq!(dune.read_status, 2);
+            assert_eq!(dune.content_type, "6");
+            assert_eq!(dune.mime_type, "application/x-kobo-epub+zip");
+
+            let expected_time = if schema == KoboSchemaVersion::Latest { 1260 } else { 0 };
+            assert_eq!(dune.time_spent_reading, expected_time, "{:?}", schema);
+
+            let ulysses = books.iter().find(|b| b.title == "Ulysses").unwrap();
+            assert_eq!(ulysses.isbn, None);
+            assert_eq!(ulysses.date_last_read, None);
+        }
+    }
+
+    #[test]
+    fn test_get_kobo_events() {
+        for schema in KoboSchemaVersion::ALL {
+            let fixture = sample_fixture(schema);
+            let events = get_kobo_events(fixture.device_path()).unwrap();
+
+            assert_eq!(events.len(), 3, "{:?}", schema);
+
+            // Most recent first
+            assert_eq!(events[0].event_type, 1011);
+            assert_eq!(events[0].extra_data, None);
+
+            let started = events.iter().find(|e| e.event_type == 3).unwrap();
+            assert_eq!(started.event_count, 12);
+            assert!(started.extra_data.as_ref().is_some_and(|d| !d.is_empty()));
+        }
+    }
+
+    #[test]
+    fn test_get_kobo_bookmarks() {
+        for schema in KoboSchemaVersion::ALL {
+            let fixture = sample_fixture(schema);
+            let bookmarks = get_kobo_bookmarks(fixture.device_path()).unwrap();
+
+            // The hidden bookmark is filtered out
+            assert_eq!(bookmarks.len(), 3, "{:?}", schema);
+            assert!(bookmarks.iter().all(|b| b.bookmark_id != "8f2b6c1e-0004"));
+
+            let highlight = bookmarks.iter().find(|b| b.bookmark_id == "8f2b6c1e-0001").unwrap();
+            assert_eq!(highlight.text, "Fear is the mind-killer.");
+            assert_eq!(highlight.bookmark_type, "highlight");
+            assert_eq!(highlight.chapter_progress, 0.35);
+            assert!(highlight.content_id.ends_with("!OEBPS!ch02.xhtml"));
+
+            let annotation = bookmarks.iter().find(|b| b.bookmark_id == "8f2b6c1e-0002").unwrap();
+            assert_eq!(annotation.annotation.as_deref(), Some("Theme of the whole book"));
+            assert_eq!(annotation.bookmark_type, "annotation");
+
+            let dogear = bookmarks.iter().find(|b| b.bookmark_id == "8f2b6c1e-0003").unwrap();
+            assert_eq!(dogear.text, "");
+            assert_eq!(dogear.bookmark_type, "dogear");
+        }
+    }
+
+    #[test]
+    fn test_get_kobo_vocabulary() {
+        for schema in KoboSchemaVersion::ALL {
+            let fixture = sample_fixture(schema);
+            let vocabulary = get_kobo_vocabulary(fixture.device_path()).unwrap();
+
+            let words: Vec<&str> = vocabulary.iter().map(|v| v.text.as_str()).collect();
+            assert_eq!(words, vec!["psychohistoire", "sietch"], "{:?}", schema);
+        }
+    }
+
+    #[test]
+    fn test_get_kobo_library_data() {
+        for schema in KoboSchemaVersion::ALL {
+            let fixture = sample_fixture(schema);
+            let data = get_kobo_library_data(fixture.device_path()).unwrap();
+
+            assert_eq!(data.books.len(), 3, "{:?}", schema);
+            assert_eq!(data.events.len(), 3);
+            assert_eq!(data.bookmarks.len(), 3);
+            assert_eq!(data.vocabulary.len(), 2);
+            assert!(!data.last_sync.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_get_book_progress() {
+        for schema in KoboSchemaVersion::ALL {
+            let fixture = sample_fixture(schema);
+
+            let by_isbn = get_book_progress(fixture.device_path(), Some("9782070360536".to_string()), None)
+                .unwrap()
+                .unwrap();
+            assert_eq!(by_isbn.title, "Fondation");
+            assert_eq!(by_isbn.percent_read, 42.0);
+
+            let by_title = get_book_progress(fixture.device_path(), None, Some("ulyss".to_string()))
+                .unwrap()
+                .unwrap();
+            assert_eq!(by_title.title, "Ulysses");
+
+            // Quotes are matched literally, not interpreted as SQL
+            let injected = get_book_progress(fixture.device_path(), Some("' OR '1'='1".to_string()), None).unwrap();
+            assert!(injected.is_none());
+
+            // Chapter rows share the content table but are not books
+            let chapter = get_book_progress(fixture.device_path(), None, Some("Chapter 1".to_string())).unwrap();
+            assert!(chapter.is_none());
+
+            assert!(get_book_progress(fixture.device_path(), None, None).is_err());
+        }
+    }
+
+    #[test]
+    fn test_missing_database() {
+        let result = get_kobo_books("/nonexistent/KOBOeReader".to_string());
+        assert!(result.is_err());
+    }
 }
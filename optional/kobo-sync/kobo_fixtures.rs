@@ -0,0 +1,539 @@
+#![cfg(any(test, feature = "kobo-fixtures"))]
+
+/**
+ * KoboReader.sqlite Fixtures
+ *
+ * Builds throwaway Kobo device folders with a realistic KoboReader.sqlite so
+ * the query layer can be tested without a device. Only compiled for tests or
+ * with the `kobo-fixtures` feature.
+ *
+ * Usage:
+ *   let fixture = KoboFixture::builder(KoboSchemaVersion::Latest)
+ *       .sample_library()
+ *       .build()?;
+ *   let books = get_kobo_books(fixture.device_path())?;
+ */
+
+use rusqlite::{params, Connection, Result as SqlResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::kobo_db::get_kobo_dir;
+
+static FIXTURE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// ============================================================================
+// Schema Versions
+// ============================================================================
+
+/// Firmware generations with distinct KoboReader.sqlite layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KoboSchemaVersion {
+    /// Firmware 3.x: no Bookmark.Type, no content.TimeSpentReading
+    Firmware3,
+    /// Firmware 4.0 - 4.19: Bookmark.Type, no content.TimeSpentReading
+    Firmware4,
+    /// Firmware 4.20+: all columns read by kobo_db.rs
+    Latest,
+}
+
+impl KoboSchemaVersion {
+    pub const ALL: [KoboSchemaVersion; 3] = [Self::Firmware3, Self::Firmware4, Self::Latest];
+
+    /// Value stored in the DbVersion table
+    pub fn db_version(self) -> i32 {
+        match self {
+            Self::Firmware3 => 98,
+            Self::Firmware4 => 130,
+            Self::Latest => 174,
+        }
+    }
+
+    fn schema(self) -> String {
+        let time_spent = match self {
+            Self::Latest => "TimeSpentReading INTEGER, LastTimeStartedReading TEXT,",
+            _ => "",
+        };
+        let bookmark_type = match self {
+            Self::Firmware3 => "",
+            _ => "Type TEXT,",
+        };
+        let dict_suffix = match self {
+            Self::Firmware3 => "",
+            _ => "DictSuffix TEXT,",
+        };
+
+        format!(
+            "CREATE TABLE DbVersion (version INTEGER);
+            CREATE TABLE content (
+                ContentID TEXT NOT NULL,
+                ContentType TEXT NOT NULL,
+                MimeType TEXT NOT NULL,
+                BookID TEXT,
+                BookTitle TEXT,
+                ImageId TEXT,
+                Title TEXT COLLATE NOCASE,
+                Attribution TEXT COLLATE NOCASE,
+                Description TEXT,
+                Publisher TEXT,
+                DateLastRead TEXT,
+                ChapterIDBookmarked TEXT,
+                ReadStatus INTEGER,
+                ___UserID TEXT NOT NULL,
+                ___PercentRead INTEGER,
+                Language TEXT,
+                ISBN TEXT,
+                VolumeIndex INTEGER,
+                {time_spent}
+                PRIMARY KEY (ContentID)
+            );
+            CREATE TABLE Event (
+                EventType INTEGER NOT NULL,
+                FirstOccurrence TEXT,
+                LastOccurrence TEXT,
+                EventCount INTEGER DEFAULT 0,
+                ContentID TEXT,
+                ExtraData BLOB,
+                Checksum TEXT,
+                PRIMARY KEY (EventType, ContentID)
+            );
+            CREATE TABLE Bookmark (
+                BookmarkID TEXT NOT NULL,
+                VolumeID TEXT NOT NULL,
+                ContentID TEXT NOT NULL,
+                StartContainerPath TEXT NOT NULL,
+                StartContainerChildIndex INTEGER NOT NULL,
+                StartOffset INTEGER NOT NULL,
+                EndContainerPath TEXT NOT NULL,
+                EndContainerChildIndex INTEGER NOT NULL,
+                EndOffset INTEGER NOT NULL,
+                Text TEXT,
+                Annotation TEXT,
+                ExtraAnnotationData BLOB,
+                DateCreated TEXT,
+                ChapterProgress REAL NOT NULL DEFAULT 0,
+                Hidden BOOL NOT NULL DEFAULT 0,
+                Version TEXT,
+                DateModified TEXT,
+                Creator TEXT,
+                UUID TEXT,
+                UserID TEXT,
+                SyncTime TEXT,
+                Published BIT DEFAULT false,
+                {bookmark_type}
+                PRIMARY KEY (BookmarkID)
+            );
+            CREATE TABLE WordList (
+                Text TEXT NOT NULL,
+                VolumeId TEXT NOT NULL,
+                {dict_suffix}
+                DateCreated TEXT,
+                PRIMARY KEY (Text)
+            );"
+        )
+    }
+}
+
+// ============================================================================
+// Fixture Records
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct FixtureBook {
+    pub content_id: String,
+    pub title: String,
+    pub attribution: Option<String>,
+    pub isbn: Option<String>,
+    pub language: Option<String>,
+    pub percent_read: i32,
+    pub read_status: i32,
+    pub time_spent_reading: i32,
+    pub date_last_read: Option<String>,
+    pub chapters: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixtureEvent {
+    pub content_id: String,
+    pub event_type: i32,
+    pub event_count: i32,
+    pub last_occurrence: String,
+    pub extra_data: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixtureBookmark {
+    pub bookmark_id: String,
+    pub volume_id: String,
+    pub chapter: usize,
+    pub text: Option<String>,
+    pub annotation: Option<String>,
+    pub chapter_progress: f64,
+    pub date_created: String,
+    pub bookmark_type: String,
+    pub hidden: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixtureWord {
+    pub text: String,
+    pub volume_id: String,
+    pub date_created: String,
+}
+
+/// ContentID Kobo uses for a sideloaded file under the device root
+pub fn sideloaded_content_id(relative_path: &str) -> String {
+    format!("file:///mnt/onboard/{}", relative_path)
+}
+
+/// ContentID of a chapter inside a kepub (`<book>!OEBPS!chNN.xhtml`)
+fn chapter_content_id(book_id: &str, chapter: usize) -> String {
+    format!("{}!OEBPS!ch{:02}.xhtml", book_id, chapter + 1)
+}
+
+/// Serialized QVariantMap-like ExtraData blob, as found in reading events
+pub fn event_extra_data(pairs: &[(&str, &str)]) -> Vec<u8> {
+    let mut data = (pairs.len() as u32).to_be_bytes().to_vec();
+    for (key, value) in pairs {
+        for text in [key, value] {
+            let utf16: Vec<u8> = text.encode_utf16().flat_map(|c| c.to_be_bytes()).collect();
+            data.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+            data.extend_from_slice(&utf16);
+        }
+    }
+    data
+}
+
+// ============================================================================
+// Builder
+// ============================================================================
+
+pub struct KoboFixtureBuilder {
+    schema: KoboSchemaVersion,
+    books: Vec<FixtureBook>,
+    events: Vec<FixtureEvent>,
+    bookmarks: Vec<FixtureBookmark>,
+    words: Vec<FixtureWord>,
+}
+
+impl KoboFixtureBuilder {
+    pub fn book(mut self, book: FixtureBook) -> Self {
+        self.books.push(book);
+        self
+    }
+
+    pub fn event(mut self, event: FixtureEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    pub fn bookmark(mut self, bookmark: FixtureBookmark) -> Self {
+        self.bookmarks.push(bookmark);
+        self
+    }
+
+    pub fn word(mut self, text: &str, volume_id: &str, date_created: &str) -> Self {
+        self.words.push(FixtureWord {
+            text: text.to_string(),
+            volume_id: volume_id.to_string(),
+            date_created: date_created.to_string(),
+        });
+        self
+    }
+
+    /// Three books (finished, in progress, unread) with chapters, reading
+    /// events, highlights, an annotation, a dogear, a hidden bookmark and
+    /// vocabulary lookups
+    pub fn sample_library(self) -> Self {
+        let dune = sideloaded_content_id("Stomy/Frank Herbert - Dune.kepub.epub");
+        let fondation = sideloaded_content_id("Stomy/Isaac Asimov - Fondation.kepub.epub");
+        let ulysses = sideloaded_content_id("Stomy/James Joyce - Ulysses.kepub.epub");
+
+        self.book(FixtureBook {
+            content_id: dune.clone(),
+            title: "Dune".to_string(),
+            attribution: Some("Frank Herbert".to_string()),
+            isbn: Some("9780441013593".to_string()),
+            language: Some("en".to_string()),
+            percent_read: 100,
+            read_status: 2,
+            time_spent_reading: 1260,
+            date_last_read: Some("2025-02-14T21:03:11Z".to_string()),
+            chapters: 4,
+        })
+        .book(FixtureBook {
+            content_id: fondation.clone(),
+            title: "Fondation".to_string(),
+            attribution: Some("Isaac Asimov".to_string()),
+            isbn: Some("9782070360536".to_string()),
+            language: Some("fr".to_string()),
+            percent_read: 42,
+            read_status: 1,
+            time_spent_reading: 310,
+            date_last_read: Some("2025-03-02T08:45:00Z".to_string()),
+            chapters: 5,
+        })
+        .book(FixtureBook {
+            content_id: ulysses.clone(),
+            title: "Ulysses".to_string(),
+            attribution: Some("James Joyce".to_string()),
+            isbn: None,
+            language: Some("en".to_string()),
+            percent_read: 0,
+            read_status: 0,
+            time_spent_reading: 0,
+            date_last_read: None,
+            chapters: 3,
+        })
+        .event(FixtureEvent {
+            content_id: dune.clone(),
+            event_type: 3,
+            event_count: 12,
+            last_occurrence: "2025-02-14T20:10:00Z".to_string(),
+            extra_data: Some(event_extra_data(&[("eventTimestamps", "1739563800")])),
+        })
+        .event(FixtureEvent {
+            content_id: dune.clone(),
+            event_type: 5,
+            event_count: 1,
+            last_occurrence: "2025-02-14T21:03:11Z".to_string(),
+            extra_data: Some(event_extra_data(&[("progress", "100"), ("source", "reader")])),
+        })
+        .event(FixtureEvent {
+            content_id: fondation.clone(),
+            event_type: 1011,
+            event_count: 1,
+            last_occurrence: "2025-03-01T19:30:00Z".to_string(),
+            extra_data: None,
+        })
+        .bookmark(FixtureBookmark {
+            bookmark_id: "8f2b6c1e-0001".to_string(),
+            volume_id: dune.clone(),
+            chapter: 1,
+            text: Some("Fear is the mind-killer.".to_string()),
+            annotation: None,
+            chapter_progress: 0.35,
+            date_created: "2025-02-02T22:14:00Z".to_string(),
+            bookmark_type: "highlight".to_string(),
+            hidden: false,
+        })
+        .bookmark(FixtureBookmark {
+            bookmark_id: "8f2b6c1e-0002".to_string(),
+            volume_id: dune.clone(),
+            chapter: 2,
+            text: Some("The spice must flow.".to_string()),
+            annotation: Some("Theme of the whole book".to_string()),
+            chapter_progress: 0.8,
+            date_created: "2025-02-05T21:00:00Z".to_string(),
+            bookmark_type: "annotation".to_string(),
+            hidden: false,
+        })
+        .bookmark(FixtureBookmark {
+            bookmark_id: "8f2b6c1e-0003".to_string(),
+            volume_id: fondation.clone(),
+            chapter: 0,
+            text: None,
+            annotation: None,
+            chapter_progress: 0.5,
+            date_created: "2025-03-01T19:31:00Z".to_string(),
+            bookmark_type: "dogear".to_string(),
+            hidden: false,
+        })
+        .bookmark(FixtureBookmark {
+            bookmark_id: "8f2b6c1e-0004".to_string(),
+            volume_id: fondation.clone(),
+            chapter: 3,
+            text: Some("Deleted highlight".to_string()),
+            annotation: None,
+            chapter_progress: 0.1,
+            date_created: "2025-03-01T20:00:00Z".to_string(),
+            bookmark_type: "highlight".to_string(),
+            hidden: true,
+        })
+        .word("sietch", &dune, "2025-02-03T21:12:00Z")
+        .word("psychohistoire", &fondation, "2025-03-01T19:40:00Z")
+    }
+
+    /// Create the device folder and write KoboReader.sqlite
+    pub fn build(self) -> Result<KoboFixture, String> {
+        let root = std::env::temp_dir().join(format!(
+            "stomy-kobo-fixture-{}-{}",
+            std::process::id(),
+            FIXTURE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let fixture = KoboFixture {
+            device_path: root.to_string_lossy().to_string(),
+            root,
+            schema: self.schema,
+        };
+
+        let kobo_dir = get_kobo_dir(&fixture.device_path);
+        fs::create_dir_all(&kobo_dir)
+            .map_err(|e| format!("Failed to create fixture device: {}", e))?;
+
+        let conn = Connection::open(kobo_dir.join("KoboReader.sqlite"))
+            .map_err(|e| format!("Failed to create fixture database: {}", e))?;
+        self.populate(&conn)
+            .map_err(|e| format!("Failed to populate fixture database: {}", e))?;
+
+        Ok(fixture)
+    }
+
+    fn populate(&self, conn: &Connection) -> SqlResult<()> {
+        conn.execute_batch(&self.schema.schema())?;
+        conn.execute("INSERT INTO DbVersion (version) VALUES (?1)", [self.schema.db_version()])?;
+
+        for book in &self.books {
+            self.insert_book(conn, book)?;
+        }
+        for event in &self.events {
+            conn.execute(
+                "INSERT INTO Event (EventType, FirstOccurrence, LastOccurrence, EventCount, ContentID, ExtraData)
+                VALUES (?1, ?2, ?2, ?3, ?4, ?5)",
+                params![
+                    event.event_type,
+                    event.last_occurrence,
+                    event.event_count,
+                    event.content_id,
+                    event.extra_data
+                ],
+            )?;
+        }
+        for bookmark in &self.bookmarks {
+            self.insert_bookmark(conn, bookmark)?;
+        }
+        for word in &self.words {
+            conn.execute(
+                "INSERT INTO WordList (Text, VolumeId, DateCreated) VALUES (?1, ?2, ?3)",
+                params![word.text, word.volume_id, word.date_created],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_book(&self, conn: &Connection, book: &FixtureBook) -> SqlResult<()> {
+        let columns = "ContentID, ContentType, MimeType, ImageId, Title, Attribution, Description, \
+            Publisher, DateLastRead, ChapterIDBookmarked, ReadStatus, ___UserID, ___PercentRead, \
+            Language, ISBN";
+        let current_chapter = (book.chapters > 0)
+            .then(|| format!("OEBPS/ch{:02}.xhtml", (book.percent_read as usize * book.chapters / 100).max(1)));
+
+        conn.execute(
+            &format!(
+                "INSERT INTO content ({}) VALUES (?1, '6', 'application/x-kobo-epub+zip', ?2, ?3, ?4, \
+                'Fixture description', 'Stomy Fixtures', ?5, ?6, ?7, 'adobe_user', ?8, ?9, ?10)",
+                columns
+            ),
+            params![
+                book.content_id,
+                book.content_id.replace(['/', ' ', ':', '.'], "_"),
+                book.title,
+                book.attribution,
+                book.date_last_read,
+                current_chapter,
+                book.read_status,
+                book.percent_read,
+                book.language,
+                book.isbn
+            ],
+        )?;
+
+        if self.schema == KoboSchemaVersion::Latest {
+            conn.execute(
+                "UPDATE content SET TimeSpentReading = ?1 WHERE ContentID = ?2",
+                params![book.time_spent_reading, book.content_id],
+            )?;
+        }
+
+        for chapter in 0..book.chapters {
+            conn.execute(
+                "INSERT INTO content (ContentID, ContentType, MimeType, BookID, BookTitle, Title, \
+                ___UserID, VolumeIndex) VALUES (?1, '899', 'application/xhtml+xml', ?2, ?3, ?4, 'adobe_user', ?5)",
+                params![
+                    chapter_content_id(&book.content_id, chapter),
+                    book.content_id,
+                    book.title,
+                    format!("Chapter {}", chapter + 1),
+                    chapter as i32
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_bookmark(&self, conn: &Connection, bookmark: &FixtureBookmark) -> SqlResult<()> {
+        let content_id = chapter_content_id(&bookmark.volume_id, bookmark.chapter);
+        let hidden = if bookmark.hidden { "true" } else { "false" };
+
+        conn.execute(
+            "INSERT INTO Bookmark (BookmarkID, VolumeID, ContentID, StartContainerPath, \
+            StartContainerChildIndex, StartOffset, EndContainerPath, EndContainerChildIndex, EndOffset, \
+            Text, Annotation, DateCreated, ChapterProgress, Hidden, DateModified) \
+            VALUES (?1, ?2, ?3, 'span#kobo\\.12\\.1', -99, 0, 'span#kobo\\.12\\.3', -99, 24, ?4, ?5, ?6, ?7, ?8, ?6)",
+            params![
+                bookmark.bookmark_id,
+                bookmark.volume_id,
+                content_id,
+                bookmark.text,
+                bookmark.annotation,
+                bookmark.date_created,
+                bookmark.chapter_progress,
+                hidden
+            ],
+        )?;
+
+        if self.schema != KoboSchemaVersion::Firmware3 {
+            conn.execute(
+                "UPDATE Bookmark SET Type = ?1 WHERE BookmarkID = ?2",
+                params![bookmark.bookmark_type, bookmark.bookmark_id],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Fixture
+// ============================================================================
+
+/// A fake mounted Kobo, removed from disk when dropped
+pub struct KoboFixture {
+    root: PathBuf,
+    device_path: String,
+    schema: KoboSchemaVersion,
+}
+
+impl KoboFixture {
+    pub fn builder(schema: KoboSchemaVersion) -> KoboFixtureBuilder {
+        KoboFixtureBuilder {
+            schema,
+            books: Vec::new(),
+            events: Vec::new(),
+            bookmarks: Vec::new(),
+            words: Vec::new(),
+        }
+    }
+
+    /// Mount path to pass to the `get_kobo_*` commands
+    pub fn device_path(&self) -> String {
+        self.device_path.clone()
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn schema(&self) -> KoboSchemaVersion {
+        self.schema
+    }
+}
+
+impl Drop for KoboFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
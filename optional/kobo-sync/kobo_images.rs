@@ -0,0 +1,291 @@
+/**
+ * Kobo Image Cache
+ *
+ * Manages the cover thumbnails Kobo keeps in `.kobo-images` and the
+ * screenshots saved at the root of the device.
+ *
+ * Thumbnails are stored as `.kobo-images/<dir1>/<dir2>/<ImageID> - <SUFFIX>.parsed`
+ * where the two folders are derived from a hash of the ImageID.
+ */
+
+use image::imageops::FilterType;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tauri::command;
+
+use super::kobo_db::open_kobo_db;
+
+/// Default full-screen cover size (Clara HD / Libra class screens)
+const DEFAULT_FULL_SIZE: (u32, u32) = (1072, 1448);
+
+/// Library thumbnail sizes, identical across current firmware
+const LIBRARY_FULL_SIZE: (u32, u32) = (355, 530);
+const LIBRARY_GRID_SIZE: (u32, u32) = (149, 223);
+
+const SUFFIX_FULL: &str = " - N3_FULL.parsed";
+const SUFFIX_LIBRARY_FULL: &str = " - N3_LIBRARY_FULL.parsed";
+const SUFFIX_LIBRARY_GRID: &str = " - N3_LIBRARY_GRID.parsed";
+
+const COVER_SUFFIXES: [&str; 3] = [SUFFIX_FULL, SUFFIX_LIBRARY_FULL, SUFFIX_LIBRARY_GRID];
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboCoverResult {
+    pub image_id: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboImagePurgeResult {
+    pub scanned: usize,
+    pub removed: usize,
+    pub freed_bytes: u64,
+    pub removed_files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboScreenshot {
+    pub path: String,
+    pub file_name: String,
+    pub size: u64,
+}
+
+// ============================================================================
+// Image ID & Paths
+// ============================================================================
+
+/// Kobo ImageID for a ContentID (separators replaced by underscores)
+pub(crate) fn kobo_image_id(content_id: &str) -> String {
+    content_id.replace(['/', ' ', ':', '.'], "_")
+}
+
+/// Hash used by the firmware to spread images across folders (Qt's qHash)
+fn kobo_image_hash(image_id: &str) -> u32 {
+    let mut hash: u32 = 0;
+    for byte in image_id.bytes() {
+        hash = (hash << 4).wrapping_add(byte as u32);
+        hash ^= (hash & 0xf000_0000) >> 23;
+        hash &= 0x0fff_ffff;
+    }
+    hash
+}
+
+fn get_kobo_images_dir(device_path: &str) -> PathBuf {
+    PathBuf::from(device_path).join(".kobo-images")
+}
+
+fn get_kobo_image_dir(device_path: &str, image_id: &str) -> PathBuf {
+    let hash = kobo_image_hash(image_id);
+    get_kobo_images_dir(device_path)
+        .join((hash & 0xff).to_string())
+        .join(((hash & 0xff00) >> 8).to_string())
+}
+
+/// ImageID part of a cached thumbnail file name, if it is one
+fn parse_cover_file_name(file_name: &str) -> Option<&str> {
+    COVER_SUFFIXES
+        .iter()
+        .find_map(|suffix| file_name.strip_suffix(suffix))
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Compute the Kobo ImageID for a ContentID
+#[command]
+pub fn get_kobo_image_id(content_id: String) -> String {
+    kobo_image_id(&content_id)
+}
+
+/// Regenerate the three cover thumbnails of a sideloaded book from a Stomy cover
+#[command]
+pub fn regenerate_kobo_cover(
+    device_path: String,
+    content_id: String,
+    cover_path: String,
+    screen_width: Option<u32>,
+    screen_height: Option<u32>,
+) -> Result<KoboCoverResult, String> {
+    let cover = image::open(&cover_path)
+        .map_err(|e| format!("Failed to read cover image: {}", e))?;
+
+    let full_size = match (screen_width, screen_height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+        _ => DEFAULT_FULL_SIZE,
+    };
+
+    let image_id = kobo_image_id(&content_id);
+    let image_dir = get_kobo_image_dir(&device_path, &image_id);
+    fs::create_dir_all(&image_dir)
+        .map_err(|e| format!("Failed to create image folder: {}", e))?;
+
+    let mut files = Vec::new();
+    for (suffix, (width, height)) in [
+        (SUFFIX_FULL, full_size),
+        (SUFFIX_LIBRARY_FULL, LIBRARY_FULL_SIZE),
+        (SUFFIX_LIBRARY_GRID, LIBRARY_GRID_SIZE),
+    ] {
+        let target = image_dir.join(format!("{}{}", image_id, suffix));
+        let resized = cover.resize(width, height, FilterType::Lanczos3).to_rgb8();
+
+        // Thumbnails are plain JPEG data despite the .parsed extension
+        let file = fs::File::create(&target)
+            .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+        resized
+            .write_to(&mut BufWriter::new(file), ImageFormat::Jpeg)
+            .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+
+        files.push(target.to_string_lossy().to_string());
+    }
+
+    Ok(KoboCoverResult { image_id, files })
+}
+
+/// Remove cached thumbnails that no longer belong to a book in the database
+#[command]
+pub fn purge_kobo_orphan_images(device_path: String) -> Result<KoboImagePurgeResult, String> {
+    let conn = open_kobo_db(&device_path)
+        .map_err(|e| format!("Failed to open Kobo database: {}", e))?;
+
+    let mut stmt = conn
+        .prepare("SELECT ContentID, ImageId FROM content WHERE ContentType = 6")
+        .map_err(|e| format!("Failed to query books: {}", e))?;
+
+    let known: HashSet<String> = stmt
+        .query_map([], |row| {
+            let content_id: String = row.get(0)?;
+            let image_id: Option<String> = row.get(1).ok().flatten();
+            Ok(image_id
+                .filter(|id| !id.is_empty())
+                .unwrap_or_else(|| kobo_image_id(&content_id)))
+        })
+        .map_err(|e| format!("Failed to query books: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let images_dir = get_kobo_images_dir(&device_path);
+    let mut files = Vec::new();
+    if images_dir.is_dir() {
+        collect_files(&images_dir, &mut files)
+            .map_err(|e| format!("Failed to read image cache: {}", e))?;
+    }
+
+    let mut result = KoboImagePurgeResult {
+        scanned: 0,
+        removed: 0,
+        freed_bytes: 0,
+        removed_files: Vec::new(),
+    };
+
+    for path in files {
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string());
+        let Some(image_id) = file_name.as_deref().and_then(parse_cover_file_name) else {
+            continue;
+        };
+
+        result.scanned += 1;
+        if known.contains(image_id) {
+            continue;
+        }
+
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+
+        result.removed += 1;
+        result.freed_bytes += size;
+        result.removed_files.push(path.to_string_lossy().to_string());
+    }
+
+    Ok(result)
+}
+
+/// List screenshots saved at the root of the device (`screen_*.png`)
+#[command]
+pub fn list_kobo_screenshots(device_path: String) -> Result<Vec<KoboScreenshot>, String> {
+    let entries = fs::read_dir(&device_path)
+        .map_err(|e| format!("Failed to read device: {}", e))?;
+
+    let mut screenshots: Vec<KoboScreenshot> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !(file_name.starts_with("screen_") && file_name.ends_with(".png")) {
+                return None;
+            }
+            Some(KoboScreenshot {
+                path: entry.path().to_string_lossy().to_string(),
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                file_name,
+            })
+        })
+        .collect();
+
+    screenshots.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(screenshots)
+}
+
+/// Delete all screenshots from the device, returning how many were removed
+#[command]
+pub fn delete_kobo_screenshots(device_path: String) -> Result<usize, String> {
+    let screenshots = list_kobo_screenshots(device_path)?;
+
+    for screenshot in &screenshots {
+        fs::remove_file(&screenshot.path)
+            .map_err(|e| format!("Failed to remove {}: {}", screenshot.file_name, e))?;
+    }
+
+    Ok(screenshots.len())
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_path() {
+        let image_id = kobo_image_id("file:///mnt/onboard/Stomy/Dune.epub");
+        assert_eq!(image_id, "file____mnt_onboard_Stomy_Dune_epub");
+        assert_eq!(kobo_image_hash(&image_id), 31867890);
+        assert_eq!(
+            get_kobo_image_dir("/Volumes/KOBOeReader", &image_id).to_str().unwrap(),
+            "/Volumes/KOBOeReader/.kobo-images/242/67"
+        );
+    }
+
+    #[test]
+    fn test_parse_cover_file_name() {
+        assert_eq!(
+            parse_cover_file_name("file____mnt_onboard_Dune_epub - N3_LIBRARY_GRID.parsed"),
+            Some("file____mnt_onboard_Dune_epub")
+        );
+        assert_eq!(parse_cover_file_name("notes.txt"), None);
+    }
+}
@@ -0,0 +1,362 @@
+/**
+ * Kobo Inspector
+ *
+ * Headless tool to inspect a Kobo device or a copied KoboReader.sqlite
+ * without running Stomy. Uses the same queries as the Tauri commands.
+ *
+ * Location: src-tauri/src/bin/kobo-inspect.rs
+ *
+ * Usage:
+ *   kobo-inspect <mount path | KoboReader.sqlite> [books|progress|bookmarks|vocabulary|stats|all] [--json]
+ */
+
+#[path = "../plugins/kobo_db.rs"]
+#[allow(dead_code)]
+mod kobo_db;
+
+#[cfg(test)]
+#[path = "../plugins/kobo_fixtures.rs"]
+#[allow(dead_code)]
+mod kobo_fixtures;
+
+use kobo_db::{KoboBook, KoboBookmark, KoboVocabulary};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: kobo-inspect <mount path | KoboReader.sqlite> \
+[books|progress|bookmarks|vocabulary|stats|all] [--json]";
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Books,
+    Progress,
+    Bookmarks,
+    Vocabulary,
+    Stats,
+    All,
+}
+
+impl Section {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "books" => Some(Self::Books),
+            "progress" => Some(Self::Progress),
+            "bookmarks" => Some(Self::Bookmarks),
+            "vocabulary" => Some(Self::Vocabulary),
+            "stats" => Some(Self::Stats),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+
+    fn includes(self, other: Section) -> bool {
+        self == Section::All || self == other
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KoboStats {
+    books: usize,
+    unread: usize,
+    reading: usize,
+    finished: usize,
+    time_spent_reading: i64, // Minutes
+    highlights: usize,
+    annotations: usize,
+    bookmarks: usize,
+    vocabulary: usize,
+    events: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    books: Option<Vec<KoboBook>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<Vec<KoboBook>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bookmarks: Option<Vec<KoboBookmark>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vocabulary: Option<Vec<KoboVocabulary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<KoboStats>,
+}
+
+// ============================================================================
+// Report
+// ============================================================================
+
+/// Open a database from a mount path or a copied KoboReader.sqlite, read-only
+fn open_kobo_db_readonly(path: &str) -> rusqlite::Result<Connection> {
+    let db_path = if Path::new(path).is_file() {
+        PathBuf::from(path)
+    } else {
+        kobo_db::get_kobo_dir(path).join("KoboReader.sqlite")
+    };
+    Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+}
+
+fn build_report(path: &str, section: Section) -> Result<Report, String> {
+    let conn = open_kobo_db_readonly(path)
+        .map_err(|e| format!("Failed to open Kobo database: {}", e))?;
+
+    let books = kobo_db::query_books(&conn)
+        .map_err(|e| format!("Failed to query books: {}", e))?;
+    let bookmarks = kobo_db::query_bookmarks(&conn)
+        .map_err(|e| format!("Failed to query bookmarks: {}", e))?;
+    let vocabulary = kobo_db::query_vocabulary(&conn)
+        .map_err(|e| format!("Failed to query vocabulary: {}", e))?;
+
+    let mut report = Report::default();
+
+    if section.includes(Section::Stats) {
+        let events = kobo_db::query_events(&conn)
+            .map_err(|e| format!("Failed to query events: {}", e))?;
+        let count_type = |t: &str| bookmarks.iter().filter(|b| b.bookmark_type == t).count();
+
+        report.stats = Some(KoboStats {
+            books: books.len(),
+            unread: books.iter().filter(|b| b.read_status == 0).count(),
+            reading: books.iter().filter(|b| b.read_status == 1).count(),
+            finished: books.iter().filter(|b| b.read_status == 2).count(),
+            time_spent_reading: books.iter().map(|b| b.time_spent_reading as i64).sum(),
+            highlights: count_type("highlight"),
+            annotations: count_type("annotation"),
+            bookmarks: count_type("dogear") + count_type("bookmark"),
+            vocabulary: vocabulary.len(),
+            events: events.len(),
+        });
+    }
+    if section.includes(Section::Progress) {
+        let mut progress: Vec<KoboBook> = books
+            .iter()
+            .filter(|b| b.read_status > 0 || b.percent_read > 0.0)
+            .cloned()
+            .collect();
+        progress.sort_by(|a, b| b.percent_read.total_cmp(&a.percent_read));
+        report.progress = Some(progress);
+    }
+    if section.includes(Section::Books) {
+        report.books = Some(books);
+    }
+    if section.includes(Section::Bookmarks) {
+        report.bookmarks = Some(bookmarks);
+    }
+    if section.includes(Section::Vocabulary) {
+        report.vocabulary = Some(vocabulary);
+    }
+
+    Ok(report)
+}
+
+// ============================================================================
+// Table Output
+// ============================================================================
+
+/// Shorten a cell to `width` characters, keeping tables aligned
+fn truncate(value: &str, width: usize) -> String {
+    let value = value.replace('\n', " ");
+    if value.chars().count() <= width {
+        value
+    } else {
+        let mut cut: String = value.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+fn print_table(title: &str, headers: &[(&str, usize)], rows: Vec<Vec<String>>) {
+    println!("\n{} ({})", title, rows.len());
+
+    let line: Vec<String> = headers
+        .iter()
+        .map(|(name, width)| format!("{:<width$}", name, width = *width))
+        .collect();
+    println!("{}", line.join("  "));
+    println!("{}", "-".repeat(headers.iter().map(|(_, w)| w + 2).sum::<usize>()));
+
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(headers)
+            .map(|(cell, (_, width))| format!("{:<width$}", truncate(cell, *width), width = *width))
+            .collect();
+        println!("{}", line.join("  "));
+    }
+}
+
+fn read_status_label(status: i32) -> &'static str {
+    match status {
+        1 => "Reading",
+        2 => "Finished",
+        _ => "Unread",
+    }
+}
+
+fn print_report(report: &Report) {
+    if let Some(stats) = &report.stats {
+        println!("\nStats");
+        println!("  Books:        {} ({} unread, {} reading, {} finished)",
+            stats.books, stats.unread, stats.reading, stats.finished);
+        println!("  Reading time: {}h {}m", stats.time_spent_reading / 60, stats.time_spent_reading % 60);
+        println!("  Highlights:   {}", stats.highlights);
+        println!("  Annotations:  {}", stats.annotations);
+        println!("  Bookmarks:    {}", stats.bookmarks);
+        println!("  Vocabulary:   {}", stats.vocabulary);
+        println!("  Events:       {}", stats.events);
+    }
+
+    if let Some(books) = &report.books {
+        print_table(
+            "Books",
+            &[("Title", 40), ("Author", 24), ("Status", 8), ("Read", 5), ("ContentID", 50)],
+            books
+                .iter()
+                .map(|b| vec![
+                    b.title.clone(),
+                    b.attribution.clone().unwrap_or_default(),
+                    read_status_label(b.read_status).to_string(),
+                    format!("{:.0}%", b.percent_read),
+                    b.content_id.clone(),
+                ])
+                .collect(),
+        );
+    }
+
+    if let Some(progress) = &report.progress {
+        print_table(
+            "Progress",
+            &[("Title", 40), ("Read", 5), ("Time", 8), ("Last read", 24)],
+            progress
+                .iter()
+                .map(|b| vec![
+                    b.title.clone(),
+                    format!("{:.0}%", b.percent_read),
+                    format!("{}m", b.time_spent_reading),
+                    b.date_last_read.clone().unwrap_or_default(),
+                ])
+                .collect(),
+        );
+    }
+
+    if let Some(bookmarks) = &report.bookmarks {
+        print_table(
+            "Bookmarks",
+            &[("Type", 10), ("Text", 50), ("Note", 30), ("Created", 24)],
+            bookmarks
+                .iter()
+                .map(|b| vec![
+                    b.bookmark_type.clone(),
+                    b.text.clone(),
+                    b.annotation.clone().unwrap_or_default(),
+                    b.date_created.clone(),
+                ])
+                .collect(),
+        );
+    }
+
+    if let Some(vocabulary) = &report.vocabulary {
+        print_table(
+            "Vocabulary",
+            &[("Word", 24), ("Book", 60), ("Created", 24)],
+            vocabulary
+                .iter()
+                .map(|v| vec![v.text.clone(), v.volume_id.clone(), v.date_created.clone()])
+                .collect(),
+        );
+    }
+}
+
+// ============================================================================
+// Entry Point
+// ============================================================================
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|a| a == "--json");
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+
+    let Some(path) = positional.first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+    let section = match positional.get(1) {
+        None => Section::All,
+        Some(value) => match Section::parse(value) {
+            Some(section) => section,
+            None => {
+                eprintln!("Unknown section: {}\n{}", value, USAGE);
+                return ExitCode::from(2);
+            }
+        },
+    };
+
+    let report = match build_report(path, section) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(output) => println!("{}", output),
+            Err(e) => {
+                eprintln!("Failed to serialize report: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        print_report(&report);
+    }
+
+    ExitCode::SUCCESS
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::kobo_fixtures::{KoboFixture, KoboSchemaVersion};
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Dune", 10), "Dune");
+        assert_eq!(truncate("Fondation et Empire", 10), "Fondation…");
+    }
+
+    #[test]
+    fn test_build_report() {
+        let fixture = KoboFixture::builder(KoboSchemaVersion::Latest)
+            .sample_library()
+            .build()
+            .unwrap();
+
+        // Both a mount path and the sqlite file itself are accepted
+        let db_file = fixture.root().join(".kobo").join("KoboReader.sqlite");
+        for path in [fixture.device_path(), db_file.to_string_lossy().to_string()] {
+            let report = build_report(&path, Section::All).unwrap();
+            let stats = report.stats.unwrap();
+            assert_eq!(stats.books, 3);
+            assert_eq!(stats.finished, 1);
+            assert_eq!(stats.highlights, 1);
+            assert_eq!(report.progress.unwrap().len(), 2);
+        }
+
+        let report = build_report(&fixture.device_path(), Section::Vocabulary).unwrap();
+        assert!(report.books.is_none());
+        assert_eq!(report.vocabulary.unwrap().len(), 2);
+    }
+}
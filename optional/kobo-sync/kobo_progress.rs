@@ -0,0 +1,367 @@
+/**
+ * Kobo Reading Progress Merge
+ *
+ * Compares reading progress stored in Stomy with the progress recorded on a
+ * Kobo device and builds a resolution plan. Nothing is written here: the UI
+ * shows the plan, lets the user confirm or override each decision, then
+ * applies it.
+ */
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::command;
+
+use super::kobo_db::open_kobo_db;
+
+/// Differences below this many percent are treated as identical
+const DEFAULT_TOLERANCE: f64 = 0.5;
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProgressMergePolicy {
+    FurthestWins,
+    MostRecentWins,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProgressAction {
+    InSync,
+    UpdateStomy,
+    UpdateKobo,
+    Ask,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressMergeOptions {
+    pub policy: ProgressMergePolicy,
+    pub tolerance: Option<f64>, // Percent
+}
+
+/// Progress as known by Stomy for a book synced to the Kobo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StomyProgress {
+    pub book_id: String,
+    pub content_id: String,
+    pub percent_read: f64, // 0-100
+    pub date_last_read: Option<String>,
+    pub chapter_id: Option<String>,
+}
+
+/// Progress as recorded in the Kobo `content` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboProgress {
+    pub content_id: String,
+    pub title: String,
+    pub percent_read: f64, // 0-100
+    pub read_status: i32,  // 0=Unread, 1=Reading, 2=Finished
+    pub date_last_read: Option<String>,
+    pub chapter_id: Option<String>, // ChapterIDBookmarked
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressDecision {
+    pub book_id: String,
+    pub content_id: String,
+    pub title: String,
+    pub action: ProgressAction,
+    pub reason: String,
+    pub stomy_percent: f64,
+    pub kobo_percent: f64,
+    pub stomy_date: Option<String>,
+    pub kobo_date: Option<String>,
+    pub resolved_percent: f64,
+    pub resolved_date: Option<String>,
+    pub resolved_chapter_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressMergePlan {
+    pub policy: ProgressMergePolicy,
+    pub decisions: Vec<ProgressDecision>,
+    pub in_sync: usize,
+    pub update_stomy: usize,
+    pub update_kobo: usize,
+    pub ask: usize,
+    pub unmatched: Vec<String>, // Stomy book IDs not found on the device
+}
+
+// ============================================================================
+// Kobo Progress Query
+// ============================================================================
+
+fn query_kobo_progress(device_path: &str) -> Result<Vec<KoboProgress>, String> {
+    let conn = open_kobo_db(device_path)
+        .map_err(|e| format!("Failed to open Kobo database: {}", e))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT
+                ContentID,
+                Title,
+                ___PercentRead,
+                ReadStatus,
+                DateLastRead,
+                ChapterIDBookmarked
+            FROM content
+            WHERE ContentType = 6",
+        )
+        .map_err(|e| format!("Failed to query progress: {}", e))?;
+
+    let progress = stmt
+        .query_map([], |row| {
+            Ok(KoboProgress {
+                content_id: row.get(0)?,
+                title: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                percent_read: row.get::<_, Option<f64>>(2).unwrap_or(Some(0.0)).unwrap_or(0.0),
+                read_status: row.get::<_, Option<i32>>(3).unwrap_or(Some(0)).unwrap_or(0),
+                date_last_read: row.get(4).ok().flatten(),
+                chapter_id: row.get(5).ok().flatten(),
+            })
+        })
+        .map_err(|e| format!("Failed to query progress: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(progress)
+}
+
+// ============================================================================
+// Merge Engine
+// ============================================================================
+
+/// Parse the timestamps found in Stomy (RFC 3339) and KoboReader.sqlite
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|date| date.and_utc())
+}
+
+/// Finished books report a stale percentage, so count them as complete
+fn effective_kobo_percent(kobo: &KoboProgress) -> f64 {
+    if kobo.read_status == 2 {
+        100.0
+    } else {
+        kobo.percent_read.clamp(0.0, 100.0)
+    }
+}
+
+fn decide(
+    stomy: &StomyProgress,
+    kobo: &KoboProgress,
+    policy: ProgressMergePolicy,
+    tolerance: f64,
+) -> ProgressDecision {
+    let stomy_percent = stomy.percent_read.clamp(0.0, 100.0);
+    let kobo_percent = effective_kobo_percent(kobo);
+    let stomy_date = stomy.date_last_read.as_deref().and_then(parse_date);
+    let kobo_date = kobo.date_last_read.as_deref().and_then(parse_date);
+
+    let furthest = if kobo_percent > stomy_percent {
+        (ProgressAction::UpdateStomy, "Kobo is further ahead")
+    } else {
+        (ProgressAction::UpdateKobo, "Stomy is further ahead")
+    };
+
+    let (action, reason) = if (stomy_percent - kobo_percent).abs() < tolerance {
+        (ProgressAction::InSync, "Progress already matches")
+    } else {
+        match policy {
+            ProgressMergePolicy::FurthestWins => furthest,
+            ProgressMergePolicy::MostRecentWins => match (stomy_date, kobo_date) {
+                (Some(s), Some(k)) if k > s => (ProgressAction::UpdateStomy, "Kobo was read more recently"),
+                (Some(s), Some(k)) if s > k => (ProgressAction::UpdateKobo, "Stomy was read more recently"),
+                (None, Some(_)) => (ProgressAction::UpdateStomy, "Only Kobo has a last read date"),
+                (Some(_), None) => (ProgressAction::UpdateKobo, "Only Stomy has a last read date"),
+                // Same or unknown dates: fall back to the furthest position
+                _ => furthest,
+            },
+            ProgressMergePolicy::Ask => (ProgressAction::Ask, "Progress differs between Stomy and Kobo"),
+        }
+    };
+
+    let (resolved_percent, resolved_date, resolved_chapter_id) = match action {
+        ProgressAction::UpdateStomy => (kobo_percent, kobo.date_last_read.clone(), kobo.chapter_id.clone()),
+        ProgressAction::UpdateKobo => (stomy_percent, stomy.date_last_read.clone(), stomy.chapter_id.clone()),
+        // Nothing resolved yet: keep the current Stomy state
+        ProgressAction::InSync | ProgressAction::Ask => {
+            (stomy_percent, stomy.date_last_read.clone(), stomy.chapter_id.clone())
+        }
+    };
+
+    ProgressDecision {
+        book_id: stomy.book_id.clone(),
+        content_id: kobo.content_id.clone(),
+        title: kobo.title.clone(),
+        action,
+        reason: reason.to_string(),
+        stomy_percent,
+        kobo_percent,
+        stomy_date: stomy.date_last_read.clone(),
+        kobo_date: kobo.date_last_read.clone(),
+        resolved_percent,
+        resolved_date,
+        resolved_chapter_id,
+    }
+}
+
+/// Build a resolution plan for every Stomy book found on the device
+pub fn build_progress_merge_plan(
+    stomy: &[StomyProgress],
+    kobo: &[KoboProgress],
+    options: &ProgressMergeOptions,
+) -> ProgressMergePlan {
+    let tolerance = options.tolerance.unwrap_or(DEFAULT_TOLERANCE).max(0.0);
+    let kobo_by_id: HashMap<&str, &KoboProgress> =
+        kobo.iter().map(|k| (k.content_id.as_str(), k)).collect();
+
+    let mut plan = ProgressMergePlan {
+        policy: options.policy,
+        decisions: Vec::new(),
+        in_sync: 0,
+        update_stomy: 0,
+        update_kobo: 0,
+        ask: 0,
+        unmatched: Vec::new(),
+    };
+
+    for book in stomy {
+        let Some(kobo_book) = kobo_by_id.get(book.content_id.as_str()) else {
+            plan.unmatched.push(book.book_id.clone());
+            continue;
+        };
+
+        let decision = decide(book, kobo_book, options.policy, tolerance);
+        match decision.action {
+            ProgressAction::InSync => plan.in_sync += 1,
+            ProgressAction::UpdateStomy => plan.update_stomy += 1,
+            ProgressAction::UpdateKobo => plan.update_kobo += 1,
+            ProgressAction::Ask => plan.ask += 1,
+        }
+        plan.decisions.push(decision);
+    }
+
+    plan
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Compare Stomy progress with the Kobo database and return a resolution plan
+#[command]
+pub fn plan_kobo_progress_merge(
+    device_path: String,
+    stomy_progress: Vec<StomyProgress>,
+    options: ProgressMergeOptions,
+) -> Result<ProgressMergePlan, String> {
+    let kobo_progress = query_kobo_progress(&device_path)?;
+    Ok(build_progress_merge_plan(&stomy_progress, &kobo_progress, &options))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stomy(percent: f64, date: &str) -> StomyProgress {
+        StomyProgress {
+            book_id: "book-1".to_string(),
+            content_id: "file:///mnt/onboard/Dune.epub".to_string(),
+            percent_read: percent,
+            date_last_read: Some(date.to_string()),
+            chapter_id: None,
+        }
+    }
+
+    fn kobo(percent: f64, date: &str) -> KoboProgress {
+        KoboProgress {
+            content_id: "file:///mnt/onboard/Dune.epub".to_string(),
+            title: "Dune".to_string(),
+            percent_read: percent,
+            read_status: 1,
+            date_last_read: Some(date.to_string()),
+            chapter_id: Some("OEBPS/ch05.xhtml".to_string()),
+        }
+    }
+
+    fn plan(policy: ProgressMergePolicy, s: StomyProgress, k: KoboProgress) -> ProgressMergePlan {
+        let options = ProgressMergeOptions { policy, tolerance: None };
+        build_progress_merge_plan(&[s], &[k], &options)
+    }
+
+    #[test]
+    fn test_furthest_wins() {
+        let result = plan(
+            ProgressMergePolicy::FurthestWins,
+            stomy(40.0, "2025-03-02T10:00:00Z"),
+            kobo(55.0, "2025-03-01T10:00:00Z"),
+        );
+        let decision = &result.decisions[0];
+        assert_eq!(decision.action, ProgressAction::UpdateStomy);
+        assert_eq!(decision.resolved_percent, 55.0);
+        assert_eq!(decision.resolved_chapter_id.as_deref(), Some("OEBPS/ch05.xhtml"));
+    }
+
+    #[test]
+    fn test_most_recent_wins() {
+        let result = plan(
+            ProgressMergePolicy::MostRecentWins,
+            stomy(40.0, "2025-03-02T10:00:00Z"),
+            kobo(55.0, "2025-03-01T10:00:00.000"),
+        );
+        assert_eq!(result.decisions[0].action, ProgressAction::UpdateKobo);
+        assert_eq!(result.update_kobo, 1);
+    }
+
+    #[test]
+    fn test_ask_and_in_sync() {
+        let result = plan(
+            ProgressMergePolicy::Ask,
+            stomy(40.0, "2025-03-02T10:00:00Z"),
+            kobo(55.0, "2025-03-01T10:00:00Z"),
+        );
+        assert_eq!(result.decisions[0].action, ProgressAction::Ask);
+
+        let result = plan(
+            ProgressMergePolicy::Ask,
+            stomy(55.2, "2025-03-02T10:00:00Z"),
+            kobo(55.0, "2025-03-01T10:00:00Z"),
+        );
+        assert_eq!(result.decisions[0].action, ProgressAction::InSync);
+    }
+
+    #[test]
+    fn test_finished_and_unmatched() {
+        let mut finished = kobo(87.0, "2025-03-01T10:00:00Z");
+        finished.read_status = 2;
+        let result = plan(ProgressMergePolicy::FurthestWins, stomy(90.0, "2025-03-02T10:00:00Z"), finished);
+        assert_eq!(result.decisions[0].action, ProgressAction::UpdateStomy);
+        assert_eq!(result.decisions[0].resolved_percent, 100.0);
+
+        let mut other = stomy(10.0, "2025-03-02T10:00:00Z");
+        other.content_id = "file:///mnt/onboard/Other.epub".to_string();
+        let result = plan(ProgressMergePolicy::FurthestWins, other, kobo(20.0, "2025-03-01T10:00:00Z"));
+        assert!(result.decisions.is_empty());
+        assert_eq!(result.unmatched, vec!["book-1".to_string()]);
+    }
+}
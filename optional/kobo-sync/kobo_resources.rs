@@ -0,0 +1,534 @@
+/**
+ * Kobo Fonts & Dictionaries
+ *
+ * Installs custom font families into the `fonts` folder and Kobo
+ * dictionaries (`dicthtml.zip`, `dicthtml-*.zip`) into `.kobo/dict` of connected Kobo devices.
+ *
+ * Font location: /Volumes/KOBOeReader/fonts (macOS)
+ * Dictionary location: /Volumes/KOBOeReader/.kobo/dict (macOS)
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tauri::command;
+
+use super::kobo_db::get_kobo_dir;
+
+/// Styles a Kobo font family must provide, as used in file names
+/// (`Family-Regular.ttf`, `Family-Bold.ttf`, ...)
+const FONT_STYLES: [&str; 4] = ["Regular", "Bold", "Italic", "BoldItalic"];
+
+const DICTIONARY_PREFIX: &str = "dicthtml";
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboFontFile {
+    pub path: String,
+    pub file_name: String,
+    pub family: Option<String>,
+    pub style: Option<String>,
+    pub format: Option<String>, // "ttf" or "otf"
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboFontValidation {
+    pub valid: bool,
+    pub family: Option<String>,
+    pub files: Vec<KoboFontFile>,
+    pub missing_styles: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboInstalledFont {
+    pub family: String,
+    pub styles: Vec<String>,
+    pub files: Vec<String>,
+    pub size: u64,
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboDictionaryValidation {
+    pub valid: bool,
+    pub path: String,
+    pub file_name: String,
+    pub language: Option<String>,
+    pub size: u64,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoboInstalledDictionary {
+    pub file_name: String,
+    pub language: String,
+    pub size: u64,
+}
+
+// ============================================================================
+// Device Paths
+// ============================================================================
+
+fn get_kobo_fonts_dir(device_path: &str) -> PathBuf {
+    PathBuf::from(device_path).join("fonts")
+}
+
+fn get_kobo_dict_dir(device_path: &str) -> PathBuf {
+    get_kobo_dir(device_path).join("dict")
+}
+
+/// Fail early if the device does not look like a mounted Kobo
+fn ensure_kobo_device(device_path: &str) -> Result<(), String> {
+    if get_kobo_dir(device_path).is_dir() {
+        Ok(())
+    } else {
+        Err(format!("Not a Kobo device (no .kobo folder): {}", device_path))
+    }
+}
+
+/// Check that the volume holding `device_path` has room for `required` bytes.
+/// If the volume cannot be found, the copy is attempted anyway.
+fn ensure_free_space(device_path: &str, required: u64) -> Result<(), String> {
+    let target = Path::new(device_path);
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+
+    let disk = disks
+        .list()
+        .iter()
+        .filter(|d| target.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len());
+
+    match disk {
+        Some(d) if d.available_space() < required => Err(format!(
+            "Not enough space on device: {} bytes required, {} bytes available",
+            required,
+            d.available_space()
+        )),
+        _ => Ok(()),
+    }
+}
+
+// ============================================================================
+// Fonts
+// ============================================================================
+
+/// Split `Family-Style` into its family and style parts
+fn parse_font_name(file_name: &str) -> Option<(String, String)> {
+    let stem = Path::new(file_name).file_stem()?.to_str()?;
+    let (family, style) = stem.rsplit_once('-')?;
+
+    if family.is_empty() {
+        return None;
+    }
+
+    FONT_STYLES
+        .iter()
+        .find(|s| s.eq_ignore_ascii_case(style))
+        .map(|s| (family.to_string(), s.to_string()))
+}
+
+/// Detect the font format from the sfnt header, regardless of extension
+fn read_font_format(path: &Path) -> Option<String> {
+    let mut header = [0u8; 4];
+    fs::File::open(path).ok()?.read_exact(&mut header).ok()?;
+
+    match &header {
+        [0x00, 0x01, 0x00, 0x00] | b"true" => Some("ttf".to_string()),
+        b"OTTO" => Some("otf".to_string()),
+        _ => None,
+    }
+}
+
+fn inspect_font_file(path: &Path) -> KoboFontFile {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let parsed = parse_font_name(&file_name);
+
+    KoboFontFile {
+        path: path.to_string_lossy().to_string(),
+        family: parsed.as_ref().map(|(f, _)| f.clone()),
+        style: parsed.map(|(_, s)| s),
+        format: read_font_format(path),
+        size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        file_name,
+    }
+}
+
+fn validate_font_files(paths: &[PathBuf]) -> KoboFontValidation {
+    let files: Vec<KoboFontFile> = paths.iter().map(|p| inspect_font_file(p)).collect();
+    let mut errors = Vec::new();
+
+    for file in &files {
+        let extension = Path::new(&file.file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        if !matches!(extension.as_deref(), Some("ttf") | Some("otf")) {
+            errors.push(format!("{}: font files must be .ttf or .otf", file.file_name));
+        }
+        if file.format.is_none() {
+            errors.push(format!("{}: not a valid TrueType/OpenType font", file.file_name));
+        }
+        if file.style.is_none() {
+            errors.push(format!(
+                "{}: name must be Family-Regular, Family-Bold, Family-Italic or Family-BoldItalic",
+                file.file_name
+            ));
+        }
+    }
+
+    let mut families: Vec<&String> = files.iter().filter_map(|f| f.family.as_ref()).collect();
+    families.sort();
+    families.dedup();
+
+    if families.len() > 1 {
+        errors.push(format!(
+            "Files belong to several families: {}",
+            families.iter().map(|f| f.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    let mut missing_styles = Vec::new();
+    for style in FONT_STYLES {
+        let count = files.iter().filter(|f| f.style.as_deref() == Some(style)).count();
+        match count {
+            0 => missing_styles.push(style.to_string()),
+            1 => {}
+            _ => errors.push(format!("Style {} is provided more than once", style)),
+        }
+    }
+
+    if !missing_styles.is_empty() {
+        errors.push(format!("Missing styles: {}", missing_styles.join(", ")));
+    }
+
+    KoboFontValidation {
+        valid: errors.is_empty(),
+        family: families.first().map(|f| f.to_string()),
+        files,
+        missing_styles,
+        errors,
+    }
+}
+
+// ============================================================================
+// Dictionaries
+// ============================================================================
+
+/// Extract the language part of `dicthtml-<lang>.zip` (e.g. "fr", "en-de");
+/// the stock `dicthtml.zip` is the English dictionary
+fn parse_dictionary_language(file_name: &str) -> Option<String> {
+    let rest = file_name.strip_prefix(DICTIONARY_PREFIX)?.strip_suffix(".zip")?;
+    if rest.is_empty() {
+        return Some("en".to_string());
+    }
+    let language = rest.strip_prefix('-')?;
+
+    let valid = !language.is_empty()
+        && language
+            .split('-')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    if valid {
+        Some(language.to_string())
+    } else {
+        None
+    }
+}
+
+fn validate_dictionary_file(path: &Path) -> KoboDictionaryValidation {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let language = parse_dictionary_language(&file_name);
+    let mut errors = Vec::new();
+
+    if language.is_none() {
+        errors.push(format!(
+            "{}: dictionary name must be {}.zip or {}-<language>.zip",
+            file_name, DICTIONARY_PREFIX, DICTIONARY_PREFIX
+        ));
+    }
+
+    match fs::File::open(path).map_err(|e| e.to_string()).and_then(|f| {
+        zip::ZipArchive::new(f).map_err(|e| e.to_string())
+    }) {
+        Ok(archive) => {
+            // Every Kobo dictionary ships a `words` index next to its HTML shards
+            if !archive.file_names().any(|n| n == "words") {
+                errors.push(format!("{}: missing 'words' index", file_name));
+            }
+        }
+        Err(e) => errors.push(format!("{}: not a valid ZIP archive ({})", file_name, e)),
+    }
+
+    KoboDictionaryValidation {
+        valid: errors.is_empty(),
+        path: path.to_string_lossy().to_string(),
+        size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        file_name,
+        language,
+        errors,
+    }
+}
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+/// Validate a font family before installing it on a Kobo
+#[command]
+pub fn validate_kobo_font_family(font_paths: Vec<String>) -> Result<KoboFontValidation, String> {
+    if font_paths.is_empty() {
+        return Err("No font files provided".to_string());
+    }
+
+    let paths: Vec<PathBuf> = font_paths.iter().map(PathBuf::from).collect();
+    if let Some(missing) = paths.iter().find(|p| !p.is_file()) {
+        return Err(format!("Font file not found: {}", missing.display()));
+    }
+
+    Ok(validate_font_files(&paths))
+}
+
+/// Copy a validated font family into the device `fonts` folder
+#[command]
+pub fn install_kobo_font_family(
+    device_path: String,
+    font_paths: Vec<String>,
+) -> Result<Vec<String>, String> {
+    ensure_kobo_device(&device_path)?;
+
+    let validation = validate_kobo_font_family(font_paths)?;
+    if !validation.valid {
+        return Err(format!("Invalid font family: {}", validation.errors.join("; ")));
+    }
+
+    let required = validation.files.iter().map(|f| f.size).sum();
+    ensure_free_space(&device_path, required)?;
+
+    let fonts_dir = get_kobo_fonts_dir(&device_path);
+    fs::create_dir_all(&fonts_dir)
+        .map_err(|e| format!("Failed to create fonts folder: {}", e))?;
+
+    let mut installed = Vec::new();
+    for file in &validation.files {
+        let target = fonts_dir.join(&file.file_name);
+        fs::copy(&file.path, &target)
+            .map_err(|e| format!("Failed to copy {}: {}", file.file_name, e))?;
+        installed.push(target.to_string_lossy().to_string());
+    }
+
+    Ok(installed)
+}
+
+/// List font families installed in the device `fonts` folder
+#[command]
+pub fn list_kobo_fonts(device_path: String) -> Result<Vec<KoboInstalledFont>, String> {
+    let fonts_dir = get_kobo_fonts_dir(&device_path);
+    if !fonts_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries = fs::read_dir(&fonts_dir)
+        .map_err(|e| format!("Failed to read fonts folder: {}", e))?;
+
+    let mut families: BTreeMap<String, KoboInstalledFont> = BTreeMap::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let file = inspect_font_file(&entry.path());
+        if file.format.is_none() {
+            continue;
+        }
+
+        // Fonts not following the naming convention are listed under their own name
+        let family = file.family.clone().unwrap_or_else(|| {
+            Path::new(&file.file_name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        let installed = families.entry(family.clone()).or_insert_with(|| KoboInstalledFont {
+            family,
+            styles: Vec::new(),
+            files: Vec::new(),
+            size: 0,
+            complete: false,
+        });
+        if let Some(style) = file.style {
+            installed.styles.push(style);
+        }
+        installed.files.push(file.file_name);
+        installed.size += file.size;
+    }
+
+    Ok(families
+        .into_values()
+        .map(|mut font| {
+            font.styles.sort();
+            font.files.sort();
+            font.complete = FONT_STYLES
+                .iter()
+                .all(|s| font.styles.iter().any(|installed| installed == s));
+            font
+        })
+        .collect())
+}
+
+/// Remove every file of a font family from the device
+#[command]
+pub fn remove_kobo_font_family(device_path: String, family: String) -> Result<usize, String> {
+    let fonts = list_kobo_fonts(device_path.clone())?;
+    let font = fonts
+        .into_iter()
+        .find(|f| f.family == family)
+        .ok_or_else(|| format!("Font family not installed: {}", family))?;
+
+    let fonts_dir = get_kobo_fonts_dir(&device_path);
+    for file_name in &font.files {
+        fs::remove_file(fonts_dir.join(file_name))
+            .map_err(|e| format!("Failed to remove {}: {}", file_name, e))?;
+    }
+
+    Ok(font.files.len())
+}
+
+/// Validate a Kobo dictionary before installing it
+#[command]
+pub fn validate_kobo_dictionary(dictionary_path: String) -> Result<KoboDictionaryValidation, String> {
+    let path = PathBuf::from(&dictionary_path);
+    if !path.is_file() {
+        return Err(format!("Dictionary file not found: {}", dictionary_path));
+    }
+
+    Ok(validate_dictionary_file(&path))
+}
+
+/// Copy a validated dictionary into the device `.kobo/dict` folder
+#[command]
+pub fn install_kobo_dictionary(device_path: String, dictionary_path: String) -> Result<String, String> {
+    ensure_kobo_device(&device_path)?;
+
+    let validation = validate_kobo_dictionary(dictionary_path)?;
+    if !validation.valid {
+        return Err(format!("Invalid dictionary: {}", validation.errors.join("; ")));
+    }
+
+    ensure_free_space(&device_path, validation.size)?;
+
+    let dict_dir = get_kobo_dict_dir(&device_path);
+    fs::create_dir_all(&dict_dir)
+        .map_err(|e| format!("Failed to create dictionary folder: {}", e))?;
+
+    let target = dict_dir.join(&validation.file_name);
+    fs::copy(&validation.path, &target)
+        .map_err(|e| format!("Failed to copy {}: {}", validation.file_name, e))?;
+
+    Ok(target.to_string_lossy().to_string())
+}
+
+/// List dictionaries installed in the device `.kobo/dict` folder
+#[command]
+pub fn list_kobo_dictionaries(device_path: String) -> Result<Vec<KoboInstalledDictionary>, String> {
+    let dict_dir = get_kobo_dict_dir(&device_path);
+    if !dict_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries = fs::read_dir(&dict_dir)
+        .map_err(|e| format!("Failed to read dictionary folder: {}", e))?;
+
+    let mut dictionaries: Vec<KoboInstalledDictionary> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let language = parse_dictionary_language(&file_name)?;
+            Some(KoboInstalledDictionary {
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                file_name,
+                language,
+            })
+        })
+        .collect();
+
+    dictionaries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(dictionaries)
+}
+
+/// Remove an installed dictionary from the device
+#[command]
+pub fn remove_kobo_dictionary(device_path: String, file_name: String) -> Result<(), String> {
+    // Only accept plain dictionary names so nothing outside .kobo/dict can be removed
+    if parse_dictionary_language(&file_name).is_none() {
+        return Err(format!("Invalid dictionary name: {}", file_name));
+    }
+
+    let target = get_kobo_dict_dir(&device_path).join(&file_name);
+    if !target.is_file() {
+        return Err(format!("Dictionary not installed: {}", file_name));
+    }
+
+    fs::remove_file(&target).map_err(|e| format!("Failed to remove {}: {}", file_name, e))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_paths() {
+        assert_eq!(
+            get_kobo_fonts_dir("/Volumes/KOBOeReader").to_str().unwrap(),
+            "/Volumes/KOBOeReader/fonts"
+        );
+        assert_eq!(
+            get_kobo_dict_dir("/Volumes/KOBOeReader").to_str().unwrap(),
+            "/Volumes/KOBOeReader/.kobo/dict"
+        );
+    }
+
+    #[test]
+    fn test_parse_font_name() {
+        assert_eq!(
+            parse_font_name("Literata-BoldItalic.ttf"),
+            Some(("Literata".to_string(), "BoldItalic".to_string()))
+        );
+        assert_eq!(
+            parse_font_name("Open Sans-regular.otf"),
+            Some(("Open Sans".to_string(), "Regular".to_string()))
+        );
+        assert_eq!(parse_font_name("Literata.ttf"), None);
+        assert_eq!(parse_font_name("Literata-Light.ttf"), None);
+    }
+
+    #[test]
+    fn test_parse_dictionary_language() {
+        assert_eq!(parse_dictionary_language("dicthtml-fr.zip"), Some("fr".to_string()));
+        assert_eq!(parse_dictionary_language("dicthtml-en-de.zip"), Some("en-de".to_string()));
+        assert_eq!(parse_dictionary_language("dicthtml.zip"), Some("en".to_string()));
+        assert_eq!(parse_dictionary_language("dicthtml-.zip"), None);
+        assert_eq!(parse_dictionary_language("dicthtmlfr.zip"), None);
+        assert_eq!(parse_dictionary_language("dicthtml-../x.zip"), None);
+        assert_eq!(parse_dictionary_language("dictionary-fr.zip"), None);
+    }
+}
@@ -203,3 +203,148 @@ export interface KoboSyncStats {
   vocabularySynced: number;
   errors: number;
 }
+
+// ============================================================================
+// Fonts & Dictionaries
+// ============================================================================
+
+/**
+ * A font file inspected before installation
+ */
+export interface KoboFontFile {
+  path: string;
+  fileName: string;
+  family?: string;
+  style?: 'Regular' | 'Bold' | 'Italic' | 'BoldItalic';
+  format?: 'ttf' | 'otf';
+  size: number; // Bytes
+}
+
+/**
+ * Validation result for a font family (all four styles required)
+ */
+export interface KoboFontValidation {
+  valid: boolean;
+  family?: string;
+  files: KoboFontFile[];
+  missingStyles: string[];
+  errors: string[];
+}
+
+/**
+ * Font family installed in the device `fonts` folder
+ */
+export interface KoboInstalledFont {
+  family: string;
+  styles: string[];
+  files: string[];
+  size: number; // Bytes
+  complete: boolean; // All four styles present
+}
+
+/**
+ * Validation result for a `dicthtml-*.zip` dictionary
+ */
+export interface KoboDictionaryValidation {
+  valid: boolean;
+  path: string;
+  fileName: string;
+  language?: string; // e.g. "fr", "en-de"
+  size: number; // Bytes
+  errors: string[];
+}
+
+/**
+ * Dictionary installed in `.kobo/dict`
+ */
+export interface KoboInstalledDictionary {
+  fileName: string;
+  language: string;
+  size: number; // Bytes
+}
+
+// ============================================================================
+// Image Cache
+// ============================================================================
+
+/**
+ * Cover thumbnails written to `.kobo-images` for a book
+ */
+export interface KoboCoverResult {
+  imageId: string;
+  files: string[]; // N3_FULL, N3_LIBRARY_FULL, N3_LIBRARY_GRID
+}
+
+/**
+ * Result of removing thumbnails that no longer match a book
+ */
+export interface KoboImagePurgeResult {
+  scanned: number;
+  removed: number;
+  freedBytes: number;
+  removedFiles: string[];
+}
+
+/**
+ * Screenshot saved at the root of the device
+ */
+export interface KoboScreenshot {
+  path: string;
+  fileName: string;
+  size: number; // Bytes
+}
+
+// ============================================================================
+// Reading Progress Merge
+// ============================================================================
+
+/**
+ * How to resolve progress that differs between Stomy and the Kobo
+ */
+export type ProgressMergePolicy = 'furthestWins' | 'mostRecentWins' | 'ask';
+
+export type ProgressAction = 'inSync' | 'updateStomy' | 'updateKobo' | 'ask';
+
+export interface ProgressMergeOptions {
+  policy: ProgressMergePolicy;
+  tolerance?: number; // Percent difference treated as identical (default: 0.5)
+}
+
+/**
+ * Stomy progress for a book already matched to a Kobo ContentID
+ */
+export interface StomyProgress {
+  bookId: string;
+  contentId: string;
+  percentRead: number; // 0-100
+  dateLastRead?: string; // ISO date string
+  chapterId?: string;
+}
+
+/**
+ * Per-book decision, to be confirmed by the user before applying
+ */
+export interface ProgressDecision {
+  bookId: string;
+  contentId: string;
+  title: string;
+  action: ProgressAction;
+  reason: string;
+  stomyPercent: number;
+  koboPercent: number;
+  stomyDate?: string;
+  koboDate?: string;
+  resolvedPercent: number;
+  resolvedDate?: string;
+  resolvedChapterId?: string;
+}
+
+export interface ProgressMergePlan {
+  policy: ProgressMergePolicy;
+  decisions: ProgressDecision[];
+  inSync: number;
+  updateStomy: number;
+  updateKobo: number;
+  ask: number;
+  unmatched: string[]; // Stomy book IDs not found on the device
+}